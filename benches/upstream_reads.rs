@@ -0,0 +1,83 @@
+//! Compares reading a binding's upstream through the former async mutex with
+//! reading it through `SharedUpstream`, from many tasks at once as the accept
+//! loops of a busy server do.
+//!
+//! Run with `cargo bench --bench upstream_reads`.
+
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+
+use metaproxy::proxy::SharedUpstream;
+
+/// The number of tasks reading the upstream concurrently
+const TASKS: usize = 64;
+
+/// The number of reads made by each task
+const READS_PER_TASK: usize = 20_000;
+
+const UPSTREAM: &str = "http://upstream.example.com:3128";
+
+/// Time concurrent reads of an upstream held in an async mutex
+async fn mutex_reads() -> Duration {
+    let upstream = Arc::new(Mutex::new(UPSTREAM.to_string()));
+    let started = Instant::now();
+    let tasks: Vec<_> = (0..TASKS)
+        .map(|_| {
+            let upstream = upstream.clone();
+            tokio::spawn(async move {
+                for _ in 0..READS_PER_TASK {
+                    std::hint::black_box(upstream.lock().await.clone());
+                }
+            })
+        })
+        .collect();
+    for task in tasks {
+        task.await.unwrap();
+    }
+    started.elapsed()
+}
+
+/// Time concurrent reads of an upstream held in a `SharedUpstream`
+async fn shared_reads() -> Duration {
+    let upstream = Arc::new(SharedUpstream::new(UPSTREAM));
+    let started = Instant::now();
+    let tasks: Vec<_> = (0..TASKS)
+        .map(|_| {
+            let upstream = upstream.clone();
+            tokio::spawn(async move {
+                for _ in 0..READS_PER_TASK {
+                    std::hint::black_box(upstream.load().to_string());
+                }
+            })
+        })
+        .collect();
+    for task in tasks {
+        task.await.unwrap();
+    }
+    started.elapsed()
+}
+
+fn main() {
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+    let reads = (TASKS * READS_PER_TASK) as f64;
+
+    // Warm up the runtime's worker threads before measuring
+    runtime.block_on(shared_reads());
+
+    let mutex = runtime.block_on(mutex_reads());
+    let shared = runtime.block_on(shared_reads());
+    println!(
+        "Mutex<String>:  {:>8.1?} ({:.0} ns/read)",
+        mutex,
+        mutex.as_nanos() as f64 / reads
+    );
+    println!(
+        "SharedUpstream: {:>8.1?} ({:.0} ns/read)",
+        shared,
+        shared.as_nanos() as f64 / reads
+    );
+}
@@ -4,19 +4,647 @@
  * This module provides the REST API for managing proxy bindings.
  * It defines routes for creating, updating, and deleting proxy bindings,
  * as well as a health check endpoint.
+ *
+ * When request signing is configured, the `/proxy` routes only run for
+ * requests carrying a valid signature (see [`crate::signing`]). Every route
+ * can also be rate limited (see [`crate::rate_limit`]). Changes to bindings
+ * are recorded in an audit log served at `/audit` (see [`crate::audit`]).
  */
 
+use crate::audit::{unix_now, AuditEntry, AuditLog, AuditOperation};
+use crate::auth::{redact_password, UpstreamAuth};
+use crate::balancer::{Balancer, Strategy, UpstreamTarget};
+use crate::capture::MAX_CAPTURE_BYTES;
+use crate::config::BindingsFile;
 use crate::error::{CustomRejection, Error};
-use crate::proxy::{spawn_proxy_listener, BindingMap, ProxyBinding};
-use log::{debug, error, info, warn};
+use crate::events::{BindingEvent, BindingEventKind, EventBus};
+use crate::headers::{validate_rules, HeaderRule};
+use crate::health::{check_upstreams, probe, HealthCheck, ProbeResult};
+use crate::histogram::DurationSummary;
+use crate::proxy::{
+    reconcile_bindings, validate_tags, BindingMap, BindingSpec, BindingStats, ConnectionLimit,
+    ProxyBinding, ProxyContext, UpstreamMode,
+};
+use crate::rate_limit::RateLimiter;
+use crate::routing::{HeaderRoutes, Route};
+use crate::signing::{RequestSigner, SIGNATURE_HEADER, TIMESTAMP_HEADER};
+use log::{debug, info, warn};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
+use std::collections::BTreeMap;
 use std::convert::Infallible;
+use std::net::{IpAddr, SocketAddr};
+use std::ops::RangeInclusive;
 use std::sync::Arc;
-use std::time::Duration;
-use tokio::sync::oneshot;
-use tokio::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio_stream::wrappers::errors::BroadcastStreamRecvError;
+use tokio_stream::wrappers::BroadcastStream;
+use tokio_stream::StreamExt;
+use warp::http::{Method, StatusCode};
+use warp::hyper::body::{Buf, Bytes};
+use warp::path::FullPath;
+use warp::reject::Reject;
+use warp::sse::Event;
 use warp::{Filter, Rejection, Reply};
 
+/// Body of a `POST /proxy` request
+#[derive(Debug, Clone, Deserialize)]
+pub struct CreateBindingRequest {
+    /// The port to listen on, or 0 for an ephemeral port; required unless `port_range` is set
+    #[serde(default)]
+    pub port: Option<u16>,
+    /// The first and last port of a range whose first free port is bound, instead of `port`
+    #[serde(default)]
+    pub port_range: Option<(u16, u16)>,
+    /// The upstream server address; may be omitted when `upstream_chain` or `upstreams` is set
+    #[serde(default)]
+    pub upstream: String,
+    /// Proxies to chain through, ending with the upstream itself
+    #[serde(default)]
+    pub upstream_chain: Vec<String>,
+    /// A `host:port` to connect to in place of the first upstream proxy
+    #[serde(default)]
+    pub next_hop: Option<String>,
+    /// Weighted upstreams to distribute connections across
+    #[serde(default)]
+    pub upstreams: Vec<UpstreamTarget>,
+    /// How the upstream of each connection is picked from `upstreams`
+    #[serde(default)]
+    pub strategy: Strategy,
+    /// How requests are forwarded to the upstream
+    #[serde(default)]
+    pub upstream_mode: UpstreamMode,
+    /// Rules picking the upstream of each request by its target host, for `router` bindings
+    #[serde(default)]
+    pub routes: Vec<Route>,
+    /// Rules picking the upstream of plain HTTP requests by a request header
+    #[serde(default)]
+    pub header_routes: Option<HeaderRoutes>,
+    /// Rules applied to the headers of upstream HTTP responses
+    #[serde(default)]
+    pub response_headers: Vec<HeaderRule>,
+    /// Rules applied to the headers of HTTP requests sent upstream
+    #[serde(default)]
+    pub request_headers: Vec<HeaderRule>,
+    /// How the binding authenticates to its upstream proxy
+    #[serde(default)]
+    pub upstream_auth: UpstreamAuth,
+    /// Refuse to connect to an upstream proxy that has no credentials configured
+    #[serde(default)]
+    pub require_upstream_auth: bool,
+    /// Send GET and HEAD requests again when the upstream fails before responding
+    #[serde(default)]
+    pub retry_idempotent: bool,
+    /// Probe the weighted upstreams in the background and skip unhealthy ones
+    #[serde(default)]
+    pub health_check: Option<HealthCheck>,
+    /// Gzip-compress text-like HTTP responses for clients accepting it
+    #[serde(default)]
+    pub compress_responses: bool,
+    /// The local address upstream and direct connections are opened from, overriding
+    /// `--connect-bind`
+    #[serde(default)]
+    pub connect_bind: Option<IpAddr>,
+    /// Log the method, target and upstream of every request at info level
+    #[serde(default)]
+    pub log_requests: bool,
+    /// Record the first bytes of each direction of every connection
+    #[serde(default)]
+    pub debug_capture: bool,
+    /// The number of bytes captured per direction of a connection
+    #[serde(default)]
+    pub capture_bytes: Option<usize>,
+    /// Labels organizing the binding, e.g. `{"team": "data"}`
+    #[serde(default)]
+    pub tags: BTreeMap<String, String>,
+}
+
+impl From<CreateBindingRequest> for BindingSpec {
+    fn from(request: CreateBindingRequest) -> Self {
+        BindingSpec {
+            port: request.port.unwrap_or(0),
+            upstream: request.upstream,
+            upstream_chain: request.upstream_chain,
+            next_hop: request.next_hop,
+            upstreams: request.upstreams,
+            strategy: request.strategy,
+            upstream_mode: request.upstream_mode,
+            routes: request.routes,
+            header_routes: request.header_routes,
+            response_headers: request.response_headers,
+            request_headers: request.request_headers,
+            upstream_auth: request.upstream_auth,
+            require_upstream_auth: request.require_upstream_auth,
+            retry_idempotent: request.retry_idempotent,
+            health_check: request.health_check,
+            compress_responses: request.compress_responses,
+            connect_bind: request.connect_bind,
+            log_requests: request.log_requests,
+            debug_capture: request.debug_capture,
+            capture_bytes: request.capture_bytes,
+            tags: request.tags,
+        }
+    }
+}
+
+/// Body of a `PUT /proxy/{port}` request
+///
+/// The upstream, chain and weighted upstreams are replaced as a whole, or kept
+/// when none of them is given; the strategy, request header rules, tags and
+/// request logging are kept unless new ones are given.
+#[derive(Debug, Clone, Deserialize)]
+pub struct UpdateBindingRequest {
+    /// The new upstream server address
+    #[serde(default)]
+    pub upstream: String,
+    /// The new proxies to chain through, ending with the upstream itself
+    #[serde(default)]
+    pub upstream_chain: Vec<String>,
+    /// The new weighted upstreams
+    #[serde(default)]
+    pub upstreams: Vec<UpstreamTarget>,
+    /// A new load balancing strategy
+    #[serde(default)]
+    pub strategy: Option<Strategy>,
+    /// New rules applied to the headers of HTTP requests sent upstream
+    #[serde(default)]
+    pub request_headers: Option<Vec<HeaderRule>>,
+    /// New labels replacing the binding's tags
+    #[serde(default)]
+    pub tags: Option<BTreeMap<String, String>>,
+    /// Whether to start or stop logging the binding's requests
+    #[serde(default)]
+    pub log_requests: Option<bool>,
+}
+
+/// Response to a `POST /proxy` request
+#[derive(Debug, Clone, Serialize)]
+pub struct CreateBindingResponse {
+    /// Always `created`
+    pub status: &'static str,
+    /// The port that was bound
+    pub port: u16,
+    /// The upstream server address
+    pub upstream: String,
+    /// Proxies chained through, ending with the upstream itself
+    pub upstream_chain: Vec<String>,
+    /// The `host:port` connected to in place of the first upstream proxy
+    pub next_hop: Option<String>,
+    /// Weighted upstreams connections are distributed across
+    pub upstreams: Vec<UpstreamTarget>,
+    /// How the upstream of each connection is picked from `upstreams`
+    pub strategy: Strategy,
+    /// How requests are forwarded to the upstream
+    pub upstream_mode: UpstreamMode,
+    /// Rules picking the upstream of each request by its target host
+    pub routes: Vec<Route>,
+    /// Rules picking the upstream of plain HTTP requests by a request header
+    pub header_routes: Option<HeaderRoutes>,
+    /// Rules applied to the headers of upstream HTTP responses
+    pub response_headers: Vec<HeaderRule>,
+    /// Rules applied to the headers of HTTP requests sent upstream
+    pub request_headers: Vec<HeaderRule>,
+    /// Whether GET and HEAD requests are sent again when the upstream fails before responding
+    pub retry_idempotent: bool,
+    /// How the weighted upstreams are probed in the background, if they are
+    pub health_check: Option<HealthCheck>,
+    /// Whether text-like HTTP responses are gzip-compressed for clients accepting it
+    pub compress_responses: bool,
+    /// The local address upstream and direct connections are opened from, if set for the binding
+    pub connect_bind: Option<IpAddr>,
+    /// Whether the method, target and upstream of every request are logged
+    pub log_requests: bool,
+    /// Whether the binding captures the first bytes of its connections
+    pub debug_capture: bool,
+    /// The number of bytes captured per direction of a connection, if set
+    pub capture_bytes: Option<usize>,
+    /// Labels organizing the binding
+    pub tags: BTreeMap<String, String>,
+}
+
+/// Response to a `PUT /proxy/{port}` request
+#[derive(Debug, Clone, Serialize)]
+pub struct UpdateBindingResponse {
+    /// Always `updated`
+    pub status: &'static str,
+    /// The port of the updated binding
+    pub port: u16,
+    /// The new upstream server address
+    pub upstream: String,
+    /// Proxies chained through, ending with the upstream itself
+    pub upstream_chain: Vec<String>,
+    /// Weighted upstreams connections are distributed across
+    pub upstreams: Vec<UpstreamTarget>,
+    /// How the upstream of each connection is picked from `upstreams`
+    pub strategy: Strategy,
+    /// Rules applied to the headers of HTTP requests sent upstream
+    pub request_headers: Vec<HeaderRule>,
+    /// Labels organizing the binding
+    pub tags: BTreeMap<String, String>,
+    /// Whether the method, target and upstream of every request are logged
+    pub log_requests: bool,
+}
+
+/// Response to a `GET /proxy` request
+#[derive(Debug, Clone, Serialize)]
+pub struct ListBindingsResponse {
+    /// The bindings matching every tag filter, ordered by port
+    pub bindings: Vec<BindingSummary>,
+}
+
+/// A binding listed by `GET /proxy`
+///
+/// Upstream credentials and header rules are left out, so listing bindings
+/// never reveals secrets.
+#[derive(Debug, Clone, Serialize)]
+pub struct BindingSummary {
+    /// The port the binding listens on
+    pub port: u16,
+    /// The upstream server address
+    pub upstream: String,
+    /// Proxies chained through, ending with the upstream itself
+    pub upstream_chain: Vec<String>,
+    /// The `host:port` connected to in place of the first upstream proxy
+    pub next_hop: Option<String>,
+    /// Weighted upstreams connections are distributed across
+    pub upstreams: Vec<UpstreamTarget>,
+    /// How the upstream of each connection is picked from `upstreams`
+    pub strategy: Strategy,
+    /// How requests are forwarded to the upstream
+    pub upstream_mode: UpstreamMode,
+    /// Rules picking the upstream of each request by its target host
+    pub routes: Vec<Route>,
+    /// Rules picking the upstream of plain HTTP requests by a request header
+    pub header_routes: Option<HeaderRoutes>,
+    /// Whether the binding captures the first bytes of its connections
+    pub debug_capture: bool,
+    /// Labels organizing the binding
+    pub tags: BTreeMap<String, String>,
+}
+
+/// Response to a `DELETE /proxy/{port}` request
+#[derive(Debug, Clone, Serialize)]
+pub struct DeleteBindingResponse {
+    /// Always `deleted`
+    pub status: &'static str,
+    /// The port of the deleted binding
+    pub port: u16,
+}
+
+/// Response to a `POST /proxy/{port}/reset` request
+#[derive(Debug, Clone, Serialize)]
+pub struct ResetBindingResponse {
+    /// Always `reset`
+    pub status: &'static str,
+    /// The port of the reset binding
+    pub port: u16,
+    /// The number of connections that were terminated
+    pub connections: usize,
+}
+
+/// Response to a `POST /proxy/{port}/pause` or `POST /proxy/{port}/resume` request
+#[derive(Debug, Clone, Serialize)]
+pub struct PauseBindingResponse {
+    /// `paused` or `resumed`
+    pub status: &'static str,
+    /// The port of the paused or resumed binding
+    pub port: u16,
+    /// The number of connections still being proxied
+    pub active_connections: usize,
+}
+
+/// Query parameters of a `GET /proxy/export` request
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ExportQuery {
+    /// Export upstream credentials as they are instead of redacting them
+    #[serde(default)]
+    pub include_secrets: bool,
+}
+
+/// Response to a `PUT /proxy/import` request
+#[derive(Debug, Clone, Serialize)]
+pub struct ImportBindingsResponse {
+    /// Always `imported`
+    pub status: &'static str,
+    /// Ports of bindings that were created
+    pub created: Vec<u16>,
+    /// Ports of bindings that were updated in place
+    pub updated: Vec<u16>,
+    /// Ports of bindings that were restarted with their new settings
+    pub replaced: Vec<u16>,
+    /// Ports of bindings that were removed because the import did not list them
+    pub removed: Vec<u16>,
+    /// Ports of bindings that could not be created or restarted
+    pub failed: Vec<u16>,
+}
+
+/// Response to a `POST /proxy/{port}/healthcheck` request
+#[derive(Debug, Clone, Serialize)]
+pub struct HealthCheckResponse {
+    /// The port of the binding
+    pub port: u16,
+    /// The outcome of probing each upstream of the binding
+    pub upstreams: Vec<ProbeResult>,
+}
+
+/// Response to a `GET /proxy/{port}/capture` request
+#[derive(Debug, Clone, Serialize)]
+pub struct CaptureResponse {
+    /// The port of the binding
+    pub port: u16,
+    /// The maximum number of bytes captured per direction of a connection
+    pub capture_bytes: usize,
+    /// The captures of the binding's latest connections, oldest first
+    pub connections: Vec<CapturedConnectionResponse>,
+}
+
+/// The bytes captured from a connection, as reported by `GET /proxy/{port}/capture`
+///
+/// Bytes are rendered as ASCII, with other bytes and control characters
+/// escaped like `\r`, `\n` or `\x16`.
+#[derive(Debug, Clone, Serialize)]
+pub struct CapturedConnectionResponse {
+    /// The address of the client
+    pub client: String,
+    /// When the connection was accepted, in Unix seconds
+    pub started_at: u64,
+    /// The first bytes the client sent
+    pub from_client: String,
+    /// The first bytes sent back to the client
+    pub from_upstream: String,
+    /// Whether either direction carried more bytes than were captured
+    pub truncated: bool,
+}
+
+/// Response to a `GET /health` request
+#[derive(Debug, Clone, Serialize)]
+pub struct HealthResponse {
+    /// Always `ok`
+    pub status: &'static str,
+    /// The number of active bindings
+    pub active_bindings: usize,
+    /// Proxied connections across all bindings
+    pub connections: ConnectionsHealth,
+    /// How close the server is to its connection limit, for autoscaling decisions
+    pub load: LoadHealth,
+    /// The state of each binding
+    pub bindings: Vec<BindingHealth>,
+}
+
+/// Server-wide connection counts reported by `/health`
+#[derive(Debug, Clone, Serialize)]
+pub struct ConnectionsHealth {
+    /// The number of active proxied connections
+    pub active: usize,
+    /// The server-wide connection limit, `None` when unlimited
+    pub max: Option<usize>,
+    /// Connections closed for being idle beyond `--idle-timeout` since startup
+    pub idle_reaped: u64,
+}
+
+/// The largest request body the API reads, in bytes
+pub const MAX_BODY_SIZE: u64 = 1024 * 1024;
+
+/// Fraction of the connection limit above which `/health` reports the server as saturated
+pub const SATURATION_THRESHOLD: f64 = 0.9;
+
+/// The load signal reported by `/health`
+#[derive(Debug, Clone, Serialize)]
+pub struct LoadHealth {
+    /// The number of active proxied connections
+    pub current: usize,
+    /// The server-wide connection limit, `None` when unlimited
+    pub capacity: Option<usize>,
+    /// `current` divided by `capacity`, `None` when unlimited
+    pub utilization: Option<f64>,
+    /// Whether utilization is at or above [`SATURATION_THRESHOLD`]
+    pub saturated: bool,
+}
+
+impl LoadHealth {
+    /// Compute the load from the active connections and the connection limit
+    ///
+    /// # Arguments
+    ///
+    /// * `current` - The number of active proxied connections
+    /// * `capacity` - The server-wide connection limit, if any
+    ///
+    /// # Returns
+    ///
+    /// The load; an unlimited server is never saturated
+    pub fn new(current: usize, capacity: Option<usize>) -> Self {
+        let utilization = capacity.map(|capacity| current as f64 / capacity.max(1) as f64);
+        LoadHealth {
+            current,
+            capacity,
+            utilization,
+            saturated: utilization.is_some_and(|utilization| utilization >= SATURATION_THRESHOLD),
+        }
+    }
+}
+
+/// The state of a binding reported by `/health`
+#[derive(Debug, Clone, Serialize)]
+pub struct BindingHealth {
+    /// The port the binding listens on
+    pub port: u16,
+    /// The upstream server address
+    pub upstream: String,
+    /// Proxies chained through, ending with the upstream itself
+    pub upstream_chain: Vec<String>,
+    /// Weighted upstreams and their connection counts
+    pub upstreams: Vec<UpstreamHealth>,
+    /// How the upstream of each connection is picked, `None` if the balancer was busy
+    pub strategy: Option<Strategy>,
+    /// How requests are forwarded to the upstream
+    pub upstream_mode: UpstreamMode,
+    /// Number of connections the binding is currently proxying
+    pub active_connections: usize,
+    /// Whether the binding is closing new connections
+    pub paused: bool,
+    /// Counts of error statuses returned by the upstream to CONNECT
+    pub upstream_errors: BTreeMap<u16, u64>,
+    /// How long finished connections lived
+    pub connection_durations: ConnectionDurations,
+    /// Labels organizing the binding
+    pub tags: BTreeMap<String, String>,
+    /// Unix time, in seconds, the binding was created at
+    pub created_at: u64,
+    /// Unix time, in seconds, the binding last accepted a connection at, `None` if it never did
+    pub last_active_at: Option<u64>,
+}
+
+/// How long a binding's finished connections lived, by kind
+#[derive(Debug, Clone, Serialize)]
+pub struct ConnectionDurations {
+    /// Durations of CONNECT tunnels, `None` before the first one closed
+    pub tunnel: Option<DurationSummary>,
+    /// Durations of plain HTTP connections, `None` before the first one closed
+    pub http: Option<DurationSummary>,
+}
+
+impl From<&BindingStats> for ConnectionDurations {
+    fn from(stats: &BindingStats) -> Self {
+        ConnectionDurations {
+            tunnel: stats.tunnel_durations().summary(),
+            http: stats.http_durations().summary(),
+        }
+    }
+}
+
+/// Response to a `GET /proxy/{port}/stats` request
+#[derive(Debug, Clone, Serialize)]
+pub struct BindingStatsResponse {
+    /// The port the binding listens on
+    pub port: u16,
+    /// The upstream server address
+    pub upstream: String,
+    /// Number of connections the binding is currently proxying
+    pub active_connections: usize,
+    /// Number of connections accepted since the listener started
+    pub total_connections: u64,
+    /// Bytes relayed from clients to the upstream by finished connections
+    pub bytes_from_client: u64,
+    /// Bytes relayed from the upstream to clients by finished connections
+    pub bytes_from_upstream: u64,
+    /// Number of upstream connections that failed or timed out
+    pub connect_errors: u64,
+    /// Average time taken to connect to the upstream, `None` before the first connection
+    pub average_connect_latency_ms: Option<f64>,
+    /// Counts of error statuses returned by the upstream to CONNECT
+    pub upstream_errors: BTreeMap<u16, u64>,
+    /// How long finished connections lived
+    pub connection_durations: ConnectionDurations,
+    /// Weighted upstreams and their connection counts
+    pub upstreams: Vec<UpstreamHealth>,
+    /// Unix time, in seconds, the binding was created at
+    pub created_at: u64,
+    /// Unix time, in seconds, the binding last accepted a connection at, `None` if it never did
+    pub last_active_at: Option<u64>,
+}
+
+/// The state of a weighted upstream reported by `/health`
+#[derive(Debug, Clone, Serialize)]
+pub struct UpstreamHealth {
+    /// The upstream server address
+    pub url: String,
+    /// The relative share of connections sent to this upstream
+    pub weight: u32,
+    /// The number of connections sent to this upstream
+    pub selections: u64,
+    /// The number of active connections to this upstream
+    pub active: usize,
+    /// Whether the upstream passed its latest health check, always true without health checks
+    pub healthy: bool,
+}
+
+/// Body of an error response
+#[derive(Debug, Clone, Serialize)]
+pub struct ErrorResponse {
+    /// What went wrong
+    pub error: ErrorDetail,
+}
+
+/// The error of an error response
+#[derive(Debug, Clone, Serialize)]
+pub struct ErrorDetail {
+    /// A stable, machine-readable name of the error, e.g. `binding_not_found`
+    pub code: &'static str,
+    /// A description of what went wrong
+    pub message: String,
+}
+
+impl Reply for CreateBindingResponse {
+    fn into_response(self) -> warp::reply::Response {
+        warp::reply::json(&self).into_response()
+    }
+}
+
+/// Response to a `GET /audit` request
+#[derive(Debug, Clone, Serialize)]
+pub struct AuditResponse {
+    /// The maximum number of entries kept
+    pub capacity: usize,
+    /// The latest operations, oldest first
+    pub entries: Vec<AuditEntry>,
+}
+
+/// Settings of the API routes themselves
+///
+/// Settings of the proxy bindings the routes create live in [`ProxyContext`].
+/// New settings are added as fields rather than as arguments of
+/// [`create_routes`], so callers that start from [`ApiConfig::default`] keep
+/// compiling as the server grows:
+///
+/// ```
+/// use metaproxy::api::ApiConfig;
+///
+/// let config = ApiConfig {
+///     max_bindings: Some(16),
+///     ..ApiConfig::default()
+/// };
+/// ```
+#[derive(Clone, Default)]
+pub struct ApiConfig {
+    /// Maximum number of bindings the API may create, if limited
+    pub max_bindings: Option<usize>,
+    /// Verifies the signatures of `/proxy` requests, if signing is enabled
+    pub signer: Option<Arc<RequestSigner>>,
+    /// Limits the rate of requests to every route, if set
+    pub rate_limiter: Option<RateLimiter>,
+    /// Records the changes made to bindings through the routes
+    pub audit_log: AuditLog,
+}
+
+/// Rejection for a request body that does not deserialize into the route's request type
+#[derive(Debug)]
+struct InvalidBody(serde_json::Error);
+
+impl Reject for InvalidBody {}
+
+/// Rejection for a binding creation once the maximum number of bindings exist
+#[derive(Debug)]
+struct BindingLimitReached(usize);
+
+impl Reject for BindingLimitReached {}
+
+/// Rejection for a request addressing a port with no binding
+#[derive(Debug)]
+struct BindingNotFound(u16);
+
+impl Reject for BindingNotFound {}
+
+/// Rejection for a capture request to a binding without `debug_capture`
+#[derive(Debug)]
+struct CaptureDisabled(u16);
+
+impl Reject for CaptureDisabled {}
+
+/// Rejection for an export of secrets while API requests are not signed
+#[derive(Debug)]
+struct SecretsNotAllowed;
+
+impl Reject for SecretsNotAllowed {}
+
+/// Rejection for a request body larger than [`MAX_BODY_SIZE`]
+#[derive(Debug)]
+struct BodyTooLarge;
+
+impl Reject for BodyTooLarge {}
+
+/// Rejection for a request whose signature is missing, stale or wrong
+#[derive(Debug)]
+struct InvalidSignature(Error);
+
+impl Reject for InvalidSignature {}
+
+/// Rejection for a request over the API rate limit, with how long until the client may retry
+#[derive(Debug)]
+struct RateLimited(Duration);
+
+impl Reject for RateLimited {}
+
 /// Create API routes for the proxy server
 ///
 /// This function sets up all the API routes for the proxy server,
@@ -24,177 +652,1672 @@ use warp::{Filter, Rejection, Reply};
 ///
 /// # Arguments
 ///
-/// * `bindings` - Shared state containing active proxy bindings
-/// * `timeout` - Optional request timeout for upstream connections
+/// * `context` - Server-wide settings and the bindings the routes manage
+/// * `config` - Settings of the routes themselves
 ///
 /// # Returns
 ///
 /// A warp filter that handles all API routes
 pub fn create_routes(
-    bindings: BindingMap,
-    timeout: Option<Duration>,
+    context: Arc<ProxyContext>,
+    config: ApiConfig,
 ) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
-    let proxy_routes = create_proxy_routes(bindings.clone(), timeout);
-    let health_route = create_health_route(bindings.clone());
+    let rate_limiter = config.rate_limiter.clone();
+    let health_route = create_health_route(context.clone());
+    let events_route = create_events_route(context.events.clone(), config.signer.clone());
+    let audit_route = create_audit_route(config.audit_log.clone(), config.signer.clone());
+    let proxy_routes = create_proxy_routes(context, config);
+    let openapi_route = create_openapi_route();
+
+    let routes = rate_limit(rate_limiter)
+        .and(
+            proxy_routes
+                .or(health_route)
+                .or(events_route)
+                .or(audit_route)
+                .or(openapi_route),
+        )
+        .recover(handle_rejection);
+
+    // Exactly one branch passes its coding check, so a request rejected by
+    // the routes is never handled a second time by another branch. The event
+    // stream always takes the uncompressed branch.
+    let gzip_routes = negotiated_coding(Some("gzip"))
+        .and(routes.clone())
+        .with(warp::filters::compression::gzip());
+    let deflate_routes = negotiated_coding(Some("deflate"))
+        .and(routes.clone())
+        .with(warp::filters::compression::deflate());
+    let plain_routes = negotiated_coding(None).and(routes);
+
+    gzip_routes.or(deflate_routes).or(plain_routes)
+}
+
+/// Only pass requests whose responses are compressed with a given coding
+///
+/// # Arguments
+///
+/// * `coding` - The content coding, or None for uncompressed responses
+///
+/// # Returns
+///
+/// A warp filter that rejects requests negotiating a different coding
+fn negotiated_coding(
+    coding: Option<&'static str>,
+) -> impl Filter<Extract = (), Error = Rejection> + Clone {
+    warp::path::full()
+        .and(warp::header::optional::<String>("accept-encoding"))
+        .and_then(
+            move |path: FullPath, accept_encoding: Option<String>| async move {
+                // Compressing the event stream would hold events back in the encoder
+                let negotiated = if path.as_str() == "/events" {
+                    None
+                } else {
+                    response_coding(accept_encoding.as_deref())
+                };
+                if negotiated == coding {
+                    Ok(())
+                } else {
+                    Err(warp::reject::not_found())
+                }
+            },
+        )
+        .untuple_one()
+}
+
+/// Pick the content coding of a response from the request's `Accept-Encoding`
+///
+/// `gzip` is preferred over `deflate`; codings refused with `q=0` are skipped.
+///
+/// # Arguments
+///
+/// * `accept_encoding` - The value of the `Accept-Encoding` header, if present
+///
+/// # Returns
+///
+/// `gzip` or `deflate`, or None if the response is sent uncompressed
+fn response_coding(accept_encoding: Option<&str>) -> Option<&'static str> {
+    let accepted: Vec<&str> = accept_encoding?
+        .split(',')
+        .filter_map(|entry| {
+            let mut params = entry.split(';').map(str::trim);
+            let coding = params.next()?;
+            let refused = params.any(|param| {
+                param
+                    .strip_prefix("q=")
+                    .and_then(|q| q.parse::<f32>().ok())
+                    .is_some_and(|q| q == 0.0)
+            });
+            (!refused).then_some(coding)
+        })
+        .collect();
+
+    ["gzip", "deflate"].into_iter().find(|coding| {
+        accepted
+            .iter()
+            .any(|accepted| accepted.eq_ignore_ascii_case(coding))
+    })
+}
+
+/// Reject requests over the API rate limit
+///
+/// # Arguments
+///
+/// * `rate_limiter` - The rate limiter, or None to allow every request
+///
+/// # Returns
+///
+/// A warp filter that rejects requests over the limit with [`RateLimited`]
+fn rate_limit(
+    rate_limiter: Option<RateLimiter>,
+) -> impl Filter<Extract = (), Error = Rejection> + Clone {
+    warp::addr::remote()
+        .and_then(move |remote: Option<SocketAddr>| {
+            let rate_limiter = rate_limiter.clone();
+            async move {
+                if let Some(limiter) = rate_limiter {
+                    if let Err(wait) = limiter.try_acquire(remote.map(|addr| addr.ip())).await {
+                        return Err(warp::reject::custom(RateLimited(wait)));
+                    }
+                }
+                Ok(())
+            }
+        })
+        .untuple_one()
+}
+
+/// Turn every rejection the routes produce into a JSON error response
+///
+/// Each response carries `{"error": {"code": ..., "message": ...}}` with the
+/// status matching the rejection:
+///
+/// | Rejection | Status | Code |
+/// |-----------|--------|------|
+/// | Invalid JSON body or port | `400 Bad Request` | `invalid_body` |
+/// | Invalid binding settings | `400 Bad Request` | `invalid_request` |
+/// | Missing or wrong signature | `401 Unauthorized` | `invalid_signature` |
+/// | Export of secrets without request signing | `403 Forbidden` | `secrets_not_allowed` |
+/// | Unknown binding | `404 Not Found` | `binding_not_found` |
+/// | Unknown route | `404 Not Found` | `not_found` |
+/// | Unsupported method | `405 Method Not Allowed` | `method_not_allowed` |
+/// | Capture of a binding without `debug_capture` | `409 Conflict` | `capture_disabled` |
+/// | Body over [`MAX_BODY_SIZE`] | `413 Payload Too Large` | `payload_too_large` |
+/// | Wrong content type | `415 Unsupported Media Type` | `unsupported_media_type` |
+/// | Request over the rate limit | `429 Too Many Requests` | `rate_limited` |
+/// | Creation over the binding limit | `507 Insufficient Storage` | `binding_limit_reached` |
+///
+/// Any other rejection is answered with `500 Internal Server Error` and the
+/// code `internal_error`. A `429` also carries a `Retry-After` header with the
+/// whole seconds until the client's rate limit allows another request.
+///
+/// # Arguments
+///
+/// * `rejection` - The rejection produced by the routes
+///
+/// # Returns
+///
+/// An error response
+async fn handle_rejection(rejection: Rejection) -> std::result::Result<impl Reply, Rejection> {
+    let (status, code, message) = describe_rejection(&rejection);
+    match code {
+        "rate_limited" => warn!("Rejected request over the API rate limit"),
+        "invalid_signature" => warn!("Rejected request with invalid signature: {}", message),
+        "invalid_body" => warn!("Rejected request with malformed JSON body: {}", message),
+        "internal_error" => warn!("Unhandled rejection: {:?}", rejection),
+        _ => {}
+    }
+
+    let mut response = warp::reply::with_status(
+        warp::reply::json(&ErrorResponse {
+            error: ErrorDetail { code, message },
+        }),
+        status,
+    )
+    .into_response();
+    if let Some(RateLimited(wait)) = rejection.find() {
+        let seconds = wait.as_secs_f64().ceil().max(1.0) as u64;
+        response
+            .headers_mut()
+            .insert(warp::http::header::RETRY_AFTER, seconds.into());
+    }
+    Ok(response)
+}
+
+/// Get the status, error code and message a rejection is answered with
+///
+/// # Arguments
+///
+/// * `rejection` - The rejection produced by the routes
+///
+/// # Returns
+///
+/// The response status, the error code and a description of the error, as
+/// listed for [`handle_rejection`]
+fn describe_rejection(rejection: &Rejection) -> (StatusCode, &'static str, String) {
+    if rejection.find::<RateLimited>().is_some() {
+        (
+            StatusCode::TOO_MANY_REQUESTS,
+            "rate_limited",
+            "rate limit exceeded".to_string(),
+        )
+    } else if let Some(InvalidSignature(e)) = rejection.find() {
+        (StatusCode::UNAUTHORIZED, "invalid_signature", e.to_string())
+    } else if rejection.find::<SecretsNotAllowed>().is_some() {
+        (
+            StatusCode::FORBIDDEN,
+            "secrets_not_allowed",
+            "include_secrets requires signed API requests (--api-hmac-secret)".to_string(),
+        )
+    } else if let Some(BindingLimitReached(max)) = rejection.find() {
+        (
+            StatusCode::INSUFFICIENT_STORAGE,
+            "binding_limit_reached",
+            format!("binding limit reached: at most {} bindings may exist", max),
+        )
+    } else if let Some(BindingNotFound(port)) = rejection.find() {
+        (
+            StatusCode::NOT_FOUND,
+            "binding_not_found",
+            format!("No binding found for port {}", port),
+        )
+    } else if let Some(CaptureDisabled(port)) = rejection.find() {
+        (
+            StatusCode::CONFLICT,
+            "capture_disabled",
+            format!("Debug capture is not enabled for port {}", port),
+        )
+    } else if let Some(InvalidBody(e)) = rejection.find() {
+        (
+            StatusCode::BAD_REQUEST,
+            "invalid_body",
+            format!("invalid JSON body: {}", e),
+        )
+    } else if rejection.find::<BodyTooLarge>().is_some() {
+        (
+            StatusCode::PAYLOAD_TOO_LARGE,
+            "payload_too_large",
+            format!("request body is larger than {} bytes", MAX_BODY_SIZE),
+        )
+    } else if let Some(CustomRejection(e)) = rejection.find() {
+        (StatusCode::BAD_REQUEST, "invalid_request", e.to_string())
+    } else if rejection.find::<warp::reject::MethodNotAllowed>().is_some() {
+        (
+            StatusCode::METHOD_NOT_ALLOWED,
+            "method_not_allowed",
+            "method not allowed".to_string(),
+        )
+    } else if let Some(e) = rejection.find::<warp::reject::PayloadTooLarge>() {
+        (
+            StatusCode::PAYLOAD_TOO_LARGE,
+            "payload_too_large",
+            e.to_string(),
+        )
+    } else if let Some(e) = rejection.find::<warp::reject::UnsupportedMediaType>() {
+        (
+            StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            "unsupported_media_type",
+            e.to_string(),
+        )
+    } else if rejection.is_not_found() {
+        (StatusCode::NOT_FOUND, "not_found", "not found".to_string())
+    } else {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "internal_error",
+            "internal server error".to_string(),
+        )
+    }
+}
+
+/// The audit log and the caller of the API request being handled
+#[derive(Clone)]
+struct Audit {
+    /// The log the request's operation is recorded in
+    log: AuditLog,
+    /// The address the request came from, None over a Unix domain socket
+    client: Option<SocketAddr>,
+}
+
+impl Audit {
+    /// Record the outcome of an operation and pass it on
+    ///
+    /// The password of the upstream is redacted, as the log is served by the
+    /// API.
+    ///
+    /// # Arguments
+    ///
+    /// * `operation` - What was requested
+    /// * `port` - The port of the binding, if known
+    /// * `upstream` - The upstream given with the request or set by it, if any
+    /// * `result` - The handler's reply or rejection
+    ///
+    /// # Returns
+    ///
+    /// The handler's reply as a response, or its rejection
+    fn record<R: Reply>(
+        &self,
+        operation: AuditOperation,
+        port: Option<u16>,
+        upstream: Option<String>,
+        result: std::result::Result<R, Rejection>,
+    ) -> std::result::Result<warp::reply::Response, Rejection> {
+        let (status, error, result) = match result {
+            Ok(reply) => {
+                let response = reply.into_response();
+                (response.status(), None, Ok(response))
+            }
+            Err(rejection) => {
+                let (status, _, message) = describe_rejection(&rejection);
+                (status, Some(message), Err(rejection))
+            }
+        };
+        let upstream = upstream
+            .filter(|upstream| !upstream.is_empty())
+            .map(|mut upstream| {
+                redact_password(&mut upstream);
+                upstream
+            });
+        self.log.record(AuditEntry {
+            timestamp: unix_now(),
+            operation,
+            port,
+            upstream,
+            client: self.client,
+            status: status.as_u16(),
+            error,
+        });
+        result
+    }
+}
+
+/// The caller of an API request, as shown in log messages
+///
+/// Shows the caller's IP address, or that the request came over a Unix
+/// domain socket.
+#[derive(Debug, Clone, Copy)]
+struct Caller(Option<SocketAddr>);
+
+impl std::fmt::Display for Caller {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.0 {
+            Some(addr) => write!(f, "{}", addr.ip()),
+            None => f.write_str("Unix socket"),
+        }
+    }
+}
+
+/// Extract the audit log along with the caller of the request
+///
+/// # Arguments
+///
+/// * `log` - The log operations are recorded in
+///
+/// # Returns
+///
+/// A warp filter extracting an [`Audit`]
+fn audited(log: AuditLog) -> impl Filter<Extract = (Audit,), Error = Infallible> + Clone {
+    warp::addr::remote().map(move |client| Audit {
+        log: log.clone(),
+        client,
+    })
+}
+
+/// Read the raw request body, refusing one larger than [`MAX_BODY_SIZE`]
+///
+/// A declared `Content-Length` over the limit is refused before any of the
+/// body is read, and a body without one stops being read once it exceeds the
+/// limit, so nothing larger is buffered before the signature is checked.
+///
+/// # Returns
+///
+/// A warp filter extracting the body, or rejecting the request with
+/// [`BodyTooLarge`]
+fn limited_body() -> impl Filter<Extract = (Bytes,), Error = Rejection> + Clone {
+    warp::header::optional::<u64>("content-length")
+        .and(warp::body::stream())
+        .and_then(read_limited_body)
+}
+
+/// Collect a request body no larger than [`MAX_BODY_SIZE`]
+///
+/// # Arguments
+///
+/// * `length` - The request's `Content-Length`, if it has one
+/// * `stream` - The chunks of the request body
+///
+/// # Returns
+///
+/// A result containing the body, or a rejection if it is too large or could
+/// not be read
+async fn read_limited_body<B: Buf>(
+    length: Option<u64>,
+    stream: impl tokio_stream::Stream<Item = std::result::Result<B, warp::Error>>,
+) -> std::result::Result<Bytes, Rejection> {
+    if length.is_some_and(|length| length > MAX_BODY_SIZE) {
+        return Err(warp::reject::custom(BodyTooLarge));
+    }
+    let mut stream = Box::pin(stream);
+    let mut body = Vec::new();
+    while let Some(chunk) = stream.next().await {
+        let mut chunk = chunk.map_err(|e| {
+            warp::reject::custom(CustomRejection(Error::Custom(format!(
+                "Failed to read the request body: {}",
+                e
+            ))))
+        })?;
+        if (body.len() + chunk.remaining()) as u64 > MAX_BODY_SIZE {
+            return Err(warp::reject::custom(BodyTooLarge));
+        }
+        while chunk.has_remaining() {
+            let bytes = chunk.chunk();
+            body.extend_from_slice(bytes);
+            let n = bytes.len();
+            chunk.advance(n);
+        }
+    }
+    Ok(Bytes::from(body))
+}
 
-    proxy_routes.or(health_route)
+/// Read the raw request body, verifying the request's signature if signing is enabled
+///
+/// The signature covers the path together with the raw query string, if any.
+///
+/// # Arguments
+///
+/// * `signer` - Verifies the request signature, if signing is enabled
+///
+/// # Returns
+///
+/// A warp filter extracting the body, or rejecting the request with
+/// [`InvalidSignature`]
+fn signed_body(
+    signer: Option<Arc<RequestSigner>>,
+) -> impl Filter<Extract = (Bytes,), Error = Rejection> + Clone {
+    warp::method()
+        .and(warp::path::full())
+        .and(warp::query::raw().or(warp::any().map(String::new)).unify())
+        .and(warp::header::optional::<String>(TIMESTAMP_HEADER))
+        .and(warp::header::optional::<String>(SIGNATURE_HEADER))
+        .and(limited_body())
+        .and_then(
+            move |method: Method,
+                  path: FullPath,
+                  query: String,
+                  timestamp: Option<String>,
+                  signature: Option<String>,
+                  body: Bytes| {
+                let signer = signer.clone();
+                async move {
+                    if let Some(signer) = signer {
+                        // The query is signed along with the path, so that
+                        // parameters cannot be added to a captured request
+                        let target = if query.is_empty() {
+                            path.as_str().to_string()
+                        } else {
+                            format!("{}?{}", path.as_str(), query)
+                        };
+                        signer
+                            .verify(
+                                method.as_str(),
+                                &target,
+                                timestamp.as_deref(),
+                                signature.as_deref(),
+                                &body,
+                            )
+                            .map_err(|e| warp::reject::custom(InvalidSignature(e)))?;
+                    }
+                    Ok::<_, Rejection>(body)
+                }
+            },
+        )
+}
+
+/// Verify the request's signature if signing is enabled, discarding the body
+///
+/// # Arguments
+///
+/// * `signer` - Verifies the request signature, if signing is enabled
+///
+/// # Returns
+///
+/// A warp filter that rejects requests failing the signature check
+fn signed(
+    signer: Option<Arc<RequestSigner>>,
+) -> impl Filter<Extract = (), Error = Rejection> + Clone {
+    signed_body(signer).map(|_| ()).untuple_one()
+}
+
+/// Read a signed JSON request body
+///
+/// The raw body is needed to verify the signature, so it is deserialized here
+/// rather than with `warp::body::json`.
+///
+/// # Arguments
+///
+/// * `signer` - Verifies the request signature, if signing is enabled
+///
+/// # Returns
+///
+/// A warp filter extracting the deserialized body, or rejecting the request
+/// with [`InvalidSignature`] or [`InvalidBody`]
+fn signed_json<T: DeserializeOwned + Send>(
+    signer: Option<Arc<RequestSigner>>,
+) -> impl Filter<Extract = (T,), Error = Rejection> + Clone {
+    signed_body(signer).and_then(|body: Bytes| async move {
+        serde_json::from_slice(&body).map_err(|e| warp::reject::custom(InvalidBody(e)))
+    })
 }
 
 /// Create routes for managing proxy bindings
 ///
 /// This function sets up routes for creating, updating, and deleting proxy bindings.
-/// It handles POST, PUT, and DELETE requests to the `/proxy` endpoint.
+/// It handles GET, POST, PUT, and DELETE requests to the `/proxy` endpoint,
+/// GET requests to `/proxy/export` and PUT requests to `/proxy/import` for
+/// copying every binding between instances,
+/// POST requests to `/proxy/{port}/reset` for resetting a binding's connections,
+/// POST requests to `/proxy/{port}/pause` and `/proxy/{port}/resume` for
+/// stopping and restarting the proxying of new connections,
+/// GET requests to `/proxy/{port}/stats` for a binding's traffic counters,
+/// POST requests to `/proxy/{port}/healthcheck` for probing its upstreams,
+/// and GET requests to `/proxy/{port}/capture` for its debug capture.
 ///
 /// # Arguments
 ///
-/// * `bindings` - Shared state containing active proxy bindings
-/// * `timeout` - Optional request timeout for upstream connections
+/// * `context` - Server-wide settings and the bindings the routes manage
+/// * `config` - Settings of the routes themselves
 ///
 /// # Returns
 ///
 /// A warp filter that handles proxy binding management routes
 fn create_proxy_routes(
-    bindings: BindingMap,
-    timeout: Option<Duration>,
+    context: Arc<ProxyContext>,
+    config: ApiConfig,
 ) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    let signer = config.signer.clone();
+    let audit_log = config.audit_log.clone();
+    let health_check_context = context.clone();
+    let import_context = context.clone();
+    let max_bindings = config.max_bindings;
+    let signing = config.signer.is_some();
+    let bindings = context.bindings.clone();
     let bindings_filter = warp::any().map(move || bindings.clone());
+    let events = context.events.clone();
+    let events_filter = warp::any().map(move || events.clone());
+
+    // Create the proxy binding listing route
+    let list_bindings_route = warp::path("proxy")
+        .and(warp::path::end())
+        .and(warp::get())
+        .and(signed(signer.clone()))
+        .and(bindings_filter.clone())
+        .and(warp::query::<Vec<(String, String)>>())
+        .and_then(handle_list_bindings);
+
+    // Create the proxy binding export and import routes
+    let export_bindings_route = warp::path!("proxy" / "export")
+        .and(warp::get())
+        .and(signed(signer.clone()))
+        .and(bindings_filter.clone())
+        .and(warp::query::<ExportQuery>())
+        .and(warp::any().map(move || signing))
+        .and_then(handle_export_bindings);
+    let import_bindings_route = warp::path!("proxy" / "import")
+        .and(warp::put())
+        .and(warp::any().map(move || import_context.clone()))
+        .and(signed_json::<BindingsFile>(signer.clone()))
+        .and(warp::any().map(move || max_bindings))
+        .and(audited(audit_log.clone()))
+        .and_then(
+            |context, file: BindingsFile, max_bindings, audit: Audit| async move {
+                let result =
+                    handle_import_bindings(context, file, max_bindings, Caller(audit.client)).await;
+                audit.record(AuditOperation::Import, None, None, result)
+            },
+        );
 
     // Create the proxy binding creation route
-    let timeout_clone = timeout;
     let create_binding_route = warp::path("proxy")
         .and(warp::post())
-        .and(bindings_filter.clone())
-        .and(warp::body::json())
-        .and(warp::any().map(move || timeout_clone))
-        .and_then(handle_create_binding);
+        .and(warp::any().map(move || context.clone()))
+        .and(signed_json::<CreateBindingRequest>(signer.clone()))
+        .and(warp::any().map(move || config.max_bindings))
+        .and(audited(audit_log.clone()))
+        .and_then(
+            |context, request: CreateBindingRequest, max_bindings, audit: Audit| async move {
+                let requested = (request.port, request.upstream.clone());
+                let result =
+                    handle_create_binding(context, request, max_bindings, Caller(audit.client))
+                        .await;
+                let (port, upstream) = match &result {
+                    Ok(created) => (Some(created.port), created.upstream.clone()),
+                    Err(_) => requested,
+                };
+                audit.record(AuditOperation::Create, port, Some(upstream), result)
+            },
+        );
 
     // Create the proxy binding update route
-    let timeout_clone = timeout;
     let update_binding_route = warp::path!("proxy" / u16)
         .and(warp::put())
         .and(bindings_filter.clone())
-        .and(warp::body::json())
-        .and(warp::any().map(move || timeout_clone))
-        .and_then(handle_update_binding);
+        .and(events_filter.clone())
+        .and(signed_json::<UpdateBindingRequest>(signer.clone()))
+        .and(audited(audit_log.clone()))
+        .and_then(
+            |port, bindings, events, request: UpdateBindingRequest, audit: Audit| async move {
+                let upstream = Some(request.upstream.clone());
+                let result =
+                    handle_update_binding(port, bindings, events, request, Caller(audit.client))
+                        .await;
+                audit.record(AuditOperation::Update, Some(port), upstream, result)
+            },
+        );
 
     // Create the proxy binding deletion route
-    let timeout_clone = timeout;
     let delete_binding_route = warp::path!("proxy" / u16)
         .and(warp::delete())
+        .and(signed(signer.clone()))
         .and(bindings_filter.clone())
-        .and(warp::any().map(move || timeout_clone))
-        .and_then(handle_delete_binding);
+        .and(events_filter.clone())
+        .and(audited(audit_log.clone()))
+        .and_then(|port, bindings, events, audit: Audit| async move {
+            let result = handle_delete_binding(port, bindings, events, Caller(audit.client)).await;
+            audit.record(AuditOperation::Delete, Some(port), None, result)
+        });
 
-    create_binding_route
+    // Create the proxy binding connection reset route
+    let reset_binding_route = warp::path!("proxy" / u16 / "reset")
+        .and(warp::post())
+        .and(signed(signer.clone()))
+        .and(bindings_filter.clone())
+        .and(audited(audit_log.clone()))
+        .and_then(|port, bindings, audit: Audit| async move {
+            let result = handle_reset_binding(port, bindings).await;
+            audit.record(AuditOperation::Reset, Some(port), None, result)
+        });
+
+    // Create the proxy binding pause and resume routes
+    let pause_binding = |port, bindings, events, paused, audit: Audit| async move {
+        let operation = if paused {
+            AuditOperation::Pause
+        } else {
+            AuditOperation::Resume
+        };
+        let result = handle_pause_binding(port, bindings, events, paused).await;
+        audit.record(operation, Some(port), None, result)
+    };
+    let pause_binding_route = warp::path!("proxy" / u16 / "pause")
+        .and(warp::post())
+        .and(signed(signer.clone()))
+        .and(bindings_filter.clone())
+        .and(events_filter.clone())
+        .and(warp::any().map(|| true))
+        .and(audited(audit_log.clone()))
+        .and_then(pause_binding);
+    let resume_binding_route = warp::path!("proxy" / u16 / "resume")
+        .and(warp::post())
+        .and(signed(signer.clone()))
+        .and(bindings_filter.clone())
+        .and(events_filter)
+        .and(warp::any().map(|| false))
+        .and(audited(audit_log))
+        .and_then(pause_binding);
+
+    // Create the proxy binding stats route
+    let binding_stats_route = warp::path!("proxy" / u16 / "stats")
+        .and(warp::get())
+        .and(signed(signer.clone()))
+        .and(bindings_filter.clone())
+        .and_then(handle_binding_stats);
+
+    // Create the proxy binding health check route
+    let binding_health_check_route = warp::path!("proxy" / u16 / "healthcheck")
+        .and(warp::post())
+        .and(signed(signer.clone()))
+        .and(warp::any().map(move || health_check_context.clone()))
+        .and_then(handle_binding_health_check);
+
+    // Create the proxy binding capture route
+    let binding_capture_route = warp::path!("proxy" / u16 / "capture")
+        .and(warp::get())
+        .and(signed(signer))
+        .and(bindings_filter.clone())
+        .and_then(handle_binding_capture);
+
+    export_bindings_route
+        .or(import_bindings_route)
+        .or(reset_binding_route)
+        .or(pause_binding_route)
+        .or(resume_binding_route)
+        .or(binding_stats_route)
+        .or(binding_health_check_route)
+        .or(binding_capture_route)
+        .or(list_bindings_route)
+        .or(create_binding_route)
         .or(update_binding_route)
         .or(delete_binding_route)
 }
 
-/// Create health check route
-///
-/// This function sets up a route for checking the health of the proxy server.
-/// It returns information about the server status and active bindings.
+/// Create the audit log route
+///
+/// `GET /audit` returns the latest changes made to bindings through the API.
+/// It is signed like the `/proxy` routes, since the entries reveal upstreams
+/// and callers.
+///
+/// # Arguments
+///
+/// * `audit_log` - The log to serve
+/// * `signer` - Verifies the request signature, if signing is enabled
+///
+/// # Returns
+///
+/// A warp filter that handles audit log requests
+fn create_audit_route(
+    audit_log: AuditLog,
+    signer: Option<Arc<RequestSigner>>,
+) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    warp::path("audit")
+        .and(warp::path::end())
+        .and(warp::get())
+        .and(signed(signer))
+        .map(move || {
+            warp::reply::json(&AuditResponse {
+                capacity: audit_log.capacity(),
+                entries: audit_log.snapshot(),
+            })
+        })
+}
+
+/// Create health check route
+///
+/// This function sets up a route for checking the health of the proxy server.
+/// It returns information about the server status and active bindings.
+///
+/// # Arguments
+///
+/// * `context` - Server-wide settings and the bindings to report on
+///
+/// # Returns
+///
+/// A warp filter that handles health check requests
+fn create_health_route(
+    context: Arc<ProxyContext>,
+) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    warp::path("health")
+        .and(warp::get())
+        .and(warp::any().map(move || context.clone()))
+        .and_then(handle_health_request)
+}
+
+/// Create the route streaming binding events
+///
+/// Each `GET /events` request subscribes to the event bus and receives every
+/// event published afterwards as a Server-Sent Event, named after the event
+/// type and carrying the event as JSON. The subscription ends when the client
+/// disconnects. It is signed like the `/proxy` routes, since the events reveal
+/// every binding's port and upstream.
+///
+/// # Arguments
+///
+/// * `events` - The bus binding events are published on
+/// * `signer` - Verifies the request signature, if signing is enabled
+///
+/// # Returns
+///
+/// A warp filter answering `GET /events` with a `text/event-stream`
+fn create_events_route(
+    events: EventBus,
+    signer: Option<Arc<RequestSigner>>,
+) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    warp::path("events")
+        .and(warp::path::end())
+        .and(warp::get())
+        .and(signed(signer))
+        .map(move || {
+            debug!("Subscribing a client to binding events");
+            let stream = BroadcastStream::new(events.subscribe()).filter_map(|event| match event {
+                Ok(event) => Some(Ok::<_, Infallible>(sse_event(&event))),
+                Err(BroadcastStreamRecvError::Lagged(missed)) => {
+                    warn!("Event stream client fell behind, skipped {} events", missed);
+                    None
+                }
+            });
+            warp::sse::reply(warp::sse::keep_alive().stream(stream))
+        })
+}
+
+/// Build the Server-Sent Event announcing a binding event
+///
+/// # Arguments
+///
+/// * `event` - The binding event
+///
+/// # Returns
+///
+/// An event named after the event type, with the event as JSON data
+fn sse_event(event: &BindingEvent) -> Event {
+    Event::default()
+        .event(event.kind.as_str())
+        .data(serde_json::to_string(event).unwrap_or_default())
+}
+
+/// Create the route serving the OpenAPI description of the API
+///
+/// # Returns
+///
+/// A warp filter answering `GET /openapi.json` with [`openapi_document`]
+fn create_openapi_route() -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    let document = openapi_document();
+    warp::path("openapi.json")
+        .and(warp::path::end())
+        .and(warp::get())
+        .map(move || warp::reply::json(&document))
+}
+
+/// Build the OpenAPI 3 document describing the API
 ///
-/// # Arguments
-///
-/// * `bindings` - Shared state containing active proxy bindings
+/// The schemas mirror the request and response structs defined in this
+/// module and must be kept in sync with them.
 ///
 /// # Returns
 ///
-/// A warp filter that handles health check requests
-fn create_health_route(
-    bindings: BindingMap,
-) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
-    let bindings_filter = warp::any().map(move || bindings.clone());
+/// The OpenAPI document as JSON
+pub fn openapi_document() -> Value {
+    let error_responses = json!({
+        "400": {
+            "description": "The request body is not valid JSON or has invalid fields, or the binding could not be validated or bound",
+            "content": {"application/json": {"schema": {"$ref": "#/components/schemas/ErrorResponse"}}}
+        },
+        "401": {
+            "description": "Request signing is enabled and the X-Timestamp or X-Signature header is missing, stale or wrong",
+            "content": {"application/json": {"schema": {"$ref": "#/components/schemas/ErrorResponse"}}}
+        },
+        "429": {
+            "description": "The API rate limit was exceeded",
+            "headers": {
+                "Retry-After": {
+                    "description": "Seconds until the rate limit allows another request",
+                    "schema": {"type": "integer"}
+                }
+            },
+            "content": {"application/json": {"schema": {"$ref": "#/components/schemas/ErrorResponse"}}}
+        },
+        "500": {
+            "description": "An unexpected error occurred",
+            "content": {"application/json": {"schema": {"$ref": "#/components/schemas/ErrorResponse"}}}
+        }
+    });
+    let not_found = json!({
+        "description": "No binding exists on the port",
+        "content": {"application/json": {"schema": {"$ref": "#/components/schemas/ErrorResponse"}}}
+    });
+    let port_parameter = json!({
+        "name": "port",
+        "in": "path",
+        "required": true,
+        "schema": {"type": "integer", "minimum": 1, "maximum": 65535}
+    });
+    let json_response = |description: &str, schema: &str| {
+        json!({
+            "description": description,
+            "content": {"application/json": {"schema": {"$ref": format!("#/components/schemas/{}", schema)}}}
+        })
+    };
+    let with_errors = |ok: Value| {
+        let mut responses = error_responses.clone();
+        responses["200"] = ok;
+        responses
+    };
+    let with_port_errors = |ok: Value| {
+        let mut responses = with_errors(ok);
+        responses["404"] = not_found.clone();
+        responses
+    };
 
-    warp::path("health")
-        .and(warp::get())
-        .and(bindings_filter)
-        .and_then(handle_health_request)
+    let mut create_responses = with_errors(json_response(
+        "The binding was created",
+        "CreateBindingResponse",
+    ));
+    create_responses["507"] = json!({
+        "description": "The maximum number of bindings already exist",
+        "content": {"application/json": {"schema": {"$ref": "#/components/schemas/ErrorResponse"}}}
+    });
+    let mut import_responses = with_errors(json_response(
+        "The bindings were reconciled",
+        "ImportBindingsResponse",
+    ));
+    import_responses["507"] = json!({
+        "description": "The document lists more bindings than may exist",
+        "content": {"application/json": {"schema": {"$ref": "#/components/schemas/ErrorResponse"}}}
+    });
+
+    let string_list = json!({"type": "array", "items": {"type": "string"}});
+    let header_rules =
+        json!({"type": "array", "items": {"$ref": "#/components/schemas/HeaderRule"}});
+    let upstream_targets =
+        json!({"type": "array", "items": {"$ref": "#/components/schemas/UpstreamTarget"}});
+    let routes = json!({"type": "array", "items": {"$ref": "#/components/schemas/Route"}});
+    let header_routes =
+        json!({"allOf": [{"$ref": "#/components/schemas/HeaderRoutes"}], "nullable": true});
+    let created_at = json!({"type": "integer", "description": "Unix time in seconds the binding was created at"});
+    let last_active_at = json!({
+        "type": "integer",
+        "nullable": true,
+        "description": "Unix time in seconds the binding last accepted a connection at"
+    });
+    let tags = json!({
+        "type": "object",
+        "description": "Labels organizing the binding; keys must not contain ':'",
+        "additionalProperties": {"type": "string"}
+    });
+
+    json!({
+        "openapi": "3.0.3",
+        "info": {
+            "title": "Metaproxy API",
+            "description": "Manage proxy bindings at runtime",
+            "version": env!("CARGO_PKG_VERSION")
+        },
+        "paths": {
+            "/proxy": {
+                "get": {
+                    "summary": "List proxy bindings, optionally filtered by tag",
+                    "parameters": [{
+                        "name": "tag",
+                        "in": "query",
+                        "description": "A `key` or `key:value` filter; bindings must match every filter",
+                        "schema": {"type": "array", "items": {"type": "string"}},
+                        "style": "form",
+                        "explode": true
+                    }],
+                    "responses": {
+                        "200": json_response("The matching bindings", "ListBindingsResponse"),
+                        "401": error_responses["401"],
+                        "429": error_responses["429"]
+                    }
+                },
+                "post": {
+                    "summary": "Create a proxy binding",
+                    "requestBody": {
+                        "required": true,
+                        "content": {"application/json": {"schema": {"$ref": "#/components/schemas/CreateBindingRequest"}}}
+                    },
+                    "responses": create_responses
+                }
+            },
+            "/proxy/{port}": {
+                "parameters": [port_parameter],
+                "put": {
+                    "summary": "Update the upstream, request rules or tags of a proxy binding",
+                    "requestBody": {
+                        "required": true,
+                        "content": {"application/json": {"schema": {"$ref": "#/components/schemas/UpdateBindingRequest"}}}
+                    },
+                    "responses": with_port_errors(json_response("The binding was updated", "UpdateBindingResponse"))
+                },
+                "delete": {
+                    "summary": "Delete a proxy binding",
+                    "responses": with_port_errors(json_response("The binding was deleted", "DeleteBindingResponse"))
+                }
+            },
+            "/proxy/export": {
+                "get": {
+                    "summary": "Export every proxy binding as a config file document",
+                    "parameters": [{
+                        "name": "include_secrets",
+                        "in": "query",
+                        "description": "Export upstream credentials instead of REDACTED; requires signed requests",
+                        "schema": {"type": "boolean", "default": false}
+                    }],
+                    "responses": {
+                        "200": json_response("Every binding, ordered by port", "BindingsDocument"),
+                        "401": error_responses["401"],
+                        "403": {
+                            "description": "include_secrets was set while request signing is disabled",
+                            "content": {"application/json": {"schema": {"$ref": "#/components/schemas/ErrorResponse"}}}
+                        },
+                        "429": error_responses["429"]
+                    }
+                }
+            },
+            "/proxy/import": {
+                "put": {
+                    "summary": "Make the proxy bindings match an exported document",
+                    "requestBody": {
+                        "required": true,
+                        "content": {"application/json": {"schema": {"$ref": "#/components/schemas/BindingsDocument"}}}
+                    },
+                    "responses": import_responses
+                }
+            },
+            "/proxy/{port}/reset": {
+                "parameters": [port_parameter],
+                "post": {
+                    "summary": "Terminate the active connections of a proxy binding",
+                    "responses": with_port_errors(json_response("The connections were terminated", "ResetBindingResponse"))
+                }
+            },
+            "/proxy/{port}/pause": {
+                "parameters": [port_parameter],
+                "post": {
+                    "summary": "Close new connections of a proxy binding, keeping active ones",
+                    "responses": with_port_errors(json_response("The binding was paused", "PauseBindingResponse"))
+                }
+            },
+            "/proxy/{port}/resume": {
+                "parameters": [port_parameter],
+                "post": {
+                    "summary": "Proxy new connections of a paused proxy binding again",
+                    "responses": with_port_errors(json_response("The binding was resumed", "PauseBindingResponse"))
+                }
+            },
+            "/proxy/{port}/stats": {
+                "parameters": [port_parameter],
+                "get": {
+                    "summary": "Report the traffic counters of a proxy binding",
+                    "responses": {
+                        "200": json_response("The binding's traffic counters", "BindingStatsResponse"),
+                        "401": error_responses["401"],
+                        "404": not_found,
+                        "429": error_responses["429"]
+                    }
+                }
+            },
+            "/proxy/{port}/healthcheck": {
+                "parameters": [port_parameter],
+                "post": {
+                    "summary": "Probe the upstreams of a proxy binding now and record which are healthy",
+                    "responses": {
+                        "200": json_response("The outcome of each probe", "HealthCheckResponse"),
+                        "401": error_responses["401"],
+                        "404": not_found,
+                        "429": error_responses["429"]
+                    }
+                }
+            },
+            "/proxy/{port}/capture": {
+                "parameters": [port_parameter],
+                "get": {
+                    "summary": "Report the bytes captured from the latest connections of a proxy binding",
+                    "responses": {
+                        "200": json_response("The binding's captured connections", "CaptureResponse"),
+                        "401": error_responses["401"],
+                        "404": not_found,
+                        "409": {
+                            "description": "The binding does not have debug_capture set",
+                            "content": {"application/json": {"schema": {"$ref": "#/components/schemas/ErrorResponse"}}}
+                        },
+                        "429": error_responses["429"]
+                    }
+                }
+            },
+            "/health": {
+                "get": {
+                    "summary": "Report the server status and active bindings",
+                    "responses": {"200": json_response("The server status", "HealthResponse")}
+                }
+            },
+            "/events": {
+                "get": {
+                    "summary": "Stream binding creations, updates, deletions, pauses and resumes",
+                    "responses": {
+                        "200": {
+                            "description": "Server-Sent Events named after the event type, each carrying a BindingEvent",
+                            "content": {"text/event-stream": {"schema": {"$ref": "#/components/schemas/BindingEvent"}}}
+                        }
+                    }
+                }
+            },
+            "/audit": {
+                "get": {
+                    "summary": "List the latest changes made to bindings through the API",
+                    "responses": {"200": json_response("The audit log, oldest entry first", "AuditResponse")}
+                }
+            },
+            "/openapi.json": {
+                "get": {
+                    "summary": "Describe the API",
+                    "responses": {
+                        "200": {
+                            "description": "This OpenAPI document",
+                            "content": {"application/json": {"schema": {"type": "object"}}}
+                        }
+                    }
+                }
+            }
+        },
+        "components": {
+            "schemas": {
+                "CreateBindingRequest": {
+                    "type": "object",
+                    "description": "Exactly one of port and port_range is required",
+                    "properties": {
+                        "port": {"type": "integer", "minimum": 0, "maximum": 65535, "description": "0 binds an ephemeral port"},
+                        "port_range": {
+                            "type": "array",
+                            "items": {"type": "integer", "minimum": 1, "maximum": 65535},
+                            "minItems": 2,
+                            "maxItems": 2,
+                            "description": "The first and last port of a range; the first free port is bound"
+                        },
+                        "upstream": {"type": "string"},
+                        "upstream_chain": string_list,
+                        "next_hop": {"type": "string", "nullable": true},
+                        "upstreams": upstream_targets,
+                        "strategy": {"$ref": "#/components/schemas/Strategy"},
+                        "upstream_mode": {"$ref": "#/components/schemas/UpstreamMode"},
+                        "routes": routes,
+                        "header_routes": header_routes,
+                        "response_headers": header_rules,
+                        "request_headers": header_rules,
+                        "upstream_auth": {"$ref": "#/components/schemas/UpstreamAuth"},
+                        "require_upstream_auth": {"type": "boolean", "default": false},
+                        "retry_idempotent": {"type": "boolean", "default": false},
+                        "health_check": {"$ref": "#/components/schemas/HealthCheck"},
+                        "compress_responses": {"type": "boolean", "default": false},
+                        "connect_bind": {"type": "string", "description": "An IP address assigned to the host"},
+                        "log_requests": {"type": "boolean", "default": false},
+                        "debug_capture": {"type": "boolean", "default": false},
+                        "capture_bytes": {"type": "integer", "minimum": 1, "maximum": MAX_CAPTURE_BYTES, "nullable": true},
+                        "tags": tags
+                    }
+                },
+                "UpdateBindingRequest": {
+                    "type": "object",
+                    "properties": {
+                        "upstream": {"type": "string"},
+                        "upstream_chain": string_list,
+                        "upstreams": upstream_targets,
+                        "strategy": {"$ref": "#/components/schemas/Strategy"},
+                        "request_headers": header_rules,
+                        "tags": tags,
+                        "log_requests": {"type": "boolean"}
+                    }
+                },
+                "CreateBindingResponse": {
+                    "type": "object",
+                    "required": ["status", "port", "upstream", "upstream_chain", "next_hop", "upstreams", "strategy", "upstream_mode", "routes", "header_routes", "response_headers", "request_headers", "retry_idempotent", "health_check", "compress_responses", "connect_bind", "log_requests", "debug_capture", "capture_bytes", "tags"],
+                    "properties": {
+                        "status": {"type": "string", "enum": ["created"]},
+                        "port": {"type": "integer"},
+                        "upstream": {"type": "string"},
+                        "upstream_chain": string_list,
+                        "next_hop": {"type": "string", "nullable": true},
+                        "upstreams": upstream_targets,
+                        "strategy": {"$ref": "#/components/schemas/Strategy"},
+                        "upstream_mode": {"$ref": "#/components/schemas/UpstreamMode"},
+                        "routes": routes,
+                        "header_routes": header_routes,
+                        "response_headers": header_rules,
+                        "request_headers": header_rules,
+                        "retry_idempotent": {"type": "boolean"},
+                        "health_check": {"allOf": [{"$ref": "#/components/schemas/HealthCheck"}], "nullable": true},
+                        "compress_responses": {"type": "boolean"},
+                        "connect_bind": {"type": "string", "nullable": true},
+                        "log_requests": {"type": "boolean"},
+                        "debug_capture": {"type": "boolean"},
+                        "capture_bytes": {"type": "integer", "nullable": true},
+                        "tags": tags
+                    }
+                },
+                "UpdateBindingResponse": {
+                    "type": "object",
+                    "required": ["status", "port", "upstream", "upstream_chain", "upstreams", "strategy", "request_headers", "tags", "log_requests"],
+                    "properties": {
+                        "status": {"type": "string", "enum": ["updated"]},
+                        "port": {"type": "integer"},
+                        "upstream": {"type": "string"},
+                        "upstream_chain": string_list,
+                        "upstreams": upstream_targets,
+                        "strategy": {"$ref": "#/components/schemas/Strategy"},
+                        "request_headers": header_rules,
+                        "tags": tags,
+                        "log_requests": {"type": "boolean"}
+                    }
+                },
+                "ListBindingsResponse": {
+                    "type": "object",
+                    "required": ["bindings"],
+                    "properties": {
+                        "bindings": {"type": "array", "items": {"$ref": "#/components/schemas/BindingSummary"}}
+                    }
+                },
+                "BindingSummary": {
+                    "type": "object",
+                    "required": ["port", "upstream", "upstream_chain", "next_hop", "upstreams", "strategy", "upstream_mode", "routes", "header_routes", "debug_capture", "tags"],
+                    "properties": {
+                        "port": {"type": "integer"},
+                        "upstream": {"type": "string"},
+                        "upstream_chain": string_list,
+                        "next_hop": {"type": "string", "nullable": true},
+                        "upstreams": upstream_targets,
+                        "strategy": {"$ref": "#/components/schemas/Strategy"},
+                        "upstream_mode": {"$ref": "#/components/schemas/UpstreamMode"},
+                        "routes": routes,
+                        "header_routes": header_routes,
+                        "debug_capture": {"type": "boolean"},
+                        "tags": tags
+                    }
+                },
+                "DeleteBindingResponse": {
+                    "type": "object",
+                    "required": ["status", "port"],
+                    "properties": {
+                        "status": {"type": "string", "enum": ["deleted"]},
+                        "port": {"type": "integer"}
+                    }
+                },
+                "ResetBindingResponse": {
+                    "type": "object",
+                    "required": ["status", "port", "connections"],
+                    "properties": {
+                        "status": {"type": "string", "enum": ["reset"]},
+                        "port": {"type": "integer"},
+                        "connections": {"type": "integer"}
+                    }
+                },
+                "PauseBindingResponse": {
+                    "type": "object",
+                    "required": ["status", "port", "active_connections"],
+                    "properties": {
+                        "status": {"type": "string", "enum": ["paused", "resumed"]},
+                        "port": {"type": "integer"},
+                        "active_connections": {"type": "integer"}
+                    }
+                },
+                "BindingsDocument": {
+                    "type": "object",
+                    "required": ["bindings"],
+                    "properties": {
+                        "bindings": {"type": "array", "items": {"$ref": "#/components/schemas/CreateBindingRequest"}}
+                    }
+                },
+                "ImportBindingsResponse": {
+                    "type": "object",
+                    "required": ["status", "created", "updated", "replaced", "removed", "failed"],
+                    "properties": {
+                        "status": {"type": "string", "enum": ["imported"]},
+                        "created": {"type": "array", "items": {"type": "integer"}},
+                        "updated": {"type": "array", "items": {"type": "integer"}},
+                        "replaced": {"type": "array", "items": {"type": "integer"}},
+                        "removed": {"type": "array", "items": {"type": "integer"}},
+                        "failed": {"type": "array", "items": {"type": "integer"}}
+                    }
+                },
+                "HealthCheckResponse": {
+                    "type": "object",
+                    "required": ["port", "upstreams"],
+                    "properties": {
+                        "port": {"type": "integer"},
+                        "upstreams": {
+                            "type": "array",
+                            "items": {
+                                "type": "object",
+                                "required": ["url", "healthy", "error"],
+                                "properties": {
+                                    "url": {"type": "string"},
+                                    "healthy": {"type": "boolean"},
+                                    "error": {"type": "string", "nullable": true}
+                                }
+                            }
+                        }
+                    }
+                },
+                "CaptureResponse": {
+                    "type": "object",
+                    "required": ["port", "capture_bytes", "connections"],
+                    "properties": {
+                        "port": {"type": "integer"},
+                        "capture_bytes": {"type": "integer"},
+                        "connections": {
+                            "type": "array",
+                            "items": {
+                                "type": "object",
+                                "required": ["client", "started_at", "from_client", "from_upstream", "truncated"],
+                                "properties": {
+                                    "client": {"type": "string"},
+                                    "started_at": {"type": "integer", "description": "Unix seconds"},
+                                    "from_client": {"type": "string", "description": "Escaped ASCII"},
+                                    "from_upstream": {"type": "string", "description": "Escaped ASCII"},
+                                    "truncated": {"type": "boolean"}
+                                }
+                            }
+                        }
+                    }
+                },
+                "AuditResponse": {
+                    "type": "object",
+                    "required": ["capacity", "entries"],
+                    "properties": {
+                        "capacity": {"type": "integer"},
+                        "entries": {"type": "array", "items": {"$ref": "#/components/schemas/AuditEntry"}}
+                    }
+                },
+                "AuditEntry": {
+                    "type": "object",
+                    "required": ["timestamp", "operation", "port", "upstream", "client", "status", "error"],
+                    "properties": {
+                        "timestamp": {"type": "integer", "description": "Unix seconds"},
+                        "operation": {"type": "string", "enum": ["create", "update", "delete", "reset", "pause", "resume", "import"]},
+                        "port": {"type": "integer", "nullable": true},
+                        "upstream": {"type": "string", "nullable": true},
+                        "client": {"type": "string", "nullable": true},
+                        "status": {"type": "integer"},
+                        "error": {"type": "string", "nullable": true}
+                    }
+                },
+                "BindingEvent": {
+                    "type": "object",
+                    "required": ["type", "port", "upstream"],
+                    "properties": {
+                        "type": {"type": "string", "enum": ["created", "updated", "deleted", "paused", "resumed"]},
+                        "port": {"type": "integer"},
+                        "upstream": {"type": "string"}
+                    }
+                },
+                "HealthResponse": {
+                    "type": "object",
+                    "required": ["status", "active_bindings", "connections", "load", "bindings"],
+                    "properties": {
+                        "status": {"type": "string", "enum": ["ok"]},
+                        "active_bindings": {"type": "integer"},
+                        "connections": {"$ref": "#/components/schemas/ConnectionsHealth"},
+                        "load": {"$ref": "#/components/schemas/LoadHealth"},
+                        "bindings": {"type": "array", "items": {"$ref": "#/components/schemas/BindingHealth"}}
+                    }
+                },
+                "ConnectionsHealth": {
+                    "type": "object",
+                    "required": ["active", "max", "idle_reaped"],
+                    "properties": {
+                        "active": {"type": "integer"},
+                        "max": {"type": "integer", "nullable": true},
+                        "idle_reaped": {"type": "integer"}
+                    }
+                },
+                "LoadHealth": {
+                    "type": "object",
+                    "required": ["current", "capacity", "utilization", "saturated"],
+                    "properties": {
+                        "current": {"type": "integer", "description": "Active proxied connections"},
+                        "capacity": {"type": "integer", "nullable": true, "description": "The server-wide connection limit"},
+                        "utilization": {"type": "number", "nullable": true, "description": "current divided by capacity"},
+                        "saturated": {"type": "boolean", "description": "Whether utilization is at least 0.9"}
+                    }
+                },
+                "BindingHealth": {
+                    "type": "object",
+                    "required": ["port", "upstream", "upstream_chain", "upstreams", "strategy", "upstream_mode", "active_connections", "paused", "upstream_errors", "connection_durations", "tags", "created_at", "last_active_at"],
+                    "properties": {
+                        "port": {"type": "integer"},
+                        "upstream": {"type": "string"},
+                        "upstream_chain": string_list,
+                        "upstreams": {"type": "array", "items": {"$ref": "#/components/schemas/UpstreamHealth"}},
+                        "strategy": {"allOf": [{"$ref": "#/components/schemas/Strategy"}], "nullable": true},
+                        "upstream_mode": {"$ref": "#/components/schemas/UpstreamMode"},
+                        "active_connections": {"type": "integer"},
+                        "paused": {"type": "boolean"},
+                        "upstream_errors": {
+                            "type": "object",
+                            "description": "Counts of CONNECT error responses keyed by status code",
+                            "additionalProperties": {"type": "integer"}
+                        },
+                        "connection_durations": {"$ref": "#/components/schemas/ConnectionDurations"},
+                        "tags": tags,
+                        "created_at": created_at,
+                        "last_active_at": last_active_at
+                    }
+                },
+                "BindingStatsResponse": {
+                    "type": "object",
+                    "required": ["port", "upstream", "active_connections", "total_connections", "bytes_from_client", "bytes_from_upstream", "connect_errors", "average_connect_latency_ms", "upstream_errors", "connection_durations", "upstreams", "created_at", "last_active_at"],
+                    "properties": {
+                        "port": {"type": "integer"},
+                        "upstream": {"type": "string"},
+                        "active_connections": {"type": "integer"},
+                        "total_connections": {"type": "integer"},
+                        "bytes_from_client": {"type": "integer"},
+                        "bytes_from_upstream": {"type": "integer"},
+                        "connect_errors": {"type": "integer"},
+                        "average_connect_latency_ms": {"type": "number", "nullable": true},
+                        "upstream_errors": {
+                            "type": "object",
+                            "description": "Counts of CONNECT error responses keyed by status code",
+                            "additionalProperties": {"type": "integer"}
+                        },
+                        "connection_durations": {"$ref": "#/components/schemas/ConnectionDurations"},
+                        "upstreams": {"type": "array", "items": {"$ref": "#/components/schemas/UpstreamHealth"}},
+                        "created_at": created_at,
+                        "last_active_at": last_active_at
+                    }
+                },
+                "ConnectionDurations": {
+                    "type": "object",
+                    "description": "How long finished connections lived, estimated from fixed buckets",
+                    "required": ["tunnel", "http"],
+                    "properties": {
+                        "tunnel": {"allOf": [{"$ref": "#/components/schemas/DurationSummary"}], "nullable": true},
+                        "http": {"allOf": [{"$ref": "#/components/schemas/DurationSummary"}], "nullable": true}
+                    }
+                },
+                "DurationSummary": {
+                    "type": "object",
+                    "required": ["count", "p50_ms", "p90_ms", "p99_ms", "max_ms"],
+                    "properties": {
+                        "count": {"type": "integer"},
+                        "p50_ms": {"type": "number"},
+                        "p90_ms": {"type": "number"},
+                        "p99_ms": {"type": "number"},
+                        "max_ms": {"type": "number"}
+                    }
+                },
+                "UpstreamHealth": {
+                    "type": "object",
+                    "required": ["url", "weight", "selections", "active", "healthy"],
+                    "properties": {
+                        "url": {"type": "string"},
+                        "weight": {"type": "integer"},
+                        "selections": {"type": "integer"},
+                        "active": {"type": "integer"},
+                        "healthy": {"type": "boolean"}
+                    }
+                },
+                "HealthCheck": {
+                    "type": "object",
+                    "properties": {
+                        "interval": {"type": "integer", "minimum": 1, "default": 10},
+                        "timeout": {"type": "integer", "minimum": 1, "default": 2},
+                        "when_all_unhealthy": {"type": "string", "enum": ["fail", "try_anyway"], "default": "fail"}
+                    }
+                },
+                "UpstreamTarget": {
+                    "type": "object",
+                    "required": ["url"],
+                    "properties": {
+                        "url": {"type": "string"},
+                        "weight": {"type": "integer", "minimum": 0, "default": 1}
+                    }
+                },
+                "Route": {
+                    "type": "object",
+                    "required": ["match", "upstream"],
+                    "properties": {
+                        "match": {"type": "string", "description": "Host pattern where `*` matches any run of characters"},
+                        "upstream": {"type": "string", "description": "An upstream proxy URL, or `DIRECT` to connect to the target itself"}
+                    }
+                },
+                "HeaderRoutes": {
+                    "type": "object",
+                    "required": ["header", "upstreams"],
+                    "properties": {
+                        "header": {"type": "string", "description": "The request header whose value picks the upstream"},
+                        "upstreams": {
+                            "type": "object",
+                            "description": "Upstream proxy URLs keyed by header value",
+                            "additionalProperties": {"type": "string"}
+                        }
+                    }
+                },
+                "HeaderRule": {
+                    "type": "object",
+                    "required": ["op", "name"],
+                    "properties": {
+                        "op": {"type": "string", "enum": ["set", "add", "remove", "rewrite"]},
+                        "name": {"type": "string"},
+                        "value": {"type": "string", "description": "Required by set and add"},
+                        "from": {"type": "string", "description": "Required by rewrite"},
+                        "to": {"type": "string", "description": "Required by rewrite"}
+                    }
+                },
+                "UpstreamAuth": {
+                    "type": "object",
+                    "required": ["scheme"],
+                    "properties": {
+                        "scheme": {"type": "string", "enum": ["basic", "bearer", "header"], "default": "basic"},
+                        "token": {"type": "string", "description": "Required by bearer"},
+                        "name": {"type": "string", "description": "Required by header"},
+                        "value": {"type": "string", "description": "Required by header"}
+                    }
+                },
+                "Strategy": {
+                    "type": "string",
+                    "enum": ["round_robin", "weighted", "least_connections", "random"],
+                    "default": "weighted"
+                },
+                "UpstreamMode": {
+                    "type": "string",
+                    "enum": ["proxy", "origin", "reverse", "router", "direct"],
+                    "default": "proxy"
+                },
+                "ErrorResponse": {
+                    "type": "object",
+                    "required": ["error"],
+                    "properties": {"error": {"$ref": "#/components/schemas/ErrorDetail"}}
+                },
+                "ErrorDetail": {
+                    "type": "object",
+                    "required": ["code", "message"],
+                    "properties": {"code": {"type": "string"}, "message": {"type": "string"}}
+                }
+            }
+        }
+    })
 }
 
 /// Handle proxy binding creation requests
 ///
 /// This function handles requests for creating new proxy bindings.
 /// It processes the request and updates the shared state accordingly.
+/// A `port` of 0 binds an ephemeral port, which is returned in the response.
 ///
 /// # Arguments
 ///
-/// * `bindings` - Shared state containing active proxy bindings
-/// * `body` - The request body as JSON
-/// * `timeout` - Optional request timeout for upstream connections
+/// * `context` - Server-wide settings and the bindings to add to
+/// * `request` - The binding to create
+/// * `max_bindings` - Maximum number of bindings that may exist, if limited
+/// * `caller` - Who requested the binding, for log messages
 ///
 /// # Returns
 ///
 /// A result containing a JSON response or a rejection
 async fn handle_create_binding(
-    bindings: BindingMap,
-    body: Value,
-    timeout: Option<Duration>,
-) -> std::result::Result<impl Reply, Rejection> {
-    // For creation, extract "port" and "upstream" from the JSON body.
-    let new_port = body.get("port").and_then(|v| v.as_u64()).ok_or_else(|| {
-        warp::reject::custom(CustomRejection(Error::Custom("Missing port".into())))
-    })? as u16;
-    let upstream = body
-        .get("upstream")
-        .and_then(|v| v.as_str())
-        .ok_or_else(|| {
-            warp::reject::custom(CustomRejection(Error::Custom("Missing upstream".into())))
-        })?
-        .to_string();
+    context: Arc<ProxyContext>,
+    request: CreateBindingRequest,
+    max_bindings: Option<usize>,
+    caller: Caller,
+) -> std::result::Result<CreateBindingResponse, Rejection> {
+    // An explicit port 0 requests an ephemeral port.
+    let port_range = requested_port_range(request.port, request.port_range).map_err(|e| {
+        warn!("Rejected binding with invalid port from {}: {}", caller, e);
+        warp::reject::custom(InvalidBody(e))
+    })?;
+    let requested_port = request.port.unwrap_or(0);
+    let mut spec = BindingSpec::from(request);
+    spec.validate().map_err(|e| {
+        warn!(
+            "Rejected binding on port {} from {}: {}",
+            requested_port, caller, e
+        );
+        warp::reject::custom(CustomRejection(e))
+    })?;
+    spec.normalize();
 
     info!(
-        "Creating new proxy binding on port {} with upstream {}",
-        new_port, upstream
+        "Creating new proxy binding on port {} with upstream {} ({:?} mode) for {}",
+        requested_port, spec.upstream, spec.upstream_mode, caller
     );
 
     // Get the lock once for the entire operation
-    let mut bindings_lock = bindings.lock().await;
+    let mut bindings_lock = context.bindings.lock().await;
 
     // Check if the binding already exists and return error if it does
-    if bindings_lock.contains_key(&new_port) {
-        warn!("Binding on port {} already exists", new_port);
+    if port_range.is_none() && requested_port != 0 && bindings_lock.contains_key(&requested_port) {
+        warn!(
+            "Binding on port {} requested by {} already exists",
+            requested_port, caller
+        );
         return Err(warp::reject::custom(CustomRejection(Error::Custom(
-            format!("Binding on port {} already exists", new_port),
+            format!("Binding on port {} already exists", requested_port),
         ))));
     }
 
-    // Create a new binding.
-    let (shutdown_tx, shutdown_rx) = oneshot::channel();
-    let upstream_arc = Arc::new(Mutex::new(upstream.clone()));
-
-    // Spawn a new proxy listener.
-    let upstream_clone = upstream_arc.clone();
-    let timeout_clone = timeout;
-    tokio::spawn(async move {
-        if let Err(e) =
-            spawn_proxy_listener(new_port, upstream_clone, shutdown_rx, timeout_clone).await
-        {
-            error!("Error in proxy listener: {}", e);
+    // Checked under the same lock the binding is inserted with, so concurrent
+    // creations cannot both pass the check
+    if let Some(max) = max_bindings {
+        if bindings_lock.len() >= max {
+            warn!(
+                "Rejected binding on port {} from {}: limit of {} bindings reached",
+                requested_port, caller, max
+            );
+            return Err(warp::reject::custom(BindingLimitReached(max)));
         }
-    });
+    }
 
-    // Store the binding.
-    bindings_lock.insert(
-        new_port,
-        ProxyBinding {
-            port: new_port,
-            upstream: upstream_arc,
-            shutdown_tx,
-        },
-    );
+    // Bind the port, spawn a new proxy listener and store the binding
+    // under the port that was actually bound. Holding the lock while trying
+    // the ports of a range keeps concurrent requests from picking the same one.
+    let binding = match port_range {
+        Some(ports) => ProxyBinding::bind_in_range(&spec, ports, &bindings_lock, &context)
+            .await
+            .map_err(|e| {
+                warn!("Failed to bind a port in range for {}: {}", caller, e);
+                warp::reject::custom(CustomRejection(e))
+            })?,
+        None => ProxyBinding::bind(&spec, &context).await.map_err(|e| {
+            warn!(
+                "Failed to bind port {} for {}: {}",
+                requested_port, caller, e
+            );
+            warp::reject::custom(CustomRejection(Error::Custom(format!(
+                "Failed to bind port {}: {}",
+                requested_port, e
+            ))))
+        })?,
+    };
+    let new_port = binding.port;
+    bindings_lock.insert(new_port, binding);
+    context
+        .events
+        .publish(BindingEventKind::Created, new_port, &spec.upstream);
 
     debug!("Added binding for port {} to binding map", new_port);
 
     // Drop the lock before returning
     drop(bindings_lock);
 
-    Ok(warp::reply::json(&json!({
-        "status": "created",
-        "port": new_port,
-        "upstream": upstream
-    })))
+    Ok(CreateBindingResponse {
+        status: "created",
+        port: new_port,
+        upstream: spec.upstream,
+        upstream_chain: spec.upstream_chain,
+        next_hop: spec.next_hop,
+        upstreams: spec.upstreams,
+        strategy: spec.strategy,
+        upstream_mode: spec.upstream_mode,
+        routes: spec.routes,
+        header_routes: spec.header_routes,
+        response_headers: spec.response_headers,
+        request_headers: spec.request_headers,
+        retry_idempotent: spec.retry_idempotent,
+        health_check: spec.health_check,
+        compress_responses: spec.compress_responses,
+        connect_bind: spec.connect_bind,
+        log_requests: spec.log_requests,
+        debug_capture: spec.debug_capture,
+        capture_bytes: spec.capture_bytes,
+        tags: spec.tags,
+    })
+}
+
+/// Check the port fields of a `POST /proxy` request
+///
+/// Exactly one of `port` and `port_range` must be given, and a range must
+/// name its first port before its last, neither being 0.
+///
+/// # Arguments
+///
+/// * `port` - The requested port, if any
+/// * `port_range` - The requested first and last port, if any
+///
+/// # Returns
+///
+/// The ports to try for a range, `None` for a single port, or an error
+/// describing the invalid request body
+fn requested_port_range(
+    port: Option<u16>,
+    port_range: Option<(u16, u16)>,
+) -> std::result::Result<Option<RangeInclusive<u16>>, serde_json::Error> {
+    match (port, port_range) {
+        (Some(_), None) => Ok(None),
+        (None, Some((first, last))) if first != 0 && first <= last => Ok(Some(first..=last)),
+        (None, Some(_)) => Err(serde::de::Error::custom(
+            "port_range must be [first, last] with 0 < first <= last",
+        )),
+        (Some(_), Some(_)) => Err(serde::de::Error::custom(
+            "port and port_range cannot both be given",
+        )),
+        (None, None) => Err(serde::de::Error::missing_field("port")),
+    }
 }
 
 /// Handle proxy binding update requests
@@ -205,8 +2328,9 @@ async fn handle_create_binding(
 ///
 /// * `port` - The port number for the proxy binding
 /// * `bindings` - Shared state containing active proxy bindings
-/// * `body` - The request body as JSON
-/// * `timeout` - Optional request timeout for upstream connections
+/// * `events` - The bus the update is published on
+/// * `request` - The changes to the binding
+/// * `caller` - Who requested the update, for log messages
 ///
 /// # Returns
 ///
@@ -214,58 +2338,324 @@ async fn handle_create_binding(
 async fn handle_update_binding(
     port: u16,
     bindings: BindingMap,
-    body: Value,
-    _timeout: Option<Duration>,
+    events: EventBus,
+    request: UpdateBindingRequest,
+    caller: Caller,
 ) -> std::result::Result<impl Reply, Rejection> {
     // For update, use the path parameter as the port.
     if port == 0 {
-        warn!("Missing port in path for PUT request");
+        warn!("Missing port in path for PUT request from {}", caller);
         return Err(warp::reject::custom(CustomRejection(Error::Custom(
             "Missing port in path".into(),
         ))));
     }
 
-    // Extract the new upstream from the JSON body.
-    let new_upstream = body
-        .get("upstream")
-        .and_then(|v| v.as_str())
-        .ok_or_else(|| {
-            warp::reject::custom(CustomRejection(Error::Custom("Missing upstream".into())))
-        })?
-        .to_string();
+    // Validate the new upstream, upstream chain or weighted upstreams along
+    // with any new request header rules and tags. Without any upstream, the
+    // binding keeps its current upstreams.
+    let replace_upstream = !request.upstream.is_empty()
+        || !request.upstream_chain.is_empty()
+        || !request.upstreams.is_empty();
+    let mut target = BindingSpec {
+        port,
+        upstream: request.upstream,
+        upstream_chain: request.upstream_chain,
+        upstreams: request.upstreams,
+        request_headers: request.request_headers.clone().unwrap_or_default(),
+        tags: request.tags.clone().unwrap_or_default(),
+        ..Default::default()
+    };
+    let validated = if replace_upstream {
+        target.validate()
+    } else {
+        validate_rules(&target.request_headers).and_then(|_| validate_tags(&target.tags))
+    };
+    validated.map_err(|e| {
+        warn!("Rejected update of port {} from {}: {}", port, caller, e);
+        warp::reject::custom(CustomRejection(e))
+    })?;
+    target.normalize();
+    let new_strategy = request.strategy;
+    let new_request_headers = request.request_headers;
+    let new_tags = request.tags;
+    let new_log_requests = request.log_requests;
 
-    info!(
-        "Updating proxy binding on port {} with new upstream {}",
-        port, new_upstream
-    );
+    if replace_upstream {
+        info!(
+            "Updating proxy binding on port {} with new upstream {} for {}",
+            port, target.upstream, caller
+        );
+    } else {
+        info!("Updating proxy binding on port {} for {}", port, caller);
+    }
 
     // Get the lock once for the entire operation
     let bindings_lock = bindings.lock().await;
 
     // Check if the binding exists.
     if let Some(binding) = bindings_lock.get(&port) {
-        // Update the upstream.
-        let mut upstream_lock = binding.upstream.lock().await;
-        *upstream_lock = new_upstream.clone();
+        if replace_upstream {
+            // Direct bindings have no upstream to replace
+            if binding.state.upstream_mode == UpstreamMode::Direct {
+                warn!(
+                    "Rejected update of port {} from {}: binding is direct",
+                    port, caller
+                );
+                return Err(warp::reject::custom(CustomRejection(Error::Custom(
+                    "Direct bindings connect to targets themselves and take no upstream".into(),
+                ))));
+            }
+
+            // Keep bindings that require upstream auth from switching to an
+            // upstream without credentials
+            if binding.state.require_upstream_auth {
+                let required = BindingSpec {
+                    upstream_auth: binding.state.upstream_auth.clone(),
+                    ..target.clone()
+                };
+                required.validate_upstream_credentials().map_err(|e| {
+                    warn!("Rejected update of port {} from {}: {}", port, caller, e);
+                    warp::reject::custom(CustomRejection(e))
+                })?;
+            }
+
+            // Update the upstream.
+            binding.state.upstream.store(target.upstream.as_str());
+
+            debug!("Updated upstream for port {} to {}", port, target.upstream);
+
+            // Replace the proxies the upstream is reached through
+            *binding.state.via.lock().await = target.via().to_vec();
+        }
+
+        // Replace the balanced upstreams, keeping the strategy unless a new one
+        // was provided; without any upstreams, `upstream` is used again
+        let mut balancer_lock = binding.state.balancer.lock().await;
+        let strategy = new_strategy.unwrap_or_else(|| balancer_lock.strategy());
+        if replace_upstream {
+            *balancer_lock = Balancer::new(target.upstreams.clone(), strategy);
+        } else if new_strategy.is_some() {
+            *balancer_lock = Balancer::new(balancer_lock.targets().to_vec(), strategy);
+        }
+        drop(balancer_lock);
+
+        // Replace the request header rules if new ones were provided
+        let mut request_headers_lock = binding.state.request_headers.lock().await;
+        if let Some(rules) = new_request_headers {
+            debug!("Updated request header rules for port {}", port);
+            *request_headers_lock = rules;
+        }
+        drop(request_headers_lock);
 
-        debug!("Updated upstream for port {} to {}", port, new_upstream);
+        // Replace the tags if new ones were provided
+        if let Some(tags) = new_tags {
+            debug!("Updated tags for port {}", port);
+            *binding.tags.lock().await = tags;
+        }
+
+        // Start or stop logging requests if asked to
+        if let Some(log_requests) = new_log_requests {
+            debug!("Set request logging for port {} to {}", port, log_requests);
+            binding.set_log_requests(log_requests);
+        }
 
-        // Drop the upstream lock
-        drop(upstream_lock);
+        let current = binding.spec().await;
+        events.publish(BindingEventKind::Updated, port, &current.upstream);
 
         // Drop the bindings lock before returning
         drop(bindings_lock);
 
-        Ok(warp::reply::json(&json!({
-            "status": "updated",
-            "port": port,
-            "upstream": new_upstream
-        })))
+        Ok(warp::reply::json(&UpdateBindingResponse {
+            status: "updated",
+            port,
+            upstream: current.upstream,
+            upstream_chain: current.upstream_chain,
+            upstreams: current.upstreams,
+            strategy,
+            request_headers: current.request_headers,
+            tags: current.tags,
+            log_requests: current.log_requests,
+        }))
     } else {
-        warn!("No binding found for port {} during update", port);
-        Err(warp::reject::custom(CustomRejection(Error::Custom(
-            format!("No binding found for port {}", port),
-        ))))
+        warn!(
+            "No binding found for port {} during update from {}",
+            port, caller
+        );
+        Err(warp::reject::custom(BindingNotFound(port)))
+    }
+}
+
+/// Handle proxy binding listing requests
+///
+/// Each `tag` query parameter is a filter, either `key` to match bindings
+/// having that tag or `key:value` to match its value too; a binding is listed
+/// when it matches every filter. Other query parameters are ignored.
+///
+/// # Arguments
+///
+/// * `bindings` - Shared state containing active proxy bindings
+/// * `query` - The query parameters of the request
+///
+/// # Returns
+///
+/// A result containing a JSON response
+async fn handle_list_bindings(
+    bindings: BindingMap,
+    query: Vec<(String, String)>,
+) -> std::result::Result<impl Reply, Rejection> {
+    let filters: Vec<&str> = query
+        .iter()
+        .filter(|(name, _)| name == "tag")
+        .map(|(_, filter)| filter.as_str())
+        .collect();
+    debug!("Listing bindings matching tags {:?}", filters);
+
+    let bindings_lock = bindings.lock().await;
+    let mut listed = Vec::new();
+    for binding in bindings_lock.values() {
+        let spec = binding.spec().await;
+        if !filters.iter().all(|filter| matches_tag(&spec.tags, filter)) {
+            continue;
+        }
+        listed.push(BindingSummary {
+            port: spec.port,
+            upstream: spec.upstream,
+            upstream_chain: spec.upstream_chain,
+            next_hop: spec.next_hop,
+            upstreams: spec.upstreams,
+            strategy: spec.strategy,
+            upstream_mode: spec.upstream_mode,
+            routes: spec.routes,
+            header_routes: spec.header_routes,
+            debug_capture: spec.debug_capture,
+            tags: spec.tags,
+        });
+    }
+    drop(bindings_lock);
+
+    listed.sort_by_key(|binding| binding.port);
+    Ok(warp::reply::json(&ListBindingsResponse {
+        bindings: listed,
+    }))
+}
+
+/// Handle binding export requests
+///
+/// This function lists the definition of every binding in the format of a
+/// `--config` file, ready to be imported into another instance. Upstream
+/// credentials are replaced with `REDACTED` unless `include_secrets` is set,
+/// which is only allowed when API requests are signed. The flag is covered by
+/// the signature, so it cannot be added to a captured export request.
+///
+/// # Arguments
+///
+/// * `bindings` - Shared state containing active proxy bindings
+/// * `query` - The query parameters of the request
+/// * `signing` - Whether API requests must be signed
+///
+/// # Returns
+///
+/// A result containing a JSON response or a rejection
+async fn handle_export_bindings(
+    bindings: BindingMap,
+    query: ExportQuery,
+    signing: bool,
+) -> std::result::Result<impl Reply, Rejection> {
+    if query.include_secrets && !signing {
+        warn!("Rejected export of secrets while API requests are not signed");
+        return Err(warp::reject::custom(SecretsNotAllowed));
+    }
+    debug!(
+        "Exporting bindings {} secrets",
+        if query.include_secrets {
+            "with"
+        } else {
+            "without"
+        }
+    );
+
+    let bindings_lock = bindings.lock().await;
+    let mut exported = Vec::with_capacity(bindings_lock.len());
+    for binding in bindings_lock.values() {
+        let mut spec = binding.spec().await;
+        if !query.include_secrets {
+            spec.redact_secrets();
+        }
+        exported.push(spec);
+    }
+    drop(bindings_lock);
+
+    exported.sort_by_key(|spec| spec.port);
+    Ok(warp::reply::json(&BindingsFile { bindings: exported }))
+}
+
+/// Handle binding import requests
+///
+/// This function makes the bindings match an exported document, the way a
+/// reload of the `--config` file does: listed bindings are created or
+/// updated, and bindings the document does not list are removed.
+///
+/// # Arguments
+///
+/// * `context` - Server-wide settings and the bindings to reconcile
+/// * `file` - The bindings to run, as exported by `GET /proxy/export`
+/// * `max_bindings` - Maximum number of bindings that may exist, if limited
+/// * `caller` - Who requested the import, for log messages
+///
+/// # Returns
+///
+/// A result containing the changes that were made or a rejection
+async fn handle_import_bindings(
+    context: Arc<ProxyContext>,
+    mut file: BindingsFile,
+    max_bindings: Option<usize>,
+    caller: Caller,
+) -> std::result::Result<impl Reply, Rejection> {
+    file.validate().map_err(|e| {
+        warn!("Rejected import from {}: {}", caller, e);
+        warp::reject::custom(CustomRejection(e))
+    })?;
+    if let Some(max) = max_bindings.filter(|max| file.bindings.len() > *max) {
+        warn!(
+            "Rejected import of {} bindings from {}: limit of {} bindings",
+            file.bindings.len(),
+            caller,
+            max
+        );
+        return Err(warp::reject::custom(BindingLimitReached(max)));
+    }
+
+    info!("Importing {} bindings for {}", file.bindings.len(), caller);
+    let summary = reconcile_bindings(&context, &file.bindings).await;
+    info!(
+        "Import for {}: created {:?}, updated {:?}, replaced {:?}, removed {:?}, failed {:?}",
+        caller, summary.created, summary.updated, summary.replaced, summary.removed, summary.failed
+    );
+
+    Ok(warp::reply::json(&ImportBindingsResponse {
+        status: "imported",
+        created: summary.created,
+        updated: summary.updated,
+        replaced: summary.replaced,
+        removed: summary.removed,
+        failed: summary.failed,
+    }))
+}
+
+/// Check whether binding tags match a `key` or `key:value` filter
+///
+/// # Arguments
+///
+/// * `tags` - The tags of the binding
+/// * `filter` - The filter, split at its first `:`
+///
+/// # Returns
+///
+/// `true` if the binding has the tag, with the given value if there is one
+fn matches_tag(tags: &BTreeMap<String, String>, filter: &str) -> bool {
+    match filter.split_once(':') {
+        Some((key, value)) => tags.get(key).is_some_and(|tag| tag == value),
+        None => tags.contains_key(filter),
     }
 }
 
@@ -277,7 +2667,8 @@ async fn handle_update_binding(
 ///
 /// * `port` - The port number for the proxy binding
 /// * `bindings` - Shared state containing active proxy bindings
-/// * `timeout` - Optional request timeout for upstream connections
+/// * `events` - The bus the deletion is published on
+/// * `caller` - Who requested the deletion, for log messages
 ///
 /// # Returns
 ///
@@ -285,17 +2676,18 @@ async fn handle_update_binding(
 async fn handle_delete_binding(
     port: u16,
     bindings: BindingMap,
-    _timeout: Option<Duration>,
+    events: EventBus,
+    caller: Caller,
 ) -> std::result::Result<impl Reply, Rejection> {
     // For deletion, use the path parameter as the port.
     if port == 0 {
-        warn!("Missing port in path for DELETE request");
+        warn!("Missing port in path for DELETE request from {}", caller);
         return Err(warp::reject::custom(CustomRejection(Error::Custom(
             "Missing port in path".into(),
         ))));
     }
 
-    info!("Deleting proxy binding on port {}", port);
+    info!("Deleting proxy binding on port {} for {}", port, caller);
 
     // Get the lock once for the entire operation
     let mut bindings_lock = bindings.lock().await;
@@ -305,22 +2697,296 @@ async fn handle_delete_binding(
         // Signal the listener to shut down.
         let _ = binding.shutdown_tx.send(());
         debug!("Sent shutdown signal to proxy listener on port {}", port);
+        let upstream = binding.state.upstream.load().to_string();
+        events.publish(BindingEventKind::Deleted, port, upstream);
 
         // Drop the bindings lock before returning
         drop(bindings_lock);
 
-        Ok(warp::reply::json(&json!({
-            "status": "deleted",
-            "port": port
-        })))
+        Ok(warp::reply::json(&DeleteBindingResponse {
+            status: "deleted",
+            port,
+        }))
     } else {
-        warn!("No binding found for port {} during deletion", port);
-        Err(warp::reject::custom(CustomRejection(Error::Custom(
-            format!("No binding found for port {}", port),
-        ))))
+        warn!(
+            "No binding found for port {} during deletion from {}",
+            port, caller
+        );
+        Err(warp::reject::custom(BindingNotFound(port)))
+    }
+}
+
+/// Handle proxy binding connection reset requests
+///
+/// This function terminates the active connections of an existing proxy
+/// binding. The binding keeps listening and accepts new connections normally.
+///
+/// # Arguments
+///
+/// * `port` - The port number for the proxy binding
+/// * `bindings` - Shared state containing active proxy bindings
+///
+/// # Returns
+///
+/// A result containing a JSON response or a rejection
+async fn handle_reset_binding(
+    port: u16,
+    bindings: BindingMap,
+) -> std::result::Result<impl Reply, Rejection> {
+    info!("Resetting connections of proxy binding on port {}", port);
+
+    let bindings_lock = bindings.lock().await;
+    if let Some(binding) = bindings_lock.get(&port) {
+        let reset = binding.reset_connections().await;
+        debug!("Reset {} connections on port {}", reset, port);
+
+        Ok(warp::reply::json(&ResetBindingResponse {
+            status: "reset",
+            port,
+            connections: reset,
+        }))
+    } else {
+        warn!("No binding found for port {} during reset", port);
+        Err(warp::reject::custom(BindingNotFound(port)))
+    }
+}
+
+/// Handle proxy binding pause and resume requests
+///
+/// A paused binding keeps its port but closes every connection it accepts,
+/// while the connections it is already proxying keep running.
+///
+/// # Arguments
+///
+/// * `port` - The port number for the proxy binding
+/// * `bindings` - Shared state containing active proxy bindings
+/// * `events` - The bus the pause or resume is published on
+/// * `paused` - Whether to pause the binding rather than resume it
+///
+/// # Returns
+///
+/// A result containing a JSON response or a rejection
+async fn handle_pause_binding(
+    port: u16,
+    bindings: BindingMap,
+    events: EventBus,
+    paused: bool,
+) -> std::result::Result<impl Reply, Rejection> {
+    let action = if paused { "Pausing" } else { "Resuming" };
+    info!("{} proxy binding on port {}", action, port);
+
+    let bindings_lock = bindings.lock().await;
+    if let Some(binding) = bindings_lock.get(&port) {
+        binding.set_paused(paused);
+        let kind = if paused {
+            BindingEventKind::Paused
+        } else {
+            BindingEventKind::Resumed
+        };
+        events.publish(kind, port, binding.state.upstream.load().to_string());
+
+        Ok(warp::reply::json(&PauseBindingResponse {
+            status: if paused { "paused" } else { "resumed" },
+            port,
+            active_connections: binding.state.connections.len(),
+        }))
+    } else {
+        warn!("No binding found for port {} during pause or resume", port);
+        Err(warp::reject::custom(BindingNotFound(port)))
     }
 }
 
+/// Handle proxy binding stats requests
+///
+/// This function reports the traffic counters of an existing proxy binding
+/// along with the state of its upstreams.
+///
+/// # Arguments
+///
+/// * `port` - The port number for the proxy binding
+/// * `bindings` - Shared state containing active proxy bindings
+///
+/// # Returns
+///
+/// A result containing a JSON response or a rejection
+async fn handle_binding_stats(
+    port: u16,
+    bindings: BindingMap,
+) -> std::result::Result<impl Reply, Rejection> {
+    debug!("Received stats request for port {}", port);
+
+    let bindings_lock = bindings.lock().await;
+    let Some(binding) = bindings_lock.get(&port) else {
+        warn!("No binding found for port {} during stats", port);
+        return Err(warp::reject::custom(BindingNotFound(port)));
+    };
+
+    let upstream = binding.state.upstream.load().to_string();
+    let upstream_errors = binding.state.upstream_errors.lock().await.clone();
+    let upstreams = binding
+        .state
+        .balancer
+        .lock()
+        .await
+        .stats()
+        .map(|(target, selections, active, healthy)| UpstreamHealth {
+            url: target.url.clone(),
+            weight: target.weight,
+            selections,
+            active,
+            healthy,
+        })
+        .collect();
+    let stats = &binding.state.stats;
+
+    Ok(warp::reply::json(&BindingStatsResponse {
+        port,
+        upstream,
+        active_connections: binding.state.connections.len(),
+        total_connections: stats.total_connections(),
+        bytes_from_client: stats.bytes_from_client(),
+        bytes_from_upstream: stats.bytes_from_upstream(),
+        connect_errors: stats.connect_errors(),
+        average_connect_latency_ms: stats
+            .average_connect_latency()
+            .map(|latency| latency.as_secs_f64() * 1000.0),
+        upstream_errors,
+        connection_durations: ConnectionDurations::from(stats),
+        upstreams,
+        created_at: unix_seconds(stats.created_at()),
+        last_active_at: stats.last_active_at().map(unix_seconds),
+    }))
+}
+
+/// Handle proxy binding health check requests
+///
+/// This function probes the upstreams of an existing proxy binding right
+/// away, the way its background health checks do, and records which
+/// weighted upstreams are healthy. Bindings without `health_check` are
+/// probed with the default settings.
+///
+/// # Arguments
+///
+/// * `port` - The port number for the proxy binding
+/// * `context` - Server-wide settings and the bindings to probe
+///
+/// # Returns
+///
+/// A result containing a JSON response or a rejection
+async fn handle_binding_health_check(
+    port: u16,
+    context: Arc<ProxyContext>,
+) -> std::result::Result<impl Reply, Rejection> {
+    info!("Checking the upstreams of proxy binding on port {}", port);
+
+    let bindings_lock = context.bindings.lock().await;
+    let Some(binding) = bindings_lock.get(&port) else {
+        warn!("No binding found for port {} during health check", port);
+        return Err(warp::reject::custom(BindingNotFound(port)));
+    };
+    let balancer = binding.state.balancer.clone();
+    let check = binding.state.health_check.clone().unwrap_or_default();
+    let upstream = (binding.state.upstream_mode != UpstreamMode::Direct)
+        .then(|| binding.state.upstream.load().to_string());
+    let source = binding.connect_bind.or(context.connect_source);
+    let listeners = context.listeners.for_binding(port);
+    drop(bindings_lock);
+
+    // Weighted upstreams are probed and marked, while a single upstream
+    // has no health state to record
+    let weighted = !balancer.lock().await.targets().is_empty();
+    let upstreams = if weighted {
+        check_upstreams(
+            port,
+            &balancer,
+            &check,
+            &context.resolver,
+            source,
+            &listeners,
+        )
+        .await
+    } else if let Some(upstream) = upstream {
+        let probe_timeout = Duration::from_secs(check.timeout);
+        vec![
+            probe(
+                &upstream,
+                probe_timeout,
+                &context.resolver,
+                source,
+                &listeners,
+            )
+            .await,
+        ]
+    } else {
+        Vec::new()
+    };
+
+    Ok(warp::reply::json(&HealthCheckResponse { port, upstreams }))
+}
+
+/// Handle proxy binding capture requests
+///
+/// This function reports the bytes captured from the latest connections of
+/// a binding with `debug_capture` set.
+///
+/// # Arguments
+///
+/// * `port` - The port number for the proxy binding
+/// * `bindings` - Shared state containing active proxy bindings
+///
+/// # Returns
+///
+/// A result containing a JSON response or a rejection
+async fn handle_binding_capture(
+    port: u16,
+    bindings: BindingMap,
+) -> std::result::Result<impl Reply, Rejection> {
+    debug!("Received capture request for port {}", port);
+
+    let bindings_lock = bindings.lock().await;
+    let Some(binding) = bindings_lock.get(&port) else {
+        warn!("No binding found for port {} during capture", port);
+        return Err(warp::reject::custom(BindingNotFound(port)));
+    };
+    let Some(capture) = binding.state.capture.clone() else {
+        return Err(warp::reject::custom(CaptureDisabled(port)));
+    };
+    drop(bindings_lock);
+
+    let connections = capture
+        .snapshot()
+        .into_iter()
+        .map(|connection| CapturedConnectionResponse {
+            client: connection.client.to_string(),
+            started_at: unix_seconds(connection.started_at),
+            from_client: connection.from_client.escape_ascii().to_string(),
+            from_upstream: connection.from_upstream.escape_ascii().to_string(),
+            truncated: connection.truncated,
+        })
+        .collect();
+
+    Ok(warp::reply::json(&CaptureResponse {
+        port,
+        capture_bytes: capture.limit(),
+        connections,
+    }))
+}
+
+/// Convert a wall-clock time to Unix time in seconds
+///
+/// # Arguments
+///
+/// * `time` - The time to convert
+///
+/// # Returns
+///
+/// The seconds since the Unix epoch, or 0 for times before it
+fn unix_seconds(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|since_epoch| since_epoch.as_secs())
+        .unwrap_or(0)
+}
+
 /// Handle health check requests
 ///
 /// This function handles requests to the health check endpoint.
@@ -328,41 +2994,110 @@ async fn handle_delete_binding(
 ///
 /// # Arguments
 ///
-/// * `bindings` - Shared state containing active proxy bindings
+/// * `context` - Server-wide settings and the bindings to report on
 ///
 /// # Returns
 ///
 /// A result containing a JSON response
 async fn handle_health_request(
-    bindings: BindingMap,
+    context: Arc<ProxyContext>,
 ) -> std::result::Result<impl Reply, Infallible> {
     debug!("Received health check request");
 
-    let bindings_lock = bindings.lock().await;
+    let connection_limit = &context.connection_limit;
+    let bindings_lock = context.bindings.lock().await;
     let binding_count = bindings_lock.len();
 
-    let binding_info: Vec<Value> = bindings_lock
+    let binding_info: Vec<BindingHealth> = bindings_lock
         .iter()
         .map(|(port, binding)| {
-            let upstream = binding
-                .upstream
+            let upstream = binding.state.upstream.load().to_string();
+            let upstream_errors = binding
+                .state
+                .upstream_errors
                 .try_lock()
-                .map(|u| u.clone())
-                .unwrap_or_else(|_| "locked".to_string());
-            json!({
-                "port": port,
-                "upstream": upstream
-            })
+                .map(|errors| errors.clone())
+                .unwrap_or_default();
+            let upstream_chain = binding
+                .state
+                .via
+                .try_lock()
+                .map(|via| {
+                    if via.is_empty() {
+                        Vec::new()
+                    } else {
+                        via.iter().cloned().chain([upstream.clone()]).collect()
+                    }
+                })
+                .unwrap_or_default();
+            let (upstreams, strategy) = binding
+                .state
+                .balancer
+                .try_lock()
+                .map(|balancer| {
+                    let upstreams = balancer
+                        .stats()
+                        .map(|(target, selections, active, healthy)| UpstreamHealth {
+                            url: target.url.clone(),
+                            weight: target.weight,
+                            selections,
+                            active,
+                            healthy,
+                        })
+                        .collect();
+                    (upstreams, Some(balancer.strategy()))
+                })
+                .unwrap_or_default();
+            let tags = binding
+                .tags
+                .try_lock()
+                .map(|tags| tags.clone())
+                .unwrap_or_default();
+            BindingHealth {
+                port: *port,
+                upstream,
+                upstream_chain,
+                upstreams,
+                strategy,
+                upstream_mode: binding.state.upstream_mode,
+                active_connections: binding.state.connections.len(),
+                paused: binding.is_paused(),
+                upstream_errors,
+                connection_durations: ConnectionDurations::from(&binding.state.stats),
+                tags,
+                created_at: unix_seconds(binding.state.stats.created_at()),
+                last_active_at: binding.state.stats.last_active_at().map(unix_seconds),
+            }
         })
         .collect();
 
+    // Connections of removed bindings that are still draining hold on to
+    // their permits, so the limit gives the more accurate count when set
+    let active_connections = match connection_limit {
+        Some(limit) => limit.active(),
+        None => bindings_lock
+            .values()
+            .map(|binding| binding.state.connections.len())
+            .sum(),
+    };
+
     drop(bindings_lock);
 
     debug!("Health check found {} active bindings", binding_count);
 
-    Ok(warp::reply::json(&json!({
-        "status": "ok",
-        "active_bindings": binding_count,
-        "bindings": binding_info
-    })))
+    let max_connections = connection_limit.as_ref().map(ConnectionLimit::max);
+    Ok(warp::reply::json(&HealthResponse {
+        status: "ok",
+        active_bindings: binding_count,
+        connections: ConnectionsHealth {
+            active: active_connections,
+            max: max_connections,
+            idle_reaped: context
+                .idle_tracker
+                .as_ref()
+                .map_or(0, |tracker| tracker.reaped()),
+        },
+        load: LoadHealth::new(active_connections, max_connections),
+        bindings: binding_info,
+    }))
 }
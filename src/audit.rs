@@ -0,0 +1,184 @@
+/*!
+ * # Audit Module
+ *
+ * This module keeps a record of who changed which binding through the
+ * management API, for compliance and for troubleshooting changes nobody
+ * remembers making.
+ *
+ * Every creation, update, deletion, connection reset, pause, resume and
+ * import requested through the API is appended to an [`AuditLog`] as an
+ * [`AuditEntry`], whether it succeeded or not:
+ *
+ * ```json
+ * {"timestamp": 1700000000, "operation": "create", "port": 9000,
+ *  "upstream": "http://proxy-a:3128", "client": "10.0.0.7:51234",
+ *  "status": 200, "error": null}
+ * ```
+ *
+ * The log lives in memory only. It keeps the latest entries up to its
+ * capacity and drops the oldest ones beyond it, so memory stays bounded.
+ */
+
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// The number of entries an audit log keeps when `--audit-log-size` is not set
+pub const DEFAULT_AUDIT_ENTRIES: usize = 1024;
+
+/// A change requested through the management API
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AuditOperation {
+    /// `POST /proxy`
+    Create,
+    /// `PUT /proxy/{port}`
+    Update,
+    /// `DELETE /proxy/{port}`
+    Delete,
+    /// `POST /proxy/{port}/reset`
+    Reset,
+    /// `POST /proxy/{port}/pause`
+    Pause,
+    /// `POST /proxy/{port}/resume`
+    Resume,
+    /// `PUT /proxy/import`
+    Import,
+}
+
+/// A record of a single API operation
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct AuditEntry {
+    /// When the operation completed, in Unix seconds
+    pub timestamp: u64,
+    /// What was requested
+    pub operation: AuditOperation,
+    /// The port of the binding, if known
+    pub port: Option<u16>,
+    /// The upstream given with the request or set by it, if any
+    pub upstream: Option<String>,
+    /// The address the request came from, None over a Unix domain socket
+    pub client: Option<SocketAddr>,
+    /// The status code the request was answered with
+    pub status: u16,
+    /// Why the operation failed, if it did
+    pub error: Option<String>,
+}
+
+/// The latest API operations, shared by every route
+///
+/// Clones share the same entries.
+#[derive(Debug, Clone)]
+pub struct AuditLog {
+    /// The maximum number of entries kept
+    capacity: usize,
+    /// The entries, oldest first
+    entries: Arc<Mutex<VecDeque<AuditEntry>>>,
+}
+
+impl Default for AuditLog {
+    fn default() -> Self {
+        AuditLog::new(DEFAULT_AUDIT_ENTRIES)
+    }
+}
+
+impl AuditLog {
+    /// Create an empty audit log
+    ///
+    /// # Arguments
+    ///
+    /// * `capacity` - The maximum number of entries kept
+    ///
+    /// # Returns
+    ///
+    /// A new `AuditLog` without entries
+    pub fn new(capacity: usize) -> Self {
+        AuditLog {
+            capacity,
+            entries: Arc::new(Mutex::new(VecDeque::with_capacity(
+                capacity.min(DEFAULT_AUDIT_ENTRIES),
+            ))),
+        }
+    }
+
+    /// Get the maximum number of entries kept
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Append an entry, dropping the oldest one if the log is full
+    ///
+    /// # Arguments
+    ///
+    /// * `entry` - The entry to append
+    pub fn record(&self, entry: AuditEntry) {
+        if self.capacity == 0 {
+            return;
+        }
+        let mut entries = self.entries.lock().unwrap();
+        if entries.len() == self.capacity {
+            entries.pop_front();
+        }
+        entries.push_back(entry);
+    }
+
+    /// Copy out the entries
+    ///
+    /// # Returns
+    ///
+    /// The entries, oldest first
+    pub fn snapshot(&self) -> Vec<AuditEntry> {
+        self.entries.lock().unwrap().iter().cloned().collect()
+    }
+}
+
+/// Get the current time in Unix seconds, for [`AuditEntry::timestamp`]
+pub fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(port: u16) -> AuditEntry {
+        AuditEntry {
+            timestamp: unix_now(),
+            operation: AuditOperation::Delete,
+            port: Some(port),
+            upstream: None,
+            client: Some("127.0.0.1:5000".parse().unwrap()),
+            status: 200,
+            error: None,
+        }
+    }
+
+    #[test]
+    fn test_audit_log_is_bounded() {
+        let log = AuditLog::new(3);
+        for port in 9000..9005 {
+            log.record(entry(port));
+        }
+
+        let ports: Vec<_> = log.snapshot().iter().map(|entry| entry.port).collect();
+        assert_eq!(ports, [Some(9002), Some(9003), Some(9004)]);
+
+        // Clones share the entries
+        log.clone().record(entry(9005));
+        assert_eq!(log.snapshot().last().unwrap().port, Some(9005));
+        assert_eq!(log.snapshot().len(), log.capacity());
+    }
+
+    #[test]
+    fn test_audit_entry_serialization() {
+        let json = serde_json::to_value(entry(9000)).unwrap();
+        assert_eq!(json["operation"], "delete");
+        assert_eq!(json["client"], "127.0.0.1:5000");
+        assert_eq!(json["error"], serde_json::Value::Null);
+    }
+}
@@ -0,0 +1,522 @@
+/*!
+ * # Load Balancing Module
+ *
+ * This module distributes a binding's connections across several upstreams.
+ *
+ * Upstreams are configured through the API as a list of weighted targets:
+ *
+ * ```json
+ * [
+ *   {"url": "http://proxy-a:3128", "weight": 70},
+ *   {"url": "http://proxy-b:3128", "weight": 30}
+ * ]
+ * ```
+ *
+ * How the upstream of each new connection is picked depends on the binding's
+ * [`Strategy`]. The default, `weighted`, uses smooth weighted round-robin, which
+ * interleaves the upstreams in proportion to their weights. A weight of zero
+ * excludes an upstream without removing it from the list, whatever the strategy.
+ *
+ * Upstreams that failed their latest health check are skipped the same way
+ * until they pass one again; see the [`health`](crate::health) module.
+ */
+
+use crate::error::{Error, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use url::Url;
+
+/// The default weight of an upstream target
+fn default_weight() -> u32 {
+    1
+}
+
+/// An upstream that connections can be distributed to
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UpstreamTarget {
+    /// The upstream server address
+    pub url: String,
+    /// The relative share of connections sent to this upstream
+    #[serde(default = "default_weight")]
+    pub weight: u32,
+}
+
+/// How a balancer picks the upstream of a new connection
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Strategy {
+    /// Cycle through the upstreams in order, ignoring their weights
+    RoundRobin,
+    /// Interleave the upstreams in proportion to their weights
+    #[default]
+    Weighted,
+    /// Pick the upstream with the fewest active connections
+    LeastConnections,
+    /// Pick an upstream at random, in proportion to the weights
+    Random,
+}
+
+/// Marks a connection as active on the upstream it was sent to
+///
+/// The upstream's active connection count is decremented when this is dropped.
+#[derive(Debug)]
+pub struct ActiveConnection(Arc<AtomicUsize>);
+
+impl Drop for ActiveConnection {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// Validate a list of upstream targets
+///
+/// # Arguments
+///
+/// * `targets` - The targets to validate
+///
+/// # Returns
+///
+/// A result indicating success, or an error if a URL is invalid or no
+/// target has a positive weight
+pub fn validate_targets(targets: &[UpstreamTarget]) -> Result<()> {
+    for target in targets {
+        Url::parse(&target.url)
+            .map_err(|_| Error::Custom(format!("Invalid upstream URL: {}", target.url)))?;
+    }
+    if !targets.is_empty() && targets.iter().all(|target| target.weight == 0) {
+        return Err(Error::Custom(
+            "At least one upstream must have a positive weight".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// Selection state for a binding's upstreams
+#[derive(Debug, Default)]
+pub struct Balancer {
+    /// The upstreams to choose from
+    targets: Vec<UpstreamTarget>,
+    /// How the upstream of a new connection is picked
+    strategy: Strategy,
+    /// Smooth weighted round-robin state, one entry per target
+    current_weights: Vec<i64>,
+    /// Index of the next target for round-robin selection
+    next: usize,
+    /// State of the pseudo-random generator for random selection
+    rng: u64,
+    /// Number of times each target has been selected
+    selections: Vec<u64>,
+    /// Number of active connections to each target
+    active: Vec<Arc<AtomicUsize>>,
+    /// Whether each target passed its latest health check
+    healthy: Vec<bool>,
+}
+
+impl Balancer {
+    /// Create a balancer for a list of upstream targets
+    ///
+    /// # Arguments
+    ///
+    /// * `targets` - The upstreams to choose from; may be empty
+    /// * `strategy` - How the upstream of a new connection is picked
+    ///
+    /// # Returns
+    ///
+    /// A new `Balancer` with all selection counters at zero
+    pub fn new(targets: Vec<UpstreamTarget>, strategy: Strategy) -> Self {
+        let count = targets.len();
+        Balancer {
+            targets,
+            strategy,
+            current_weights: vec![0; count],
+            next: 0,
+            // Seed from the randomly keyed std hasher; the low bit keeps xorshift away from zero
+            rng: RandomState::new().build_hasher().finish() | 1,
+            selections: vec![0; count],
+            active: (0..count).map(|_| Arc::new(AtomicUsize::new(0))).collect(),
+            healthy: vec![true; count],
+        }
+    }
+
+    /// Get the upstream targets
+    pub fn targets(&self) -> &[UpstreamTarget] {
+        &self.targets
+    }
+
+    /// Get the selection strategy
+    pub fn strategy(&self) -> Strategy {
+        self.strategy
+    }
+
+    /// Check whether the balancer has any upstreams to choose from
+    pub fn is_empty(&self) -> bool {
+        self.targets.is_empty()
+    }
+
+    /// Record the outcome of an upstream's health check
+    ///
+    /// # Arguments
+    ///
+    /// * `url` - The upstream server address
+    /// * `healthy` - Whether the upstream passed the check
+    ///
+    /// # Returns
+    ///
+    /// True if the upstream's health changed
+    pub fn set_healthy(&mut self, url: &str, healthy: bool) -> bool {
+        let mut changed = false;
+        for (target, current) in self.targets.iter().zip(&mut self.healthy) {
+            if target.url == url && *current != healthy {
+                *current = healthy;
+                changed = true;
+            }
+        }
+        changed
+    }
+
+    /// Check whether every enabled upstream failed its latest health check
+    pub fn all_unhealthy(&self) -> bool {
+        let mut enabled = self
+            .targets
+            .iter()
+            .zip(&self.healthy)
+            .filter(|(target, _)| target.weight > 0)
+            .peekable();
+        enabled.peek().is_some() && enabled.all(|(_, healthy)| !healthy)
+    }
+
+    /// Pick the upstream for a new connection, skipping unhealthy upstreams
+    ///
+    /// # Returns
+    ///
+    /// The URL of the selected upstream and a guard that counts the connection as
+    /// active until it is dropped, or `None` if no healthy target has a positive weight
+    pub fn select(&mut self) -> Option<(String, ActiveConnection)> {
+        self.select_with(false)
+    }
+
+    /// Pick the upstream for a new connection as if every upstream were healthy
+    ///
+    /// # Returns
+    ///
+    /// The URL of the selected upstream and a guard that counts the connection as
+    /// active until it is dropped, or `None` if no target has a positive weight
+    pub fn select_ignoring_health(&mut self) -> Option<(String, ActiveConnection)> {
+        self.select_with(true)
+    }
+
+    /// Pick the upstream for a new connection with the binding's strategy
+    fn select_with(&mut self, ignore_health: bool) -> Option<(String, ActiveConnection)> {
+        let weights = self.weights(ignore_health);
+        let index = match self.strategy {
+            Strategy::RoundRobin => self.select_round_robin(&weights),
+            Strategy::Weighted => self.select_weighted(&weights),
+            Strategy::LeastConnections => self.select_least_connections(&weights),
+            Strategy::Random => self.select_random(&weights),
+        }?;
+
+        Some(self.take(index))
+    }
+
+    /// Get the weight of each target, zero for unhealthy ones unless health is ignored
+    fn weights(&self, ignore_health: bool) -> Vec<u32> {
+        self.targets
+            .iter()
+            .zip(&self.healthy)
+            .map(|(target, &healthy)| {
+                if healthy || ignore_health {
+                    target.weight
+                } else {
+                    0
+                }
+            })
+            .collect()
+    }
+
+    /// Pick the next upstream after the one a request failed on, to retry it
+    ///
+    /// # Arguments
+    ///
+    /// * `failed` - The URL of the upstream the request failed on
+    ///
+    /// # Returns
+    ///
+    /// The URL of the next enabled and healthy upstream in the list and a guard
+    /// that counts the connection as active until it is dropped, or `None` if no
+    /// other upstream is enabled and healthy
+    pub fn select_after(&mut self, failed: &str) -> Option<(String, ActiveConnection)> {
+        let weights = self.weights(false);
+        let count = self.targets.len();
+        let start = self
+            .targets
+            .iter()
+            .position(|target| target.url == failed)
+            .map_or(0, |index| index + 1);
+        let index = (0..count)
+            .map(|offset| (start + offset) % count)
+            .find(|&index| weights[index] > 0 && self.targets[index].url != failed)?;
+
+        Some(self.take(index))
+    }
+
+    /// Count a connection to a target as selected and active
+    fn take(&mut self, index: usize) -> (String, ActiveConnection) {
+        self.selections[index] += 1;
+        let active = self.active[index].clone();
+        active.fetch_add(1, Ordering::Relaxed);
+        (self.targets[index].url.clone(), ActiveConnection(active))
+    }
+
+    /// Pick the next enabled target after the previously selected one
+    fn select_round_robin(&mut self, weights: &[u32]) -> Option<usize> {
+        let count = self.targets.len();
+        let index = (0..count)
+            .map(|offset| (self.next + offset) % count)
+            .find(|&index| weights[index] > 0)?;
+        self.next = index + 1;
+        Some(index)
+    }
+
+    /// Pick a target with smooth weighted round-robin
+    fn select_weighted(&mut self, weights: &[u32]) -> Option<usize> {
+        let mut total = 0i64;
+        let mut best: Option<usize> = None;
+
+        for (index, &weight) in weights.iter().enumerate() {
+            if weight == 0 {
+                continue;
+            }
+            let weight = i64::from(weight);
+            self.current_weights[index] += weight;
+            total += weight;
+            if best.is_none_or(|best| self.current_weights[index] > self.current_weights[best]) {
+                best = Some(index);
+            }
+        }
+
+        let best = best?;
+        self.current_weights[best] -= total;
+        Some(best)
+    }
+
+    /// Pick the enabled target with the fewest active connections
+    ///
+    /// Ties go to the target that has been selected the least.
+    fn select_least_connections(&mut self, weights: &[u32]) -> Option<usize> {
+        (0..self.targets.len())
+            .filter(|&index| weights[index] > 0)
+            .min_by_key(|&index| {
+                (
+                    self.active[index].load(Ordering::Relaxed),
+                    self.selections[index],
+                )
+            })
+    }
+
+    /// Pick a target at random, in proportion to the weights
+    fn select_random(&mut self, weights: &[u32]) -> Option<usize> {
+        let total: u64 = weights.iter().map(|&weight| u64::from(weight)).sum();
+        if total == 0 {
+            return None;
+        }
+
+        // xorshift64
+        self.rng ^= self.rng << 13;
+        self.rng ^= self.rng >> 7;
+        self.rng ^= self.rng << 17;
+
+        let mut point = self.rng % total;
+        weights.iter().position(|&weight| {
+            let weight = u64::from(weight);
+            if point < weight {
+                true
+            } else {
+                point -= weight;
+                false
+            }
+        })
+    }
+
+    /// Get the selection and active connection counts and the health of each upstream
+    ///
+    /// # Returns
+    ///
+    /// Tuples of upstream targets, their selection counts, their active connection
+    /// counts and whether they passed their latest health check
+    pub fn stats(&self) -> impl Iterator<Item = (&UpstreamTarget, u64, usize, bool)> {
+        self.targets
+            .iter()
+            .zip(&self.selections)
+            .zip(&self.active)
+            .zip(&self.healthy)
+            .map(|(((target, selections), active), healthy)| {
+                (
+                    target,
+                    *selections,
+                    active.load(Ordering::Relaxed),
+                    *healthy,
+                )
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn target(url: &str, weight: u32) -> UpstreamTarget {
+        UpstreamTarget {
+            url: url.to_string(),
+            weight,
+        }
+    }
+
+    #[test]
+    fn test_weighted_distribution() {
+        let mut balancer = Balancer::new(
+            vec![target("http://a:3128", 7), target("http://b:3128", 3)],
+            Strategy::Weighted,
+        );
+
+        let picks: Vec<String> = (0..10)
+            .filter_map(|_| balancer.select().map(|(url, _)| url))
+            .collect();
+        assert_eq!(
+            picks.iter().filter(|url| *url == "http://a:3128").count(),
+            7
+        );
+        assert_eq!(
+            picks.iter().filter(|url| *url == "http://b:3128").count(),
+            3
+        );
+
+        // Smooth weighted round-robin interleaves the upstreams
+        assert_ne!(
+            picks[..3],
+            ["http://a:3128", "http://a:3128", "http://a:3128"]
+        );
+    }
+
+    #[test]
+    fn test_zero_weight_is_excluded() {
+        let mut balancer = Balancer::new(
+            vec![target("http://a:3128", 0), target("http://b:3128", 1)],
+            Strategy::Weighted,
+        );
+        for _ in 0..5 {
+            assert_eq!(
+                balancer.select().map(|(url, _)| url).as_deref(),
+                Some("http://b:3128")
+            );
+        }
+        let counts: Vec<u64> = balancer.stats().map(|(_, count, _, _)| count).collect();
+        assert_eq!(counts, vec![0, 5]);
+    }
+
+    #[test]
+    fn test_round_robin_ignores_weights() {
+        let mut balancer = Balancer::new(
+            vec![
+                target("http://a:3128", 5),
+                target("http://b:3128", 0),
+                target("http://c:3128", 1),
+            ],
+            Strategy::RoundRobin,
+        );
+        let picks: Vec<String> = (0..4)
+            .filter_map(|_| balancer.select().map(|(url, _)| url))
+            .collect();
+        assert_eq!(
+            picks,
+            [
+                "http://a:3128",
+                "http://c:3128",
+                "http://a:3128",
+                "http://c:3128"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_least_connections_tracks_active_connections() {
+        let mut balancer = Balancer::new(
+            vec![target("http://a:3128", 1), target("http://b:3128", 1)],
+            Strategy::LeastConnections,
+        );
+
+        let (first, first_guard) = balancer.select().unwrap();
+        let (second, _second_guard) = balancer.select().unwrap();
+        assert_ne!(first, second);
+
+        // Closing the first connection makes its upstream the least loaded
+        drop(first_guard);
+        let (third, _third_guard) = balancer.select().unwrap();
+        assert_eq!(third, first);
+
+        let active: Vec<usize> = balancer.stats().map(|(_, _, active, _)| active).collect();
+        assert_eq!(active, vec![1, 1]);
+    }
+
+    #[test]
+    fn test_random_skips_zero_weight() {
+        let mut balancer = Balancer::new(
+            vec![target("http://a:3128", 0), target("http://b:3128", 2)],
+            Strategy::Random,
+        );
+        for _ in 0..20 {
+            assert_eq!(balancer.select().unwrap().0, "http://b:3128");
+        }
+        assert!(
+            Balancer::new(vec![target("http://a:3128", 0)], Strategy::Random)
+                .select()
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn test_select_after_skips_failed_upstream() {
+        let mut balancer = Balancer::new(
+            vec![
+                target("http://a:3128", 1),
+                target("http://b:3128", 0),
+                target("http://c:3128", 1),
+            ],
+            Strategy::Weighted,
+        );
+
+        let (next, _guard) = balancer.select_after("http://a:3128").unwrap();
+        assert_eq!(next, "http://c:3128");
+        let (next, _guard) = balancer.select_after("http://c:3128").unwrap();
+        assert_eq!(next, "http://a:3128");
+
+        let active: Vec<usize> = balancer.stats().map(|(_, _, active, _)| active).collect();
+        assert_eq!(active, vec![1, 0, 1]);
+
+        let mut single = Balancer::new(vec![target("http://a:3128", 1)], Strategy::Weighted);
+        assert!(single.select_after("http://a:3128").is_none());
+    }
+
+    #[test]
+    fn test_strategy_names() {
+        let parsed: Strategy = serde_json::from_str(r#""least_connections""#).unwrap();
+        assert_eq!(parsed, Strategy::LeastConnections);
+        assert_eq!(Strategy::default(), Strategy::Weighted);
+    }
+
+    #[test]
+    fn test_validate_targets() {
+        assert!(validate_targets(&[target("http://a:3128", 1)]).is_ok());
+        assert!(validate_targets(&[target("http://a:3128", 0)]).is_err());
+        assert!(validate_targets(&[target("not a url", 1)]).is_err());
+        assert!(validate_targets(&[]).is_ok());
+    }
+
+    #[test]
+    fn test_default_weight() {
+        let parsed: UpstreamTarget = serde_json::from_str(r#"{"url": "http://a:3128"}"#).unwrap();
+        assert_eq!(parsed.weight, 1);
+    }
+}
@@ -0,0 +1,285 @@
+/*!
+ * # Capture Module
+ *
+ * This module records the first bytes each side of a binding's connections
+ * sends, to troubleshoot what a client and its upstream actually exchange.
+ *
+ * Capturing is off unless a binding sets `debug_capture`, since the recorded
+ * traffic may contain credentials, cookies and other private data. Memory
+ * stays bounded: a [`CaptureBuffer`] keeps at most `capture_bytes` bytes per
+ * direction, itself at most [`MAX_CAPTURE_BYTES`], for only the latest
+ * [`CAPTURED_CONNECTIONS`] connections.
+ *
+ * Bytes are recorded as seen on the client side of the connection: what the
+ * client sent, and what it was sent back after any response header rules.
+ */
+
+use std::collections::VecDeque;
+use std::io;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::SystemTime;
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+/// The number of bytes captured per direction when a binding does not set `capture_bytes`
+pub const DEFAULT_CAPTURE_BYTES: usize = 4096;
+
+/// The largest `capture_bytes` a binding may set
+pub const MAX_CAPTURE_BYTES: usize = 64 * 1024;
+
+/// The number of most recent connections whose capture is kept
+pub const CAPTURED_CONNECTIONS: usize = 16;
+
+/// The captures of a binding's most recent connections
+#[derive(Debug)]
+pub struct CaptureBuffer {
+    /// The maximum number of bytes captured per direction of a connection
+    limit: usize,
+    /// The captured connections, oldest first
+    connections: Mutex<VecDeque<Arc<ConnectionCapture>>>,
+}
+
+impl CaptureBuffer {
+    /// Create an empty capture buffer
+    ///
+    /// # Arguments
+    ///
+    /// * `limit` - The maximum number of bytes captured per direction of a connection
+    ///
+    /// # Returns
+    ///
+    /// A new `CaptureBuffer` without connections
+    pub fn new(limit: usize) -> Self {
+        CaptureBuffer {
+            limit: limit.min(MAX_CAPTURE_BYTES),
+            connections: Mutex::new(VecDeque::with_capacity(CAPTURED_CONNECTIONS)),
+        }
+    }
+
+    /// Get the maximum number of bytes captured per direction of a connection
+    pub fn limit(&self) -> usize {
+        self.limit
+    }
+
+    /// Start capturing a connection, dropping the oldest capture if the buffer is full
+    ///
+    /// # Arguments
+    ///
+    /// * `client` - The address of the connection's client
+    ///
+    /// # Returns
+    ///
+    /// The capture the connection's bytes are recorded into
+    pub fn start(&self, client: SocketAddr) -> Arc<ConnectionCapture> {
+        let capture = Arc::new(ConnectionCapture {
+            client,
+            started_at: SystemTime::now(),
+            limit: self.limit,
+            from_client: Mutex::new(Vec::new()),
+            from_upstream: Mutex::new(Vec::new()),
+            truncated: AtomicBool::new(false),
+        });
+        let mut connections = self.connections.lock().unwrap();
+        if connections.len() == CAPTURED_CONNECTIONS {
+            connections.pop_front();
+        }
+        connections.push_back(capture.clone());
+        capture
+    }
+
+    /// Copy out the captures of the most recent connections
+    ///
+    /// # Returns
+    ///
+    /// The captured connections, oldest first
+    pub fn snapshot(&self) -> Vec<CapturedConnection> {
+        let connections = self.connections.lock().unwrap();
+        connections
+            .iter()
+            .map(|capture| CapturedConnection {
+                client: capture.client,
+                started_at: capture.started_at,
+                from_client: capture.from_client.lock().unwrap().clone(),
+                from_upstream: capture.from_upstream.lock().unwrap().clone(),
+                truncated: capture.truncated.load(Ordering::Relaxed),
+            })
+            .collect()
+    }
+}
+
+/// The bytes captured from a single connection, while it is running
+#[derive(Debug)]
+pub struct ConnectionCapture {
+    /// The address of the connection's client
+    client: SocketAddr,
+    /// When the connection was accepted
+    started_at: SystemTime,
+    /// The maximum number of bytes captured per direction
+    limit: usize,
+    /// The first bytes the client sent
+    from_client: Mutex<Vec<u8>>,
+    /// The first bytes sent back to the client
+    from_upstream: Mutex<Vec<u8>>,
+    /// Whether either direction carried more bytes than were captured
+    truncated: AtomicBool,
+}
+
+impl ConnectionCapture {
+    /// Record bytes into one direction, up to the capture limit
+    ///
+    /// # Arguments
+    ///
+    /// * `from_client` - Whether the bytes were sent by the client rather than to it
+    /// * `bytes` - The bytes to record
+    pub fn record(&self, from_client: bool, bytes: &[u8]) {
+        let buffer = if from_client {
+            &self.from_client
+        } else {
+            &self.from_upstream
+        };
+        let mut buffer = buffer.lock().unwrap();
+        let room = self.limit - buffer.len();
+        if bytes.len() > room {
+            self.truncated.store(true, Ordering::Relaxed);
+        }
+        buffer.extend_from_slice(&bytes[..bytes.len().min(room)]);
+    }
+}
+
+/// A copy of the bytes captured from a connection
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CapturedConnection {
+    /// The address of the connection's client
+    pub client: SocketAddr,
+    /// When the connection was accepted
+    pub started_at: SystemTime,
+    /// The first bytes the client sent
+    pub from_client: Vec<u8>,
+    /// The first bytes sent back to the client
+    pub from_upstream: Vec<u8>,
+    /// Whether either direction carried more bytes than were captured
+    pub truncated: bool,
+}
+
+/// A client stream that records the bytes read from and written to it
+///
+/// Without a capture, reads and writes pass through unchanged.
+#[derive(Debug)]
+pub struct CaptureStream<S> {
+    /// The wrapped client stream
+    inner: S,
+    /// The capture the bytes are recorded into, if capturing is enabled
+    capture: Option<Arc<ConnectionCapture>>,
+}
+
+impl<S> CaptureStream<S> {
+    /// Wrap a client stream
+    ///
+    /// # Arguments
+    ///
+    /// * `inner` - The client stream
+    /// * `capture` - The capture the bytes are recorded into, or `None` to record nothing
+    ///
+    /// # Returns
+    ///
+    /// A new `CaptureStream` over `inner`
+    pub fn new(inner: S, capture: Option<Arc<ConnectionCapture>>) -> Self {
+        CaptureStream { inner, capture }
+    }
+
+    /// Get the wrapped client stream
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+}
+
+impl<S: AsyncRead + Unpin> AsyncRead for CaptureStream<S> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        let before = buf.filled().len();
+        let result = Pin::new(&mut this.inner).poll_read(cx, buf);
+        if let (Poll::Ready(Ok(())), Some(capture)) = (&result, &this.capture) {
+            capture.record(true, &buf.filled()[before..]);
+        }
+        result
+    }
+}
+
+impl<S: AsyncWrite + Unpin> AsyncWrite for CaptureStream<S> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        let result = Pin::new(&mut this.inner).poll_write(cx, buf);
+        if let (Poll::Ready(Ok(written)), Some(capture)) = (&result, &this.capture) {
+            capture.record(false, &buf[..*written]);
+        }
+        result
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_shutdown(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    #[test]
+    fn test_capture_is_bounded() {
+        let buffer = CaptureBuffer::new(4);
+        let client: SocketAddr = "127.0.0.1:5000".parse().unwrap();
+        for _ in 0..CAPTURED_CONNECTIONS + 2 {
+            buffer.start(client);
+        }
+        let capture = buffer.start(client);
+        capture.record(true, b"GET");
+        capture.record(true, b" / HTTP/1.1");
+        capture.record(false, b"HTTP");
+
+        let connections = buffer.snapshot();
+        assert_eq!(connections.len(), CAPTURED_CONNECTIONS);
+        let last = connections.last().unwrap();
+        assert_eq!(last.from_client, b"GET ");
+        assert_eq!(last.from_upstream, b"HTTP");
+        assert!(last.truncated);
+        assert!(!connections[0].truncated);
+
+        assert_eq!(CaptureBuffer::new(usize::MAX).limit(), MAX_CAPTURE_BYTES);
+    }
+
+    #[tokio::test]
+    async fn test_capture_stream_records_both_directions() {
+        let (client, mut peer) = tokio::io::duplex(64);
+        let buffer = CaptureBuffer::new(DEFAULT_CAPTURE_BYTES);
+        let capture = buffer.start("127.0.0.1:5000".parse().unwrap());
+        let mut stream = CaptureStream::new(client, Some(capture));
+
+        peer.write_all(b"ping").await.unwrap();
+        let mut buf = [0u8; 4];
+        stream.read_exact(&mut buf).await.unwrap();
+        stream.write_all(b"pong").await.unwrap();
+        peer.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"pong");
+
+        let connections = buffer.snapshot();
+        assert_eq!(connections[0].from_client, b"ping");
+        assert_eq!(connections[0].from_upstream, b"pong");
+        assert!(!connections[0].truncated);
+    }
+}
@@ -0,0 +1,406 @@
+/*!
+ * # Response Compression Module
+ *
+ * This module gzip-compresses plain HTTP responses on their way to the
+ * client, for bindings with `compress_responses` set, to save bandwidth for
+ * clients on slow or metered links.
+ *
+ * A response is compressed when:
+ *
+ * 1. The client's `Accept-Encoding` accepts gzip ([`accepts_gzip`]).
+ * 2. The response has a body and is not a partial (`206`) response.
+ * 3. The upstream did not encode the body already, i.e. it has no
+ *    `Content-Encoding` other than `identity`.
+ * 4. Its `Content-Type` is text-like, such as `text/html`,
+ *    `application/json` or `image/svg+xml`. Event streams are left alone
+ *    so their events are not held back.
+ * 5. It does not carry `Cache-Control: no-transform`.
+ *
+ * The upstream body is decoded from its `Content-Length` or chunked framing,
+ * compressed as it arrives, and sent to the client chunked, or delimited by
+ * closing the connection for HTTP/1.0 clients and responses. Every block read from the upstream is
+ * flushed through the compressor right away, so streamed responses keep
+ * streaming. The connection is closed after a compressed response.
+ */
+
+use crate::framing::{request_body_framing, BodyDecoder, BodyFraming};
+use crate::headers::{rewrite_head, HeaderRule};
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use std::io::{self, Write};
+
+/// The maximum number of headers parsed from a response head
+const MAX_HEADERS: usize = 100;
+
+/// Check whether a client accepts gzip-encoded responses
+///
+/// # Arguments
+///
+/// * `headers` - The request headers
+///
+/// # Returns
+///
+/// `true` if `Accept-Encoding` lists `gzip`, `x-gzip` or `*` with a non-zero
+/// quality, and does not refuse gzip explicitly
+pub fn accepts_gzip(headers: &[httparse::Header]) -> bool {
+    let mut gzip = None;
+    let mut wildcard = None;
+    let codings = headers
+        .iter()
+        .filter(|header| header.name.eq_ignore_ascii_case("accept-encoding"))
+        .filter_map(|header| std::str::from_utf8(header.value).ok())
+        .flat_map(|value| value.split(','));
+    for coding in codings {
+        let mut params = coding.split(';');
+        let name = params.next().unwrap_or("").trim().to_ascii_lowercase();
+        let accepted = params
+            .filter_map(|param| param.trim().strip_prefix("q="))
+            .all(|quality| quality.trim().parse::<f32>().is_ok_and(|q| q > 0.0));
+        match name.as_str() {
+            "gzip" | "x-gzip" => gzip = Some(accepted),
+            "*" => wildcard = Some(accepted),
+            _ => {}
+        }
+    }
+    gzip.or(wildcard).unwrap_or(false)
+}
+
+/// Check whether a media type is worth compressing
+///
+/// # Arguments
+///
+/// * `content_type` - The `Content-Type` value, with or without parameters
+///
+/// # Returns
+///
+/// `true` for text and text-like types
+fn is_compressible_type(content_type: &str) -> bool {
+    let essence = content_type
+        .split(';')
+        .next()
+        .unwrap_or("")
+        .trim()
+        .to_ascii_lowercase();
+    match essence.split_once('/') {
+        Some(("text", "event-stream")) => false,
+        Some(("text", _)) => true,
+        Some(("application", subtype)) => {
+            matches!(
+                subtype,
+                "json" | "javascript" | "x-javascript" | "ecmascript" | "xml" | "xhtml+xml"
+            ) || subtype.ends_with("+json")
+                || subtype.ends_with("+xml")
+        }
+        Some(("image", "svg+xml")) => true,
+        _ => false,
+    }
+}
+
+/// A response body being gzip-compressed for the client
+pub struct GzipResponse {
+    /// Tracks the end of the upstream body; None if it ends when the upstream closes
+    decoder: Option<BodyDecoder>,
+    /// Whether the compressed body is sent chunked rather than ended by closing
+    chunked: bool,
+    /// Compresses the body data into its buffer
+    encoder: GzEncoder<Vec<u8>>,
+    /// The response head with its headers adjusted for the compressed body
+    head: Vec<u8>,
+}
+
+impl GzipResponse {
+    /// Prepare the compression of a response, if it is eligible
+    ///
+    /// # Arguments
+    ///
+    /// * `head` - The final response head, as it would be sent to the client
+    /// * `http10_client` - Whether the client sent an HTTP/1.0 request, and
+    ///   so cannot decode a chunked body
+    ///
+    /// # Returns
+    ///
+    /// The compressor for the response body, or None if the response is to
+    /// be relayed as it is
+    pub fn new(head: &[u8], http10_client: bool) -> Option<Self> {
+        let mut headers = [httparse::EMPTY_HEADER; MAX_HEADERS];
+        let mut response = httparse::Response::new(&mut headers);
+        if !response.parse(head).ok()?.is_complete() {
+            return None;
+        }
+        let status = response.code?;
+        if (100..200).contains(&status) || matches!(status, 204 | 206 | 304) {
+            return None;
+        }
+
+        let header = |name: &str| {
+            response
+                .headers
+                .iter()
+                .find(|header| header.name.eq_ignore_ascii_case(name))
+                .and_then(|header| std::str::from_utf8(header.value).ok())
+                .map(str::trim)
+        };
+        if header("content-encoding").is_some_and(|coding| !coding.eq_ignore_ascii_case("identity"))
+            || header("content-range").is_some()
+            || !header("content-type").is_some_and(is_compressible_type)
+        {
+            return None;
+        }
+        let no_transform = response
+            .headers
+            .iter()
+            .filter(|header| header.name.eq_ignore_ascii_case("cache-control"))
+            .filter_map(|header| std::str::from_utf8(header.value).ok())
+            .flat_map(|value| value.split(','))
+            .any(|directive| directive.trim().eq_ignore_ascii_case("no-transform"));
+        if no_transform {
+            return None;
+        }
+
+        // A response without Content-Length or Transfer-Encoding ends when
+        // the upstream closes the connection
+        let decoder = match request_body_framing(response.headers).ok()? {
+            BodyFraming::Length(0) => return None,
+            BodyFraming::Empty => None,
+            framing => Some(BodyDecoder::new(framing)),
+        };
+        let chunked = response.version == Some(1) && !http10_client;
+
+        // The compressed body is a different representation, so a strong
+        // validator no longer applies to it
+        let etag = header("etag")
+            .filter(|etag| !etag.starts_with("W/"))
+            .map(|etag| format!("W/{}", etag));
+        let mut rules = vec![
+            HeaderRule::Remove {
+                name: "Content-Length".to_string(),
+            },
+            HeaderRule::Remove {
+                name: "Transfer-Encoding".to_string(),
+            },
+            HeaderRule::Set {
+                name: "Content-Encoding".to_string(),
+                value: "gzip".to_string(),
+            },
+            HeaderRule::Add {
+                name: "Vary".to_string(),
+                value: "Accept-Encoding".to_string(),
+            },
+            HeaderRule::Set {
+                name: "Connection".to_string(),
+                value: "close".to_string(),
+            },
+        ];
+        if let Some(etag) = etag {
+            rules.push(HeaderRule::Set {
+                name: "ETag".to_string(),
+                value: etag,
+            });
+        }
+        if chunked {
+            rules.push(HeaderRule::Set {
+                name: "Transfer-Encoding".to_string(),
+                value: "chunked".to_string(),
+            });
+        }
+        let head = rewrite_head(head, &rules).ok()?;
+
+        Some(GzipResponse {
+            decoder,
+            chunked,
+            encoder: GzEncoder::new(Vec::new(), Compression::default()),
+            head,
+        })
+    }
+
+    /// Get the response head to send to the client in place of the upstream's
+    pub fn head(&self) -> &[u8] {
+        &self.head
+    }
+
+    /// Check whether the whole upstream body has been compressed
+    ///
+    /// A body that ends when the upstream closes is never complete before then.
+    pub fn is_complete(&self) -> bool {
+        self.decoder
+            .as_ref()
+            .is_some_and(|decoder| decoder.is_complete())
+    }
+
+    /// Compress the next bytes of the upstream body
+    ///
+    /// # Arguments
+    ///
+    /// * `bytes` - The next bytes from the upstream, in the body's framing
+    ///
+    /// # Returns
+    ///
+    /// A result containing the bytes to send to the client, or an error if
+    /// the body's chunk framing is malformed
+    pub fn compress(&mut self, bytes: &[u8]) -> io::Result<Vec<u8>> {
+        match &mut self.decoder {
+            Some(decoder) => {
+                let mut data = Vec::with_capacity(bytes.len());
+                decoder.decode(bytes, &mut data)?;
+                self.encoder.write_all(&data)?;
+            }
+            None => self.encoder.write_all(bytes)?,
+        }
+        self.encoder.flush()?;
+        let compressed = std::mem::take(self.encoder.get_mut());
+        Ok(frame(self.chunked, compressed))
+    }
+
+    /// Finish the compressed body once the upstream body is complete or the
+    /// upstream has closed the connection
+    ///
+    /// # Returns
+    ///
+    /// A result containing the last bytes to send to the client, or an
+    /// `UnexpectedEof` error if the upstream closed before the end of a body
+    /// with `Content-Length` or chunked framing
+    pub fn finish(self) -> io::Result<Vec<u8>> {
+        if self.decoder.is_some() && !self.is_complete() {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "upstream closed before the end of the response body",
+            ));
+        }
+        let mut last = frame(self.chunked, self.encoder.finish()?);
+        if self.chunked {
+            last.extend_from_slice(b"0\r\n\r\n");
+        }
+        Ok(last)
+    }
+}
+
+/// Wrap compressed bytes in a chunk if the body is sent chunked
+///
+/// # Arguments
+///
+/// * `chunked` - Whether the body is sent chunked
+/// * `bytes` - The compressed bytes
+///
+/// # Returns
+///
+/// The bytes to send; empty bytes never make a chunk, which would end the body
+fn frame(chunked: bool, bytes: Vec<u8>) -> Vec<u8> {
+    if !chunked || bytes.is_empty() {
+        return bytes;
+    }
+    let mut chunk = format!("{:x}\r\n", bytes.len()).into_bytes();
+    chunk.extend_from_slice(&bytes);
+    chunk.extend_from_slice(b"\r\n");
+    chunk
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::read::GzDecoder;
+    use std::io::Read;
+
+    fn accepts(value: &str) -> bool {
+        accepts_gzip(&[httparse::Header {
+            name: "Accept-Encoding",
+            value: value.as_bytes(),
+        }])
+    }
+
+    /// Compress a response, returning the head sent to the client and the decompressed body
+    fn roundtrip(head: &str, body: &[u8]) -> (String, Vec<u8>) {
+        let mut gzip = GzipResponse::new(head.as_bytes(), false).expect("compressible");
+        let sent_head = String::from_utf8(gzip.head().to_vec()).unwrap();
+        let mut sent = Vec::new();
+        for piece in body.chunks(7) {
+            sent.extend(gzip.compress(piece).unwrap());
+        }
+        assert!(gzip.is_complete());
+        sent.extend(gzip.finish().unwrap());
+
+        // Undo the chunking, then the compression
+        let mut decoder = BodyDecoder::new(BodyFraming::Chunked);
+        let mut compressed = Vec::new();
+        decoder.decode(&sent, &mut compressed).unwrap();
+        assert!(decoder.is_complete());
+        let mut decompressed = Vec::new();
+        GzDecoder::new(&compressed[..])
+            .read_to_end(&mut decompressed)
+            .unwrap();
+        (sent_head, decompressed)
+    }
+
+    #[test]
+    fn test_accepts_gzip() {
+        assert!(accepts("gzip, deflate, br"));
+        assert!(accepts("br;q=1.0, GZIP;q=0.5"));
+        assert!(accepts("*"));
+        assert!(!accepts("gzip;q=0"));
+        assert!(!accepts("gzip;q=0.000, *"));
+        assert!(!accepts("deflate, br"));
+        assert!(!accepts("identity"));
+        assert!(!accepts_gzip(&[]));
+    }
+
+    #[test]
+    fn test_compressible_responses() {
+        for head in [
+            "HTTP/1.1 200 OK\r\nContent-Type: text/html; charset=utf-8\r\nContent-Length: 5\r\n\r\n",
+            "HTTP/1.1 404 Not Found\r\nContent-Type: application/problem+json\r\n\r\n",
+            "HTTP/1.1 200 OK\r\nContent-Type: image/svg+xml\r\nContent-Encoding: identity\r\nTransfer-Encoding: chunked\r\n\r\n",
+        ] {
+            assert!(GzipResponse::new(head.as_bytes(), false).is_some(), "{}", head);
+        }
+
+        for head in [
+            "HTTP/1.1 200 OK\r\nContent-Type: image/png\r\nContent-Length: 5\r\n\r\n",
+            "HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\n",
+            "HTTP/1.1 200 OK\r\nContent-Type: text/html\r\nContent-Encoding: br\r\n\r\n",
+            "HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\n\r\n",
+            "HTTP/1.1 200 OK\r\nContent-Type: text/html\r\nCache-Control: public, no-transform\r\n\r\n",
+            "HTTP/1.1 200 OK\r\nContent-Type: text/html\r\nContent-Length: 0\r\n\r\n",
+            "HTTP/1.1 206 Partial Content\r\nContent-Type: text/html\r\nContent-Length: 5\r\n\r\n",
+            "HTTP/1.1 304 Not Modified\r\nContent-Type: text/html\r\n\r\n",
+        ] {
+            assert!(GzipResponse::new(head.as_bytes(), false).is_none(), "{}", head);
+        }
+    }
+
+    #[test]
+    fn test_compress_length_body() {
+        let body = "hello, compressed world! ".repeat(20);
+        let head = format!(
+            "HTTP/1.1 200 OK\r\nContent-Type: text/plain\r\nContent-Length: {}\r\nETag: \"v1\"\r\n\r\n",
+            body.len()
+        );
+        let (head, decompressed) = roundtrip(&head, body.as_bytes());
+
+        assert!(head.contains("Content-Encoding: gzip\r\n"));
+        assert!(head.contains("Transfer-Encoding: chunked\r\n"));
+        assert!(head.contains("Vary: Accept-Encoding\r\n"));
+        assert!(head.contains("Connection: close\r\n"));
+        assert!(head.contains("ETag: W/\"v1\"\r\n"));
+        assert!(!head.contains("Content-Length"));
+        assert_eq!(decompressed, body.as_bytes());
+    }
+
+    #[test]
+    fn test_compress_chunked_body() {
+        let head = "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nTransfer-Encoding: chunked\r\n\r\n";
+        let body = b"7\r\n{\"a\": 1\r\n1\r\n}\r\n0\r\n\r\n";
+        let (_, decompressed) = roundtrip(head, body);
+        assert_eq!(decompressed, b"{\"a\": 1}");
+    }
+
+    #[test]
+    fn test_truncated_body_is_an_error() {
+        let head = "HTTP/1.1 200 OK\r\nContent-Type: text/plain\r\nContent-Length: 10\r\n\r\n";
+        let mut gzip = GzipResponse::new(head.as_bytes(), false).unwrap();
+        gzip.compress(b"short").unwrap();
+        assert!(!gzip.is_complete());
+        assert_eq!(
+            gzip.finish().unwrap_err().kind(),
+            io::ErrorKind::UnexpectedEof
+        );
+    }
+}
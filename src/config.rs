@@ -2,13 +2,34 @@
  * # Configuration Module
  *
  * This module handles the configuration for the metaproxy server,
- * including command line argument parsing and validation.
+ * including command line argument parsing and validation, and loading
+ * proxy bindings from a JSON config file:
+ *
+ * ```json
+ * {
+ *   "bindings": [
+ *     {"port": 9000, "upstream": "http://127.0.0.1:8080"},
+ *     {"port": 9001, "upstream": "http://backend:8080", "upstream_mode": "reverse"}
+ *   ]
+ * }
+ * ```
  */
 
-use crate::error::Result;
-use clap::Parser;
-use std::net::SocketAddr;
+use crate::auth::REDACTED;
+use crate::dns::{check_source_address, Resolver};
+use crate::error::{Error, Result};
+use crate::proxy::{BindingSpec, ConnectionLimit, HostHeaderMode, Http10Mode, SocketOptions};
+use crate::rate_limit::RateLimiter;
+use crate::signing::RequestSigner;
+use clap::{ArgAction, Parser};
+use log::LevelFilter;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::path::Path;
+use std::sync::Arc;
 use std::time::Duration;
+use url::Url;
 
 /// Proxy server configuration
 ///
@@ -23,7 +44,7 @@ use std::time::Duration;
 /// let config = Config::from_args();
 /// println!("Binding to: {}", config.bind);
 /// ```
-#[derive(Parser, Debug, Clone)]
+#[derive(Parser, Clone)]
 #[command(version, about, long_about = None)]
 pub struct Config {
     /// Address to bind the proxy server to
@@ -33,13 +54,450 @@ pub struct Config {
     #[arg(long, default_value = "127.0.0.1:8000")]
     pub bind: String,
 
-    /// Request timeout in seconds
+    /// Host (IP address) for the management API
+    ///
+    /// Overrides the host part of `bind` when set.
+    #[arg(long)]
+    pub api_host: Option<String>,
+
+    /// Port for the management API
+    ///
+    /// Overrides the port part of `bind` when set.
+    #[arg(long)]
+    pub api_port: Option<u16>,
+
+    /// Connect timeout in seconds
     ///
-    /// If a request to the upstream server doesn't complete within this time,
-    /// it will be canceled and an error will be returned to the client.
-    /// Set to 0 for no timeout.
+    /// Bounds connecting to the upstream, including the CONNECT handshakes
+    /// with upstream proxies. A client whose upstream cannot be reached in
+    /// time gets `504 Gateway Timeout`. Set to 0 for no timeout.
     #[arg(long, default_value = "30")]
+    pub connect_timeout: u64,
+
+    /// Request timeout in seconds
+    ///
+    /// Bounds a whole plain HTTP exchange, from connecting to the upstream
+    /// until the response has been relayed. On a keep-alive connection this
+    /// bounds each request on its own. A request timing
+    /// out before any of the response reached the client gets `504 Gateway
+    /// Timeout`; one timing out later has its connection closed. CONNECT
+    /// tunnels are only bounded by the connect timeout. Set to 0 for no
+    /// timeout, the default; before `--connect-timeout` existed this flag
+    /// bounded connecting only and defaulted to 30.
+    #[arg(long, default_value = "0")]
     pub request_timeout: u64,
+
+    /// Connection drain timeout in seconds
+    ///
+    /// On shutdown, proxy listeners stop accepting connections and in-flight
+    /// connections are given this long to complete before they are cancelled.
+    /// Set to 0 to wait indefinitely.
+    #[arg(long, default_value = "30")]
+    pub drain_timeout: u64,
+
+    /// Overall shutdown timeout in seconds
+    ///
+    /// Bounds the whole shutdown: in-flight API requests are dropped and proxy
+    /// connections cancelled once this long has passed since the shutdown signal,
+    /// even if the drain timeout is longer. Set to 0 for no overall bound.
+    #[arg(long, default_value = "0")]
+    pub shutdown_timeout: u64,
+
+    /// Path of a Unix domain socket to serve the management API on
+    ///
+    /// When set, the API is served over this socket instead of the TCP `bind`
+    /// address, so access can be controlled with filesystem permissions.
+    /// A stale socket file at this path is replaced on startup and removed on shutdown;
+    /// startup fails if the path is another kind of file or a socket still in use.
+    #[arg(long)]
+    pub api_socket: Option<String>,
+
+    /// Shared secret for signing `/proxy`, `/audit` and `/events` API requests
+    ///
+    /// When set, every such request must carry an `X-Timestamp` header and an
+    /// `X-Signature` header holding the hex HMAC-SHA256 of the timestamp, method,
+    /// path and body, or it is rejected with 401.
+    #[arg(long)]
+    pub api_hmac_secret: Option<String>,
+
+    /// Maximum difference in seconds between a signed request's timestamp and the server clock
+    #[arg(long, default_value = "300")]
+    pub api_hmac_max_skew: u64,
+
+    /// Maximum number of management API requests per second
+    ///
+    /// Requests over the limit are answered with 429. Bursts of up to one
+    /// second's worth of requests are allowed. Unlimited when not set.
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+    pub api_rate_limit: Option<u32>,
+
+    /// Apply `--api-rate-limit` to each client IP address separately instead of to all clients together
+    #[arg(long)]
+    pub api_rate_limit_per_client: bool,
+
+    /// Path of a JSON file listing proxy bindings
+    ///
+    /// The bindings are created on startup, and the file is re-read and
+    /// reconciled against the active bindings when the process receives SIGHUP.
+    #[arg(long = "config")]
+    pub config_file: Option<String>,
+
+    /// Set `SO_REUSEPORT` on proxy listener sockets
+    ///
+    /// Lets another process (e.g. a new instance during a zero-downtime restart)
+    /// listen on the same binding ports. `SO_REUSEADDR` is always set.
+    #[arg(long)]
+    pub reuse_port: bool,
+
+    /// Bind proxy listeners to `127.0.0.1` instead of all interfaces
+    ///
+    /// A guardrail against accidentally exposing proxies publicly: bindings
+    /// created through the API or the config file are only reachable from
+    /// the local host.
+    #[arg(long)]
+    pub restrict_listen_loopback: bool,
+
+    /// Length of the queue of pending connections on proxy listener sockets
+    ///
+    /// Connections arriving in a burst faster than they are accepted wait in
+    /// this queue; once it is full the kernel drops them. The kernel may cap it,
+    /// e.g. at `net.core.somaxconn` on Linux.
+    #[arg(long, default_value = "1024", value_parser = clap::value_parser!(u32).range(1..=i32::MAX as i64))]
+    pub listen_backlog: u32,
+
+    /// How many bindings from the config file are created at once
+    ///
+    /// Applies at startup and when the config file is reloaded. A port that
+    /// cannot be bound does not hold back the others.
+    #[arg(long, default_value = "16", value_parser = parse_positive)]
+    pub bind_concurrency: usize,
+
+    /// Size in bytes of the buffers used to relay proxied data
+    ///
+    /// Each direction of a proxied connection gets its own buffer of this size.
+    /// Larger buffers can raise throughput of large transfers at the cost of memory.
+    #[arg(long, default_value = "8192", value_parser = parse_positive)]
+    pub copy_buffer_size: usize,
+
+    /// Maximum size in bytes of a proxied request's head (request line and headers)
+    ///
+    /// Requests with a larger head are answered with
+    /// `431 Request Header Fields Too Large`.
+    #[arg(long, default_value = "8192", value_parser = parse_positive)]
+    pub max_header_size: usize,
+
+    /// Maximum length in bytes of a proxied request's request line
+    ///
+    /// Checked as the request line arrives, before the head is parsed, so a
+    /// long request target cannot use up the whole header budget. Requests
+    /// with a longer request line are answered with `414 URI Too Long`. The
+    /// request line is only bounded by `--max-header-size` when unset.
+    #[arg(long, value_parser = parse_positive)]
+    pub max_request_line_bytes: Option<usize>,
+
+    /// Maximum size in bytes of an upstream response head read by the proxy
+    ///
+    /// Applies to the upstream's answer to CONNECT and to responses that
+    /// response header rules are applied to. Larger heads are answered with
+    /// `502 Bad Gateway`.
+    #[arg(long, default_value = "8192", value_parser = parse_positive)]
+    pub max_upstream_header_size: usize,
+
+    /// How the client's `Host` header is forwarded with absolute-form requests
+    ///
+    /// Applies to bindings in the `proxy` and `router` modes. `normalize`
+    /// replaces it with the authority of the request URL, as RFC 9112 requires;
+    /// `preserve` forwards it unchanged and `remove` drops it.
+    #[arg(long, default_value = "normalize", value_parser = parse_host_header)]
+    pub host_header: HostHeaderMode,
+
+    /// How HTTP/1.0 requests are forwarded to the upstream
+    ///
+    /// `passthrough` forwards them as HTTP/1.0; `upgrade` forwards them as
+    /// HTTP/1.1, adding a `Host` header if the client sent none. Either way
+    /// the upstream closes the connection after the response, and chunked
+    /// responses are decoded for the client.
+    #[arg(long, default_value = "passthrough", value_parser = parse_http10_requests)]
+    pub http10_requests: Http10Mode,
+
+    /// Set `TCP_NODELAY` on proxied client and upstream sockets
+    ///
+    /// Disables Nagle's algorithm so small writes, such as TLS handshakes in
+    /// CONNECT tunnels, are sent without delay. Pass `--tcp-nodelay=false` to
+    /// let the kernel coalesce small writes instead.
+    #[arg(long, default_value_t = true, action = ArgAction::Set, num_args = 0..=1, default_missing_value = "true")]
+    pub tcp_nodelay: bool,
+
+    /// Idle time in seconds before TCP keepalive probes are sent on proxied sockets
+    ///
+    /// Enables TCP keepalive on client and upstream sockets so dead peers are
+    /// detected. Keepalive is left to the system default when unset.
+    #[arg(long)]
+    pub tcp_keepalive_idle: Option<u64>,
+
+    /// Interval in seconds between TCP keepalive probes
+    ///
+    /// Only used together with `tcp_keepalive_idle`.
+    #[arg(long)]
+    pub tcp_keepalive_interval: Option<u64>,
+
+    /// Maximum number of concurrent proxied connections across all bindings
+    ///
+    /// Connections beyond the limit wait in the listen backlog until a running
+    /// connection finishes. There is no limit when unset.
+    #[arg(long, value_parser = parse_positive)]
+    pub max_global_connections: Option<usize>,
+
+    /// Number of API operations kept in the audit log served at `/audit`
+    ///
+    /// The oldest entries are dropped once the log is full.
+    #[arg(long, default_value = "1024", value_parser = parse_positive)]
+    pub audit_log_size: usize,
+
+    /// Maximum number of proxy bindings
+    ///
+    /// Creating a binding through the API fails with 507 once this many
+    /// bindings exist. Set to 0 for no limit, which is the default.
+    #[arg(long, default_value = "0")]
+    pub max_bindings: usize,
+
+    /// Idle time in seconds after which a binding is deleted automatically
+    ///
+    /// A binding is idle while it has no active connections; its idle time
+    /// counts from its last accepted connection, or from its creation if it
+    /// never accepted one. Set to 0 to keep idle bindings, which is the default.
+    #[arg(long, default_value = "0")]
+    pub idle_binding_ttl: u64,
+
+    /// Interval in seconds between scans for idle bindings
+    #[arg(long, default_value = "60", value_parser = clap::value_parser!(u64).range(1..))]
+    pub idle_scan_interval: u64,
+
+    /// Idle time in seconds after which a proxied connection is closed
+    ///
+    /// A connection is idle while no bytes go between the client and the
+    /// proxy in either direction. When set, a background reaper closes
+    /// connections idle for longer than this, e.g. CONNECT tunnels whose
+    /// peers went away. Set to 0 to keep idle connections, which is the default.
+    #[arg(long, default_value = "0")]
+    pub idle_timeout: u64,
+
+    /// Interval in seconds between scans for connections idle beyond `--idle-timeout`
+    #[arg(long, default_value = "10", value_parser = clap::value_parser!(u64).range(1..))]
+    pub idle_connection_scan_interval: u64,
+
+    /// Nameserver that upstream and direct target hosts are resolved through
+    ///
+    /// An IP address, optionally with a port (default 53), e.g. `10.0.0.2` or
+    /// `[fd00::53]:5353`. The system resolver is used when unset.
+    #[arg(long, value_parser = parse_nameserver)]
+    pub dns_server: Option<SocketAddr>,
+
+    /// Local address that upstream and direct connections are opened from
+    ///
+    /// The address must be assigned to one of the host's interfaces, e.g. to
+    /// have policy routing on a multi-homed host pick the outgoing interface.
+    /// A binding's `connect_bind` overrides it. The system picks the source
+    /// address when unset.
+    #[arg(long, value_parser = parse_connect_bind)]
+    pub connect_bind: Option<IpAddr>,
+
+    /// URL that binding events are POSTed to as JSON
+    ///
+    /// Every creation, update, deletion, pause and resume of a binding is
+    /// delivered in the background, retrying failed deliveries a few times.
+    /// Only `http://` URLs are supported.
+    #[arg(long, value_parser = parse_webhook_url)]
+    pub event_webhook: Option<Url>,
+
+    /// Log level: off, error, warn, info, debug or trace
+    ///
+    /// Takes precedence over `RUST_LOG`. Without either, the level is `info`.
+    #[arg(long)]
+    pub log_level: Option<LevelFilter>,
+}
+
+/// Parse a size or count argument that must be positive
+fn parse_positive(value: &str) -> std::result::Result<usize, String> {
+    match value.parse::<usize>() {
+        Ok(0) => Err("value must be greater than 0".to_string()),
+        Ok(size) => Ok(size),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+/// Parse how the `Host` header of absolute-form requests is forwarded
+fn parse_host_header(value: &str) -> std::result::Result<HostHeaderMode, String> {
+    match value {
+        "normalize" => Ok(HostHeaderMode::Normalize),
+        "preserve" => Ok(HostHeaderMode::Preserve),
+        "remove" => Ok(HostHeaderMode::Remove),
+        _ => Err(format!(
+            "expected one of normalize, preserve or remove: {}",
+            value
+        )),
+    }
+}
+
+/// Parse how HTTP/1.0 requests are forwarded
+fn parse_http10_requests(value: &str) -> std::result::Result<Http10Mode, String> {
+    match value {
+        "passthrough" => Ok(Http10Mode::Passthrough),
+        "upgrade" => Ok(Http10Mode::Upgrade),
+        _ => Err(format!("expected passthrough or upgrade: {}", value)),
+    }
+}
+
+/// Parse a nameserver address, defaulting to the DNS port
+fn parse_nameserver(value: &str) -> std::result::Result<SocketAddr, String> {
+    if let Ok(addr) = value.parse::<SocketAddr>() {
+        return Ok(addr);
+    }
+    value
+        .trim_start_matches('[')
+        .trim_end_matches(']')
+        .parse::<std::net::IpAddr>()
+        .map(|ip| SocketAddr::new(ip, 53))
+        .map_err(|_| format!("invalid nameserver address: {}", value))
+}
+
+/// Parse a source address for outgoing connections, which must be usable on this host
+fn parse_connect_bind(value: &str) -> std::result::Result<IpAddr, String> {
+    let source = value
+        .parse::<IpAddr>()
+        .map_err(|_| format!("invalid IP address: {}", value))?;
+    check_source_address(source).map_err(|e| e.to_string())?;
+    Ok(source)
+}
+
+/// Parse a webhook URL, which must use plain HTTP
+fn parse_webhook_url(value: &str) -> std::result::Result<Url, String> {
+    let url = Url::parse(value).map_err(|e| format!("invalid URL: {}", e))?;
+    if url.scheme() != "http" || url.host_str().is_none() {
+        return Err(format!("webhook URL must be an http:// URL: {}", value));
+    }
+    Ok(url)
+}
+
+/// The contents of a bindings config file
+///
+/// `GET /proxy/export` produces the same document, so an export can be used
+/// as a config file or imported into another instance.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BindingsFile {
+    /// The proxy bindings to run
+    #[serde(default)]
+    pub bindings: Vec<BindingSpec>,
+}
+
+impl BindingsFile {
+    /// Check that every binding has a fixed port of its own and valid settings,
+    /// and normalize the bindings
+    ///
+    /// # Returns
+    ///
+    /// A result indicating whether the bindings are valid, with a descriptive error if not
+    pub fn validate(&mut self) -> Result<()> {
+        let mut ports = HashSet::new();
+        for spec in &mut self.bindings {
+            if spec.port == 0 {
+                return Err(Error::Custom(
+                    "Every binding needs a fixed port".to_string(),
+                ));
+            }
+            if !ports.insert(spec.port) {
+                return Err(Error::Custom(format!(
+                    "Port {} is listed more than once",
+                    spec.port
+                )));
+            }
+            spec.validate().map_err(|e| {
+                Error::Custom(format!("Invalid binding on port {}: {}", spec.port, e))
+            })?;
+            spec.normalize();
+        }
+        Ok(())
+    }
+}
+
+/// Load and validate the proxy bindings listed in a config file
+///
+/// # Arguments
+///
+/// * `path` - Path of the JSON config file
+///
+/// # Returns
+///
+/// A `Result` containing the binding definitions, or an error if the file
+/// cannot be read, is not valid JSON, lists port 0 or a port twice, or has invalid rules
+pub fn load_bindings(path: impl AsRef<Path>) -> Result<Vec<BindingSpec>> {
+    let path = path.as_ref();
+    let contents = std::fs::read_to_string(path)?;
+    let invalid = |e: &dyn std::fmt::Display| {
+        Error::Custom(format!("Invalid config file {}: {}", path.display(), e))
+    };
+    let mut file: BindingsFile = serde_json::from_str(&contents).map_err(|e| invalid(&e))?;
+    file.validate().map_err(|e| invalid(&e))?;
+
+    Ok(file.bindings)
+}
+
+impl std::fmt::Debug for Config {
+    /// Format the configuration for logs, with the API's HMAC secret redacted
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Config")
+            .field("bind", &self.bind)
+            .field("api_host", &self.api_host)
+            .field("api_port", &self.api_port)
+            .field("connect_timeout", &self.connect_timeout)
+            .field("request_timeout", &self.request_timeout)
+            .field("drain_timeout", &self.drain_timeout)
+            .field("shutdown_timeout", &self.shutdown_timeout)
+            .field("api_socket", &self.api_socket)
+            .field(
+                "api_hmac_secret",
+                &self.api_hmac_secret.as_ref().map(|_| REDACTED),
+            )
+            .field("api_hmac_max_skew", &self.api_hmac_max_skew)
+            .field("api_rate_limit", &self.api_rate_limit)
+            .field("api_rate_limit_per_client", &self.api_rate_limit_per_client)
+            .field("config_file", &self.config_file)
+            .field("reuse_port", &self.reuse_port)
+            .field("restrict_listen_loopback", &self.restrict_listen_loopback)
+            .field("listen_backlog", &self.listen_backlog)
+            .field("bind_concurrency", &self.bind_concurrency)
+            .field("copy_buffer_size", &self.copy_buffer_size)
+            .field("max_header_size", &self.max_header_size)
+            .field("max_request_line_bytes", &self.max_request_line_bytes)
+            .field("max_upstream_header_size", &self.max_upstream_header_size)
+            .field("host_header", &self.host_header)
+            .field("http10_requests", &self.http10_requests)
+            .field("tcp_nodelay", &self.tcp_nodelay)
+            .field("tcp_keepalive_idle", &self.tcp_keepalive_idle)
+            .field("tcp_keepalive_interval", &self.tcp_keepalive_interval)
+            .field("max_global_connections", &self.max_global_connections)
+            .field("audit_log_size", &self.audit_log_size)
+            .field("max_bindings", &self.max_bindings)
+            .field("idle_binding_ttl", &self.idle_binding_ttl)
+            .field("idle_scan_interval", &self.idle_scan_interval)
+            .field("idle_timeout", &self.idle_timeout)
+            .field(
+                "idle_connection_scan_interval",
+                &self.idle_connection_scan_interval,
+            )
+            .field("dns_server", &self.dns_server)
+            .field("connect_bind", &self.connect_bind)
+            .field("event_webhook", &self.event_webhook)
+            .field("log_level", &self.log_level)
+            .finish()
+    }
+}
+
+impl Default for Config {
+    /// Build a configuration populated with the command line defaults
+    fn default() -> Self {
+        Config::parse_from(["metaproxy"])
+    }
 }
 
 impl Config {
@@ -57,15 +515,51 @@ impl Config {
 
     /// Get the socket address to bind to
     ///
-    /// This function parses the `bind` string into a `SocketAddr`.
+    /// This function parses the `bind` string into a `SocketAddr`. When
+    /// `api_host` or `api_port` are set they replace the corresponding part
+    /// of `bind`, and the composed address is validated.
     ///
     /// # Returns
     ///
     /// A `Result` containing the parsed `SocketAddr` or an error if parsing fails
     pub fn get_bind_addr(&self) -> Result<SocketAddr> {
-        self.bind
-            .parse()
-            .map_err(|e| format!("Invalid bind address: {}", e).into())
+        if self.api_host.is_none() && self.api_port.is_none() {
+            return self
+                .bind
+                .parse()
+                .map_err(|e| format!("Invalid bind address: {}", e).into());
+        }
+
+        let (bind_host, bind_port) = self.bind.rsplit_once(':').unwrap_or((&self.bind, ""));
+        let host = self.api_host.as_deref().unwrap_or(bind_host);
+        let port = match self.api_port {
+            Some(port) => port.to_string(),
+            None => bind_port.to_string(),
+        };
+
+        // IPv6 hosts need brackets to be combined with a port
+        let host = host.trim_start_matches('[').trim_end_matches(']');
+        let addr = if host.contains(':') {
+            format!("[{}]:{}", host, port)
+        } else {
+            format!("{}:{}", host, port)
+        };
+
+        addr.parse()
+            .map_err(|e| format!("Invalid bind address {}: {}", addr, e).into())
+    }
+
+    /// Get the connect timeout as a Duration
+    ///
+    /// # Returns
+    ///
+    /// An Option containing the timeout Duration, or None if no timeout is set
+    pub fn get_connect_timeout(&self) -> Option<Duration> {
+        if self.connect_timeout == 0 {
+            None
+        } else {
+            Some(Duration::from_secs(self.connect_timeout))
+        }
     }
 
     /// Get the request timeout as a Duration
@@ -83,6 +577,164 @@ impl Config {
             Some(Duration::from_secs(self.request_timeout))
         }
     }
+
+    /// Get the TCP options applied to proxied sockets
+    ///
+    /// # Returns
+    ///
+    /// The `SocketOptions` built from the `tcp_*` settings
+    pub fn get_socket_options(&self) -> SocketOptions {
+        SocketOptions {
+            nodelay: self.tcp_nodelay,
+            keepalive_idle: self.tcp_keepalive_idle.map(Duration::from_secs),
+            keepalive_interval: self.tcp_keepalive_interval.map(Duration::from_secs),
+        }
+    }
+
+    /// Get the overall shutdown timeout as a Duration
+    ///
+    /// # Returns
+    ///
+    /// An Option containing the shutdown timeout Duration, or None for no overall bound
+    pub fn get_shutdown_timeout(&self) -> Option<Duration> {
+        if self.shutdown_timeout == 0 {
+            None
+        } else {
+            Some(Duration::from_secs(self.shutdown_timeout))
+        }
+    }
+
+    /// Get the drain timeout left once part of the shutdown timeout has been spent
+    ///
+    /// # Arguments
+    ///
+    /// * `elapsed` - Time spent shutting down since the shutdown signal
+    ///
+    /// # Returns
+    ///
+    /// The drain timeout, shortened so the process exits within the shutdown
+    /// timeout, or None to wait indefinitely
+    pub fn get_remaining_drain_timeout(&self, elapsed: Duration) -> Option<Duration> {
+        let Some(shutdown_timeout) = self.get_shutdown_timeout() else {
+            return self.get_drain_timeout();
+        };
+        let remaining = shutdown_timeout.saturating_sub(elapsed);
+        Some(match self.get_drain_timeout() {
+            Some(drain_timeout) => drain_timeout.min(remaining),
+            None => remaining,
+        })
+    }
+
+    /// Get the resolver that upstream and direct target hosts are resolved with
+    ///
+    /// # Returns
+    ///
+    /// A `Resolver` querying `dns_server`, or the system resolver if unset
+    pub fn get_resolver(&self) -> Resolver {
+        match self.dns_server {
+            Some(addr) => Resolver::nameserver(addr),
+            None => Resolver::System,
+        }
+    }
+
+    /// Get the server-wide connection limit
+    ///
+    /// Every call creates a separate limit, so it should be called once and the
+    /// result shared between all bindings.
+    ///
+    /// # Returns
+    ///
+    /// A `ConnectionLimit` for `max_global_connections`, or None if unlimited
+    pub fn get_connection_limit(&self) -> Option<ConnectionLimit> {
+        self.max_global_connections.map(ConnectionLimit::new)
+    }
+
+    /// Get the address proxy listeners bind
+    ///
+    /// # Returns
+    ///
+    /// `127.0.0.1` if `restrict_listen_loopback` is set, or else `0.0.0.0`
+    pub fn get_listen_ip(&self) -> IpAddr {
+        if self.restrict_listen_loopback {
+            IpAddr::V4(Ipv4Addr::LOCALHOST)
+        } else {
+            IpAddr::V4(Ipv4Addr::UNSPECIFIED)
+        }
+    }
+
+    /// Get the maximum number of proxy bindings
+    ///
+    /// # Returns
+    ///
+    /// The limit, or None if the number of bindings is unlimited
+    pub fn get_max_bindings(&self) -> Option<usize> {
+        (self.max_bindings > 0).then_some(self.max_bindings)
+    }
+
+    /// Get the idle time after which bindings are deleted automatically
+    ///
+    /// # Returns
+    ///
+    /// The TTL, or None if idle bindings are kept
+    pub fn get_idle_binding_ttl(&self) -> Option<Duration> {
+        (self.idle_binding_ttl > 0).then(|| Duration::from_secs(self.idle_binding_ttl))
+    }
+
+    /// Get the interval between scans for idle bindings
+    pub fn get_idle_scan_interval(&self) -> Duration {
+        Duration::from_secs(self.idle_scan_interval)
+    }
+
+    /// Get the idle time after which proxied connections are closed
+    ///
+    /// # Returns
+    ///
+    /// The timeout, or None if idle connections are kept
+    pub fn get_idle_timeout(&self) -> Option<Duration> {
+        (self.idle_timeout > 0).then(|| Duration::from_secs(self.idle_timeout))
+    }
+
+    /// Get the interval between scans for idle connections
+    pub fn get_idle_connection_scan_interval(&self) -> Duration {
+        Duration::from_secs(self.idle_connection_scan_interval)
+    }
+
+    /// Get the signer that verifies `/proxy` API requests
+    ///
+    /// # Returns
+    ///
+    /// A `RequestSigner` for `api_hmac_secret`, or None if requests are not signed
+    pub fn get_request_signer(&self) -> Option<Arc<RequestSigner>> {
+        self.api_hmac_secret.as_ref().map(|secret| {
+            Arc::new(RequestSigner::new(
+                secret.as_bytes(),
+                Duration::from_secs(self.api_hmac_max_skew),
+            ))
+        })
+    }
+
+    /// Get the rate limiter for the management API
+    ///
+    /// # Returns
+    ///
+    /// A `RateLimiter` for `api_rate_limit`, or None if the API is not rate limited
+    pub fn get_rate_limiter(&self) -> Option<RateLimiter> {
+        self.api_rate_limit
+            .map(|rate| RateLimiter::new(rate, self.api_rate_limit_per_client))
+    }
+
+    /// Get the connection drain timeout as a Duration
+    ///
+    /// # Returns
+    ///
+    /// An Option containing the drain timeout Duration, or None to wait indefinitely
+    pub fn get_drain_timeout(&self) -> Option<Duration> {
+        if self.drain_timeout == 0 {
+            None
+        } else {
+            Some(Duration::from_secs(self.drain_timeout))
+        }
+    }
 }
 
 #[cfg(test)]
@@ -94,9 +746,24 @@ mod tests {
         let config = Config {
             bind: "127.0.0.1:8000".to_string(),
             request_timeout: 30,
+            ..Default::default()
         };
         assert_eq!(config.bind, "127.0.0.1:8000");
         assert_eq!(config.request_timeout, 30);
+        assert!(config.api_socket.is_none());
+    }
+
+    #[test]
+    fn test_debug_redacts_hmac_secret() {
+        let config = Config::parse_from(["metaproxy", "--api-hmac-secret", "hunter2"]);
+        let debug = format!("{:?}", config);
+        assert!(!debug.contains("hunter2"), "{}", debug);
+        assert!(
+            debug.contains("api_hmac_secret: Some(\"REDACTED\")"),
+            "{}",
+            debug
+        );
+        assert!(format!("{:?}", Config::default()).contains("api_hmac_secret: None"));
     }
 
     #[test]
@@ -104,6 +771,7 @@ mod tests {
         let config = Config {
             bind: "127.0.0.1:8000".to_string(),
             request_timeout: 30,
+            ..Default::default()
         };
         let addr = config.get_bind_addr().unwrap();
         assert_eq!(addr.to_string(), "127.0.0.1:8000");
@@ -114,15 +782,305 @@ mod tests {
         let config = Config {
             bind: "invalid:address".to_string(),
             request_timeout: 30,
+            ..Default::default()
+        };
+        assert!(config.get_bind_addr().is_err());
+    }
+
+    #[test]
+    fn test_api_host_and_port_override_bind() {
+        let config = Config {
+            bind: "127.0.0.1:8000".to_string(),
+            api_host: Some("0.0.0.0".to_string()),
+            api_port: Some(9100),
+            ..Default::default()
+        };
+        assert_eq!(config.get_bind_addr().unwrap().to_string(), "0.0.0.0:9100");
+    }
+
+    #[test]
+    fn test_api_port_only() {
+        let config = Config {
+            bind: "127.0.0.1:8000".to_string(),
+            api_port: Some(9100),
+            ..Default::default()
+        };
+        assert_eq!(
+            config.get_bind_addr().unwrap().to_string(),
+            "127.0.0.1:9100"
+        );
+    }
+
+    #[test]
+    fn test_api_host_ipv6() {
+        let config = Config {
+            api_host: Some("::1".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(config.get_bind_addr().unwrap().to_string(), "[::1]:8000");
+    }
+
+    #[test]
+    fn test_invalid_api_host() {
+        let config = Config {
+            api_host: Some("not-an-ip".to_string()),
+            ..Default::default()
         };
         assert!(config.get_bind_addr().is_err());
     }
 
+    #[test]
+    fn test_copy_buffer_size() {
+        assert_eq!(Config::default().copy_buffer_size, 8192);
+        let config = Config::parse_from(["metaproxy", "--copy-buffer-size", "65536"]);
+        assert_eq!(config.copy_buffer_size, 65536);
+        assert!(Config::try_parse_from(["metaproxy", "--copy-buffer-size", "0"]).is_err());
+    }
+
+    #[test]
+    fn test_max_header_size() {
+        assert_eq!(Config::default().max_header_size, 8192);
+        let config = Config::parse_from(["metaproxy", "--max-header-size", "32768"]);
+        assert_eq!(config.max_header_size, 32768);
+        assert!(Config::try_parse_from(["metaproxy", "--max-header-size", "0"]).is_err());
+
+        assert_eq!(Config::default().max_upstream_header_size, 8192);
+        let config = Config::parse_from(["metaproxy", "--max-upstream-header-size", "65536"]);
+        assert_eq!(config.max_upstream_header_size, 65536);
+    }
+
+    #[test]
+    fn test_audit_log_size() {
+        assert_eq!(Config::default().audit_log_size, 1024);
+        let config = Config::parse_from(["metaproxy", "--audit-log-size", "50"]);
+        assert_eq!(config.audit_log_size, 50);
+        assert!(Config::try_parse_from(["metaproxy", "--audit-log-size", "0"]).is_err());
+    }
+
+    #[test]
+    fn test_restrict_listen_loopback() {
+        assert_eq!(Config::default().get_listen_ip().to_string(), "0.0.0.0");
+        let config = Config::parse_from(["metaproxy", "--restrict-listen-loopback"]);
+        assert_eq!(config.get_listen_ip().to_string(), "127.0.0.1");
+    }
+
+    #[test]
+    fn test_max_request_line_bytes() {
+        assert_eq!(Config::default().max_request_line_bytes, None);
+        let config = Config::parse_from(["metaproxy", "--max-request-line-bytes", "2048"]);
+        assert_eq!(config.max_request_line_bytes, Some(2048));
+        assert!(Config::try_parse_from(["metaproxy", "--max-request-line-bytes", "0"]).is_err());
+    }
+
+    #[test]
+    fn test_host_header() {
+        assert_eq!(Config::default().host_header, HostHeaderMode::Normalize);
+        let config = Config::parse_from(["metaproxy", "--host-header", "preserve"]);
+        assert_eq!(config.host_header, HostHeaderMode::Preserve);
+        let config = Config::parse_from(["metaproxy", "--host-header", "remove"]);
+        assert_eq!(config.host_header, HostHeaderMode::Remove);
+        assert!(Config::try_parse_from(["metaproxy", "--host-header", "rewrite"]).is_err());
+    }
+
+    #[test]
+    fn test_http10_requests() {
+        assert_eq!(Config::default().http10_requests, Http10Mode::Passthrough);
+        let config = Config::parse_from(["metaproxy", "--http10-requests", "upgrade"]);
+        assert_eq!(config.http10_requests, Http10Mode::Upgrade);
+        assert!(Config::try_parse_from(["metaproxy", "--http10-requests", "downgrade"]).is_err());
+    }
+
+    #[test]
+    fn test_bind_concurrency() {
+        assert_eq!(Config::default().bind_concurrency, 16);
+        let config = Config::parse_from(["metaproxy", "--bind-concurrency", "64"]);
+        assert_eq!(config.bind_concurrency, 64);
+        assert!(Config::try_parse_from(["metaproxy", "--bind-concurrency", "0"]).is_err());
+    }
+
+    #[test]
+    fn test_listen_backlog() {
+        assert_eq!(Config::default().listen_backlog, 1024);
+        let config = Config::parse_from(["metaproxy", "--listen-backlog", "4096"]);
+        assert_eq!(config.listen_backlog, 4096);
+        assert!(Config::try_parse_from(["metaproxy", "--listen-backlog", "0"]).is_err());
+    }
+
+    #[test]
+    fn test_socket_options() {
+        let options = Config::default().get_socket_options();
+        assert!(options.nodelay);
+        assert!(options.keepalive_idle.is_none());
+
+        let config = Config::parse_from([
+            "metaproxy",
+            "--tcp-nodelay=false",
+            "--tcp-keepalive-idle",
+            "60",
+            "--tcp-keepalive-interval",
+            "10",
+        ]);
+        let options = config.get_socket_options();
+        assert!(!options.nodelay);
+        assert_eq!(options.keepalive_idle, Some(Duration::from_secs(60)));
+        assert_eq!(options.keepalive_interval, Some(Duration::from_secs(10)));
+    }
+
+    #[test]
+    fn test_max_bindings() {
+        assert_eq!(Config::default().get_max_bindings(), None);
+        let config = Config::parse_from(["metaproxy", "--max-bindings", "0"]);
+        assert_eq!(config.get_max_bindings(), None);
+        let config = Config::parse_from(["metaproxy", "--max-bindings", "64"]);
+        assert_eq!(config.get_max_bindings(), Some(64));
+    }
+
+    #[test]
+    fn test_log_level() {
+        assert_eq!(Config::default().log_level, None);
+        let config = Config::parse_from(["metaproxy", "--log-level", "debug"]);
+        assert_eq!(config.log_level, Some(LevelFilter::Debug));
+        let config = Config::parse_from(["metaproxy", "--log-level", "WARN"]);
+        assert_eq!(config.log_level, Some(LevelFilter::Warn));
+        assert!(Config::try_parse_from(["metaproxy", "--log-level", "loud"]).is_err());
+    }
+
+    #[test]
+    fn test_idle_binding_ttl() {
+        let config = Config::default();
+        assert_eq!(config.get_idle_binding_ttl(), None);
+        assert_eq!(config.get_idle_scan_interval(), Duration::from_secs(60));
+        let config = Config::parse_from([
+            "metaproxy",
+            "--idle-binding-ttl",
+            "600",
+            "--idle-scan-interval",
+            "15",
+        ]);
+        assert_eq!(
+            config.get_idle_binding_ttl(),
+            Some(Duration::from_secs(600))
+        );
+        assert_eq!(config.get_idle_scan_interval(), Duration::from_secs(15));
+        assert!(Config::try_parse_from(["metaproxy", "--idle-scan-interval", "0"]).is_err());
+    }
+
+    #[test]
+    fn test_idle_timeout() {
+        let config = Config::default();
+        assert_eq!(config.get_idle_timeout(), None);
+        assert_eq!(
+            config.get_idle_connection_scan_interval(),
+            Duration::from_secs(10)
+        );
+        let config = Config::parse_from([
+            "metaproxy",
+            "--idle-timeout",
+            "300",
+            "--idle-connection-scan-interval",
+            "5",
+        ]);
+        assert_eq!(config.get_idle_timeout(), Some(Duration::from_secs(300)));
+        assert_eq!(
+            config.get_idle_connection_scan_interval(),
+            Duration::from_secs(5)
+        );
+        assert!(
+            Config::try_parse_from(["metaproxy", "--idle-connection-scan-interval", "0"]).is_err()
+        );
+    }
+
+    #[test]
+    fn test_dns_server() {
+        assert!(Config::default().dns_server.is_none());
+        let config = Config::parse_from(["metaproxy", "--dns-server", "10.0.0.2"]);
+        assert_eq!(config.dns_server, Some("10.0.0.2:53".parse().unwrap()));
+        let config = Config::parse_from(["metaproxy", "--dns-server", "[fd00::53]:5353"]);
+        assert_eq!(config.dns_server, Some("[fd00::53]:5353".parse().unwrap()));
+        let config = Config::parse_from(["metaproxy", "--dns-server", "fd00::53"]);
+        assert_eq!(config.dns_server, Some("[fd00::53]:53".parse().unwrap()));
+        assert!(Config::try_parse_from(["metaproxy", "--dns-server", "dns.example"]).is_err());
+    }
+
+    #[test]
+    fn test_connect_bind() {
+        assert!(Config::default().connect_bind.is_none());
+        let config = Config::parse_from(["metaproxy", "--connect-bind", "127.0.0.1"]);
+        assert_eq!(config.connect_bind, Some("127.0.0.1".parse().unwrap()));
+        assert!(Config::try_parse_from(["metaproxy", "--connect-bind", "proxy.example"]).is_err());
+        // An address no interface has is refused up front
+        assert!(Config::try_parse_from(["metaproxy", "--connect-bind", "192.0.2.1"]).is_err());
+    }
+
+    #[test]
+    fn test_event_webhook() {
+        assert!(Config::default().event_webhook.is_none());
+        let config =
+            Config::parse_from(["metaproxy", "--event-webhook", "http://127.0.0.1:9090/hook"]);
+        assert_eq!(
+            config.event_webhook.unwrap().as_str(),
+            "http://127.0.0.1:9090/hook"
+        );
+        assert!(Config::try_parse_from([
+            "metaproxy",
+            "--event-webhook",
+            "https://hooks.example.com"
+        ])
+        .is_err());
+        assert!(Config::try_parse_from(["metaproxy", "--event-webhook", "not a url"]).is_err());
+    }
+
+    #[test]
+    fn test_connection_limit() {
+        assert!(Config::default().get_connection_limit().is_none());
+        let config = Config::parse_from(["metaproxy", "--max-global-connections", "100"]);
+        assert_eq!(config.get_connection_limit().unwrap().max(), 100);
+        assert!(Config::try_parse_from(["metaproxy", "--max-global-connections", "0"]).is_err());
+    }
+
+    #[test]
+    fn test_remaining_drain_timeout() {
+        let config = Config {
+            drain_timeout: 30,
+            ..Default::default()
+        };
+        assert!(config.get_shutdown_timeout().is_none());
+        assert_eq!(
+            config.get_remaining_drain_timeout(Duration::from_secs(100)),
+            Some(Duration::from_secs(30))
+        );
+
+        let config = Config {
+            drain_timeout: 30,
+            shutdown_timeout: 20,
+            ..Default::default()
+        };
+        assert_eq!(
+            config.get_remaining_drain_timeout(Duration::from_secs(5)),
+            Some(Duration::from_secs(15))
+        );
+        assert_eq!(
+            config.get_remaining_drain_timeout(Duration::from_secs(25)),
+            Some(Duration::ZERO)
+        );
+
+        let config = Config {
+            drain_timeout: 0,
+            shutdown_timeout: 20,
+            ..Default::default()
+        };
+        assert_eq!(
+            config.get_remaining_drain_timeout(Duration::from_secs(5)),
+            Some(Duration::from_secs(15))
+        );
+    }
+
     #[test]
     fn test_request_timeout() {
         let config = Config {
             bind: "127.0.0.1:8000".to_string(),
             request_timeout: 30,
+            ..Default::default()
         };
         let timeout = config.get_request_timeout().unwrap();
         assert_eq!(timeout.as_secs(), 30);
@@ -133,7 +1091,77 @@ mod tests {
         let config = Config {
             bind: "127.0.0.1:8000".to_string(),
             request_timeout: 0,
+            ..Default::default()
         };
         assert!(config.get_request_timeout().is_none());
     }
+
+    #[test]
+    fn test_connect_timeout() {
+        let config = Config::default();
+        assert_eq!(config.get_connect_timeout().unwrap().as_secs(), 30);
+        assert!(config.get_request_timeout().is_none());
+
+        let config = Config::parse_from(["metaproxy", "--connect-timeout", "0"]);
+        assert!(config.get_connect_timeout().is_none());
+    }
+
+    #[test]
+    fn test_drain_timeout() {
+        let config = Config::default();
+        assert_eq!(config.get_drain_timeout().unwrap().as_secs(), 30);
+
+        let config = Config {
+            drain_timeout: 0,
+            ..Default::default()
+        };
+        assert!(config.get_drain_timeout().is_none());
+    }
+
+    #[test]
+    fn test_default_matches_cli_defaults() {
+        let config = Config::default();
+        assert_eq!(config.bind, "127.0.0.1:8000");
+        assert_eq!(config.connect_timeout, 30);
+        assert_eq!(config.request_timeout, 0);
+        assert!(config.api_socket.is_none());
+    }
+
+    fn write_config_file(name: &str, contents: &str) -> std::path::PathBuf {
+        let path =
+            std::env::temp_dir().join(format!("metaproxy-{}-{}.json", name, std::process::id()));
+        std::fs::write(&path, contents).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_load_bindings() {
+        let path = write_config_file(
+            "load",
+            r#"{"bindings": [
+                {"port": 9000, "upstream": "http://127.0.0.1:8080"},
+                {"port": 9001, "upstream": "http://backend:8080", "upstream_mode": "reverse"}
+            ]}"#,
+        );
+        let specs = load_bindings(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(specs.len(), 2);
+        assert_eq!(specs[0].port, 9000);
+        assert_eq!(specs[1].upstream_mode, crate::proxy::UpstreamMode::Reverse);
+    }
+
+    #[test]
+    fn test_load_bindings_rejects_duplicate_ports() {
+        let path = write_config_file(
+            "duplicate",
+            r#"{"bindings": [
+                {"port": 9000, "upstream": "http://a:8080"},
+                {"port": 9000, "upstream": "http://b:8080"}
+            ]}"#,
+        );
+        let result = load_bindings(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert!(result.is_err());
+    }
 }
@@ -0,0 +1,344 @@
+/*!
+ * # DNS Module
+ *
+ * This module resolves the hosts of upstreams, and of the targets that
+ * `direct` bindings and `DIRECT` routes connect to.
+ *
+ * By default names are resolved by the system resolver, as
+ * `TcpStream::connect` does. With `--dns-server` they are looked up through a
+ * specific nameserver instead, e.g. to see the internal view of a
+ * split-horizon DNS.
+ *
+ * Connections are opened from whatever address the routing table picks,
+ * unless a source address is given with `--connect-bind` or a binding's
+ * `connect_bind`, e.g. to have policy routing on a multi-homed host send them
+ * out of a specific interface.
+ *
+ * Addresses of metaproxy's own listeners are never connected to; see
+ * [`crate::listeners`].
+ */
+
+use crate::error::{Error, Result};
+use crate::listeners::{hop_source, LocalListeners};
+use hickory_resolver::config::{NameServerConfigGroup, ResolverConfig, ResolverOpts};
+use hickory_resolver::TokioAsyncResolver;
+use socket2::{Domain, Protocol, Socket, Type};
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use tokio::net::{TcpSocket, TcpStream};
+
+/// Resolves the hosts that proxied connections are made to
+#[derive(Clone, Default)]
+pub enum Resolver {
+    /// Resolve names with the system resolver
+    #[default]
+    System,
+    /// Resolve names through a specific nameserver
+    Nameserver(Arc<TokioAsyncResolver>),
+}
+
+impl Resolver {
+    /// Create a resolver that queries a single nameserver
+    ///
+    /// # Arguments
+    ///
+    /// * `addr` - The address of the nameserver, queried over UDP and TCP
+    ///
+    /// # Returns
+    ///
+    /// A new `Resolver` that ignores the system configuration
+    pub fn nameserver(addr: SocketAddr) -> Self {
+        let servers = NameServerConfigGroup::from_ips_clear(&[addr.ip()], addr.port(), true);
+        let config = ResolverConfig::from_parts(None, Vec::new(), servers);
+        Resolver::Nameserver(Arc::new(TokioAsyncResolver::tokio(
+            config,
+            ResolverOpts::default(),
+        )))
+    }
+
+    /// Open a TCP connection to a host
+    ///
+    /// Each address the host resolves to is tried in turn until one accepts
+    /// the connection. With a source address, addresses of the other IP
+    /// family cannot be reached and fail. Connections looping back into
+    /// metaproxy fail with a [`ConnectionLoop`](crate::listeners::ConnectionLoop)
+    /// error instead.
+    ///
+    /// # Arguments
+    ///
+    /// * `host` - The host name or IP address, with or without IPv6 brackets
+    /// * `port` - The port to connect to
+    /// * `source` - The local address the connection is opened from, if not left to the system
+    /// * `listeners` - The addresses metaproxy listens on, and the bindings the
+    ///   connection went through
+    ///
+    /// # Returns
+    ///
+    /// A result containing the connected stream, or the last connection error
+    pub async fn connect(
+        &self,
+        host: &str,
+        port: u16,
+        source: Option<IpAddr>,
+        listeners: &LocalListeners,
+    ) -> Result<TcpStream> {
+        let host = host.trim_start_matches('[').trim_end_matches(']');
+        let addrs: Vec<SocketAddr> = match self {
+            Resolver::System => tokio::net::lookup_host((host, port)).await?.collect(),
+            Resolver::Nameserver(resolver) => lookup(resolver, host, port).await?,
+        };
+
+        let mut last_error = None;
+        for addr in addrs {
+            match connect_from(addr, source, listeners).await {
+                Ok(stream) => return Ok(stream),
+                Err(e) => last_error = Some(e),
+            }
+        }
+        Err(match last_error {
+            Some(e) => e.into(),
+            None => Error::Custom(format!("No addresses found for {}", host)),
+        })
+    }
+}
+
+/// Open a TCP connection to an address, from a source address if one is given
+///
+/// The socket is bound to the source address, with a port chosen by the
+/// system, before it connects. A connection to another binding of this proxy
+/// is bound the same way, from the binding's own address unless a source is
+/// given, so the bindings it went through can be recorded under its local
+/// address before the binding accepts it.
+///
+/// # Arguments
+///
+/// * `addr` - The address to connect to
+/// * `source` - The local address the connection is opened from, if any
+/// * `listeners` - The addresses metaproxy listens on, and the bindings the
+///   connection went through
+///
+/// # Returns
+///
+/// A result containing the connected stream, or an error wrapping
+/// [`ConnectionLoop`](crate::listeners::ConnectionLoop) if the connection
+/// would loop back into metaproxy
+async fn connect_from(
+    addr: SocketAddr,
+    source: Option<IpAddr>,
+    listeners: &LocalListeners,
+) -> io::Result<TcpStream> {
+    let hop = listeners.check(addr)?;
+    let source = match source {
+        Some(source) => source,
+        None if hop => hop_source(addr),
+        None => return TcpStream::connect(addr).await,
+    };
+    if source.is_ipv4() != addr.is_ipv4() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("cannot connect to {} from {}", addr, source),
+        ));
+    }
+    let socket = Socket::new(Domain::for_address(addr), Type::STREAM, Some(Protocol::TCP))?;
+    socket.set_nonblocking(true)?;
+    socket.bind(&SocketAddr::new(source, 0).into())?;
+    let local = match hop {
+        true => socket.local_addr()?.as_socket(),
+        false => None,
+    };
+    if let Some(local) = local {
+        listeners.record_hop(local);
+    }
+    let connected = TcpSocket::from_std_stream(socket.into())
+        .connect(addr)
+        .await;
+    if let (Err(_), Some(local)) = (&connected, local) {
+        listeners.forget_hop(local);
+    }
+    connected
+}
+
+/// Check that connections can be opened from a source address
+///
+/// The address must be assigned to one of the host's interfaces, which is
+/// checked by binding a socket to it.
+///
+/// # Arguments
+///
+/// * `source` - The local address connections are to be opened from
+///
+/// # Returns
+///
+/// A result indicating whether the address can be bound, with a descriptive error if not
+pub fn check_source_address(source: IpAddr) -> Result<()> {
+    let addr = SocketAddr::new(source, 0);
+    Socket::new(Domain::for_address(addr), Type::STREAM, Some(Protocol::TCP))
+        .and_then(|socket| socket.bind(&addr.into()))
+        .map_err(|e| Error::Custom(format!("Cannot open connections from {}: {}", source, e)))
+}
+
+/// Resolve a host through a nameserver
+///
+/// # Arguments
+///
+/// * `resolver` - The resolver querying the nameserver
+/// * `host` - The host name, or an IP address that is used as is
+/// * `port` - The port of the returned addresses
+///
+/// # Returns
+///
+/// A result containing the addresses of the host
+async fn lookup(resolver: &TokioAsyncResolver, host: &str, port: u16) -> Result<Vec<SocketAddr>> {
+    if let Ok(ip) = host.parse::<IpAddr>() {
+        return Ok(vec![SocketAddr::new(ip, port)]);
+    }
+    let ips = resolver
+        .lookup_ip(host)
+        .await
+        .map_err(|e| Error::Custom(format!("Failed to resolve {}: {}", host, e)))?;
+    Ok(ips.iter().map(|ip| SocketAddr::new(ip, port)).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::listeners::{is_connection_loop, ListenerKind};
+    use hickory_resolver::proto::op::{Message, MessageType, ResponseCode};
+    use hickory_resolver::proto::rr::rdata::A;
+    use hickory_resolver::proto::rr::{RData, Record, RecordType};
+    use std::net::Ipv4Addr;
+    use tokio::net::{TcpListener, UdpSocket};
+
+    /// Start a nameserver answering every A query with 127.0.0.1
+    async fn loopback_nameserver() -> SocketAddr {
+        let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let addr = socket.local_addr().unwrap();
+        tokio::spawn(async move {
+            let mut buf = [0u8; 512];
+            while let Ok((n, peer)) = socket.recv_from(&mut buf).await {
+                let query = Message::from_vec(&buf[..n]).unwrap();
+                let mut response = Message::new();
+                response
+                    .set_id(query.id())
+                    .set_message_type(MessageType::Response)
+                    .set_recursion_desired(true)
+                    .set_recursion_available(true)
+                    .set_response_code(ResponseCode::NoError);
+                for question in query.queries() {
+                    response.add_query(question.clone());
+                    if question.query_type() == RecordType::A {
+                        response.add_answer(Record::from_rdata(
+                            question.name().clone(),
+                            60,
+                            RData::A(A(Ipv4Addr::LOCALHOST)),
+                        ));
+                    }
+                }
+                let _ = socket.send_to(&response.to_vec().unwrap(), peer).await;
+            }
+        });
+        addr
+    }
+
+    #[tokio::test]
+    async fn test_system_resolver_connects() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        let stream = Resolver::System
+            .connect("127.0.0.1", port, None, &LocalListeners::default())
+            .await
+            .unwrap();
+        assert_eq!(stream.peer_addr().unwrap().port(), port);
+    }
+
+    #[tokio::test]
+    async fn test_connect_refuses_local_listeners() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let listeners = LocalListeners::default();
+        let _registration = listeners.register(addr, ListenerKind::Binding);
+
+        // A connection to another binding records the bindings it went through
+        let stream = Resolver::System
+            .connect("127.0.0.1", addr.port(), None, &listeners.for_binding(9000))
+            .await
+            .unwrap();
+        let (_, peer) = listener.accept().await.unwrap();
+        assert_eq!(peer, stream.local_addr().unwrap());
+        let accepted = listeners.for_binding(addr.port()).accepted(peer).unwrap();
+        assert_eq!(accepted.path(), [9000, addr.port()]);
+
+        // and may not enter the binding again
+        let error = Resolver::System
+            .connect("127.0.0.1", addr.port(), None, &accepted)
+            .await
+            .unwrap_err();
+        assert!(is_connection_loop(&error), "{}", error);
+    }
+
+    #[tokio::test]
+    async fn test_connect_from_source_address() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let source = IpAddr::V4(Ipv4Addr::LOCALHOST);
+
+        let stream = Resolver::System
+            .connect("localhost", port, Some(source), &LocalListeners::default())
+            .await
+            .unwrap();
+        assert_eq!(stream.local_addr().unwrap().ip(), source);
+        let (_, peer) = listener.accept().await.unwrap();
+        assert_eq!(peer, stream.local_addr().unwrap());
+
+        // An IPv4 source cannot reach an IPv6 address
+        let error = Resolver::System
+            .connect("::1", port, Some(source), &LocalListeners::default())
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("cannot connect to [::1]"));
+    }
+
+    #[test]
+    fn test_check_source_address() {
+        assert!(check_source_address(IpAddr::V4(Ipv4Addr::LOCALHOST)).is_ok());
+        // A documentation address is assigned to no interface
+        let error = check_source_address("192.0.2.1".parse().unwrap()).unwrap_err();
+        assert!(error
+            .to_string()
+            .starts_with("Cannot open connections from 192.0.2.1"));
+    }
+
+    #[tokio::test]
+    async fn test_nameserver_resolves_hosts() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let resolver = Resolver::nameserver(loopback_nameserver().await);
+
+        // A name only the configured nameserver knows
+        let stream = resolver
+            .connect(
+                "backend.metaproxy.test",
+                port,
+                None,
+                &LocalListeners::default(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            stream.peer_addr().unwrap(),
+            SocketAddr::from((Ipv4Addr::LOCALHOST, port))
+        );
+
+        // IP addresses are connected to without a lookup
+        assert!(resolver
+            .connect("[::1]", 9, None, &LocalListeners::default())
+            .await
+            .is_err());
+        assert!(resolver
+            .connect("127.0.0.1", port, None, &LocalListeners::default())
+            .await
+            .is_ok());
+    }
+}
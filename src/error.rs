@@ -116,7 +116,7 @@ mod tests {
 
     #[test]
     fn test_from_io_error() {
-        let io_error = std::io::Error::new(std::io::ErrorKind::Other, "test");
+        let io_error = std::io::Error::other("test");
         let error = Error::from(io_error);
         match error {
             Error::Io(_) => {} // Just check that it's the right variant
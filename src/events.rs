@@ -0,0 +1,529 @@
+/*!
+ * # Events Module
+ *
+ * This module announces changes to the set of proxy bindings, so that
+ * dashboards and other integrations can follow them as they happen rather
+ * than polling `/health`.
+ *
+ * Every creation, update, deletion, pause and resume of a binding is
+ * published on an [`EventBus`] as a [`BindingEvent`]:
+ *
+ * ```json
+ * {"type": "created", "port": 9000, "upstream": "http://proxy-a:3128"}
+ * ```
+ *
+ * Subscribers that fall behind by more than the bus capacity miss the
+ * oldest events rather than slowing down the API.
+ *
+ * With `--event-webhook`, [`deliver_to_webhook`] also POSTs each event to a
+ * URL in the background, so API requests never wait on the webhook.
+ *
+ * Every proxied connection also ends with a single [`ConnectionOutcome`],
+ * saying where it went, how many bytes it carried, how long it lived and how
+ * it ended. The binding's traffic counters are updated from it, and it is then
+ * published on its own bus for the access log written by
+ * [`log_connection_outcomes`] and any other subscriber.
+ */
+
+use crate::auth::redact_password;
+use hyper::body::Body;
+use hyper::client::HttpConnector;
+use hyper::{Client, Method, Request};
+use log::{debug, warn};
+use serde::Serialize;
+use std::net::SocketAddr;
+use std::time::Duration;
+use tokio::sync::broadcast;
+use tokio::sync::broadcast::error::RecvError;
+use url::Url;
+
+/// The number of events kept for subscribers that have not received them yet
+pub const EVENT_CAPACITY: usize = 256;
+
+/// How long a single webhook delivery may take
+pub const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How many times delivering an event to the webhook is attempted
+pub const WEBHOOK_ATTEMPTS: u32 = 3;
+
+/// What happened to a binding
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BindingEventKind {
+    /// The binding was created
+    Created,
+    /// The binding's upstream, rules or tags changed
+    Updated,
+    /// The binding was deleted
+    Deleted,
+    /// The binding stopped proxying new connections
+    Paused,
+    /// The binding proxies new connections again
+    Resumed,
+}
+
+impl BindingEventKind {
+    /// Get the name of the event kind, as serialized
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            BindingEventKind::Created => "created",
+            BindingEventKind::Updated => "updated",
+            BindingEventKind::Deleted => "deleted",
+            BindingEventKind::Paused => "paused",
+            BindingEventKind::Resumed => "resumed",
+        }
+    }
+}
+
+/// A change to a binding
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct BindingEvent {
+    /// What happened to the binding
+    #[serde(rename = "type")]
+    pub kind: BindingEventKind,
+    /// The port of the binding
+    pub port: u16,
+    /// The binding's upstream server address after the change, with its
+    /// password redacted; empty if it has none
+    pub upstream: String,
+}
+
+/// How a proxied connection ended
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ConnectionResult {
+    /// The request or tunnel completed
+    Ok,
+    /// The upstream connection or the request took longer than allowed
+    Timeout,
+    /// The upstream could not be reached, refused the request, or the
+    /// connection failed while relaying
+    UpstreamError,
+    /// The client went away before the request was sent or completed
+    ClientDisconnect,
+    /// The proxy answered the request itself with an error, e.g. a malformed
+    /// request or one that would loop back into the proxy
+    Rejected,
+    /// The connection was closed by the proxy, e.g. by a binding reset, the
+    /// shutdown drain or the idle connection reaper
+    Cancelled,
+}
+
+impl ConnectionResult {
+    /// Get the name of the result, as serialized
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ConnectionResult::Ok => "ok",
+            ConnectionResult::Timeout => "timeout",
+            ConnectionResult::UpstreamError => "upstream-error",
+            ConnectionResult::ClientDisconnect => "client-disconnect",
+            ConnectionResult::Rejected => "rejected",
+            ConnectionResult::Cancelled => "cancelled",
+        }
+    }
+}
+
+/// The outcome of a proxied connection, recorded once it closed
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ConnectionOutcome {
+    /// The port of the binding that accepted the connection
+    pub port: u16,
+    /// The address the client connected from
+    pub client: SocketAddr,
+    /// Whether the connection was a CONNECT tunnel rather than plain HTTP
+    pub tunnel: bool,
+    /// The upstream the connection was sent to, with its password redacted;
+    /// empty if it ended before one was picked
+    pub upstream: String,
+    /// Bytes relayed from the client to the upstream
+    pub bytes_from_client: u64,
+    /// Bytes relayed from the upstream to the client
+    pub bytes_from_upstream: u64,
+    /// How long the connection lived, in milliseconds
+    pub duration_ms: u64,
+    /// How the connection ended
+    pub result: ConnectionResult,
+}
+
+/// A channel that events are published to and subscribed from
+///
+/// The default bus carries [`BindingEvent`]s; connection outcomes are
+/// published on a bus of [`ConnectionOutcome`]s.
+#[derive(Clone)]
+pub struct EventBus<E = BindingEvent> {
+    sender: broadcast::Sender<E>,
+}
+
+impl<E: Clone> EventBus<E> {
+    /// Create an event bus
+    ///
+    /// # Arguments
+    ///
+    /// * `capacity` - The number of events kept for lagging subscribers
+    ///
+    /// # Returns
+    ///
+    /// A new `EventBus` without subscribers
+    pub fn new(capacity: usize) -> Self {
+        let (sender, _) = broadcast::channel(capacity);
+        EventBus { sender }
+    }
+
+    /// Publish an event to every current subscriber
+    ///
+    /// Events published while nobody is subscribed are dropped.
+    ///
+    /// # Arguments
+    ///
+    /// * `event` - The event to publish
+    pub fn send(&self, event: E) {
+        let _ = self.sender.send(event);
+    }
+
+    /// Subscribe to the events published from now on
+    ///
+    /// # Returns
+    ///
+    /// A receiver of the events; dropping it ends the subscription
+    pub fn subscribe(&self) -> broadcast::Receiver<E> {
+        self.sender.subscribe()
+    }
+}
+
+impl EventBus {
+    /// Publish an event to every current subscriber
+    ///
+    /// Events published while nobody is subscribed are dropped. The password
+    /// of the upstream is redacted, as events leave the proxy through the
+    /// webhook and the API.
+    ///
+    /// # Arguments
+    ///
+    /// * `kind` - What happened to the binding
+    /// * `port` - The port of the binding
+    /// * `upstream` - The binding's upstream server address
+    pub fn publish(&self, kind: BindingEventKind, port: u16, upstream: impl Into<String>) {
+        let mut upstream = upstream.into();
+        redact_password(&mut upstream);
+        self.send(BindingEvent {
+            kind,
+            port,
+            upstream,
+        });
+    }
+}
+
+impl<E: Clone> Default for EventBus<E> {
+    fn default() -> Self {
+        EventBus::new(EVENT_CAPACITY)
+    }
+}
+
+/// Write every received connection outcome to the access log, until the bus is dropped
+///
+/// Each connection gets a single line at debug level, in place of the lines
+/// the handlers used to log as they went.
+///
+/// # Arguments
+///
+/// * `outcomes` - A subscription to the bus connection outcomes are published on
+pub async fn log_connection_outcomes(mut outcomes: broadcast::Receiver<ConnectionOutcome>) {
+    loop {
+        match outcomes.recv().await {
+            Ok(outcome) => debug!("{}", access_log_line(&outcome)),
+            Err(RecvError::Lagged(missed)) => {
+                debug!("Access log fell behind, skipped {} connections", missed);
+            }
+            Err(RecvError::Closed) => return,
+        }
+    }
+}
+
+/// Describe a connection outcome for the access log
+///
+/// # Arguments
+///
+/// * `outcome` - The outcome of the connection
+///
+/// # Returns
+///
+/// A line such as `Port 9000 CONNECT from 10.0.0.7:51234 via http://proxy:3128: ok
+/// after 120 ms, client->upstream: 517 bytes, upstream->client: 4096 bytes`
+pub fn access_log_line(outcome: &ConnectionOutcome) -> String {
+    let kind = if outcome.tunnel { "CONNECT" } else { "HTTP" };
+    let upstream = if outcome.upstream.is_empty() {
+        String::new()
+    } else {
+        format!(" via {}", outcome.upstream)
+    };
+    format!(
+        "Port {} {} from {}{}: {} after {} ms, client->upstream: {} bytes, upstream->client: {} bytes",
+        outcome.port,
+        kind,
+        outcome.client,
+        upstream,
+        outcome.result.as_str(),
+        outcome.duration_ms,
+        outcome.bytes_from_client,
+        outcome.bytes_from_upstream
+    )
+}
+
+/// POST every received event to a webhook, until the bus is dropped
+///
+/// Events are delivered one at a time, in order. A delivery that fails, times
+/// out after [`WEBHOOK_TIMEOUT`] or gets a non-2xx status is retried with a
+/// growing delay, up to [`WEBHOOK_ATTEMPTS`] attempts in total, and then
+/// dropped with a warning.
+///
+/// # Arguments
+///
+/// * `events` - A subscription to the event bus, taken before the events to deliver are published
+/// * `url` - The `http://` URL the events are POSTed to
+pub async fn deliver_to_webhook(mut events: broadcast::Receiver<BindingEvent>, url: Url) {
+    let client = Client::new();
+    loop {
+        match events.recv().await {
+            Ok(event) => post_event(&client, &url, &event).await,
+            Err(RecvError::Lagged(missed)) => {
+                warn!("Webhook delivery fell behind, skipped {} events", missed);
+            }
+            Err(RecvError::Closed) => return,
+        }
+    }
+}
+
+/// Deliver an event to a webhook, retrying failed attempts
+///
+/// # Arguments
+///
+/// * `client` - The HTTP client to send requests with
+/// * `url` - The URL the event is POSTed to
+/// * `event` - The event, sent as the JSON body
+async fn post_event(client: &Client<HttpConnector>, url: &Url, event: &BindingEvent) {
+    let body = match serde_json::to_vec(event) {
+        Ok(body) => body,
+        Err(e) => {
+            warn!("Failed to serialize event for webhook: {}", e);
+            return;
+        }
+    };
+
+    for attempt in 1..=WEBHOOK_ATTEMPTS {
+        let request = Request::builder()
+            .method(Method::POST)
+            .uri(url.as_str())
+            .header("Content-Type", "application/json")
+            .body(Body::from(body.clone()));
+        let request = match request {
+            Ok(request) => request,
+            Err(e) => {
+                warn!("Invalid webhook request to {}: {}", url, e);
+                return;
+            }
+        };
+
+        let error = match tokio::time::timeout(WEBHOOK_TIMEOUT, client.request(request)).await {
+            Ok(Ok(response)) if response.status().is_success() => {
+                debug!(
+                    "Delivered {} event for port {} to webhook",
+                    event.kind.as_str(),
+                    event.port
+                );
+                return;
+            }
+            Ok(Ok(response)) => format!("status {}", response.status()),
+            Ok(Err(e)) => e.to_string(),
+            Err(_) => format!("timed out after {:?}", WEBHOOK_TIMEOUT),
+        };
+        warn!(
+            "Webhook delivery of {} event for port {} failed (attempt {}/{}): {}",
+            event.kind.as_str(),
+            event.port,
+            attempt,
+            WEBHOOK_ATTEMPTS,
+            error
+        );
+        if attempt < WEBHOOK_ATTEMPTS {
+            tokio::time::sleep(Duration::from_millis(200) * attempt).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_subscribers_receive_published_events() {
+        let bus = EventBus::default();
+        // Events published before subscribing are not delivered
+        bus.publish(BindingEventKind::Created, 9000, "http://a:3128");
+
+        let mut first = bus.subscribe();
+        let mut second = bus.subscribe();
+        bus.publish(BindingEventKind::Deleted, 9000, "http://a:3128");
+
+        let expected = BindingEvent {
+            kind: BindingEventKind::Deleted,
+            port: 9000,
+            upstream: "http://a:3128".to_string(),
+        };
+        assert_eq!(first.recv().await.unwrap(), expected);
+        assert_eq!(second.recv().await.unwrap(), expected);
+        assert!(first.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_webhook_retries_failed_deliveries() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+        use tokio::net::TcpListener;
+        use tokio::sync::mpsc;
+
+        // A webhook that fails the first request and accepts the rest,
+        // reporting the body of every request it receives
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = Url::parse(&format!("http://{}/hook", listener.local_addr().unwrap())).unwrap();
+        let (tx, mut bodies) = mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            let mut status = "500 Internal Server Error";
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let mut request = Vec::new();
+                let mut buf = [0u8; 1024];
+                loop {
+                    let n = stream.read(&mut buf).await.unwrap();
+                    request.extend_from_slice(&buf[..n]);
+                    let text = String::from_utf8_lossy(&request);
+                    if let Some((head, body)) = text.split_once("\r\n\r\n") {
+                        let length: usize = head
+                            .lines()
+                            .find_map(|line| line.strip_prefix("content-length: "))
+                            .unwrap()
+                            .parse()
+                            .unwrap();
+                        if body.len() == length {
+                            assert!(head.starts_with("POST /hook HTTP/1.1"));
+                            tx.send(body.to_string()).unwrap();
+                            break;
+                        }
+                    }
+                }
+                let response = format!("HTTP/1.1 {}\r\nContent-Length: 0\r\n\r\n", status);
+                stream.write_all(response.as_bytes()).await.unwrap();
+                status = "204 No Content";
+            }
+        });
+
+        let bus = EventBus::default();
+        tokio::spawn(deliver_to_webhook(bus.subscribe(), url));
+        bus.publish(BindingEventKind::Created, 9000, "http://a:3128");
+        bus.publish(BindingEventKind::Deleted, 9000, "http://a:3128");
+
+        let expected_created = r#"{"type":"created","port":9000,"upstream":"http://a:3128"}"#;
+        let expected_deleted = r#"{"type":"deleted","port":9000,"upstream":"http://a:3128"}"#;
+        let mut received = Vec::new();
+        for _ in 0..3 {
+            received.push(
+                tokio::time::timeout(Duration::from_secs(5), bodies.recv())
+                    .await
+                    .unwrap()
+                    .unwrap(),
+            );
+        }
+        // The rejected first delivery is retried before the next event is sent
+        assert_eq!(
+            received,
+            [expected_created, expected_created, expected_deleted]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_webhook_payload_redacts_passwords() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+        use tokio::net::TcpListener;
+
+        // A webhook that accepts a single delivery and reports its request
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = Url::parse(&format!("http://{}/hook", listener.local_addr().unwrap())).unwrap();
+        let (tx, delivered) = tokio::sync::oneshot::channel();
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut request = Vec::new();
+            let mut buf = [0u8; 1024];
+            while !String::from_utf8_lossy(&request).ends_with('}') {
+                let n = stream.read(&mut buf).await.unwrap();
+                request.extend_from_slice(&buf[..n]);
+            }
+            stream
+                .write_all(b"HTTP/1.1 204 No Content\r\nContent-Length: 0\r\n\r\n")
+                .await
+                .unwrap();
+            let _ = tx.send(String::from_utf8_lossy(&request).to_string());
+        });
+
+        let bus = EventBus::default();
+        let mut subscriber = bus.subscribe();
+        tokio::spawn(deliver_to_webhook(bus.subscribe(), url));
+        bus.publish(BindingEventKind::Created, 9000, "http://user:secret@a:3128");
+
+        // Neither the webhook nor API subscribers see the password
+        let request = tokio::time::timeout(Duration::from_secs(5), delivered)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(!request.contains("secret"), "{}", request);
+        assert!(request.ends_with(
+            r#"{"type":"created","port":9000,"upstream":"http://user:REDACTED@a:3128/"}"#
+        ));
+        assert_eq!(
+            subscriber.recv().await.unwrap().upstream,
+            "http://user:REDACTED@a:3128/"
+        );
+    }
+
+    #[test]
+    fn test_connection_outcome_serialization() {
+        let outcome = ConnectionOutcome {
+            port: 9000,
+            client: "10.0.0.7:51234".parse().unwrap(),
+            tunnel: true,
+            upstream: "http://proxy:3128".to_string(),
+            bytes_from_client: 517,
+            bytes_from_upstream: 4096,
+            duration_ms: 120,
+            result: ConnectionResult::ClientDisconnect,
+        };
+        assert_eq!(
+            serde_json::to_value(&outcome).unwrap(),
+            serde_json::json!({
+                "port": 9000,
+                "client": "10.0.0.7:51234",
+                "tunnel": true,
+                "upstream": "http://proxy:3128",
+                "bytes_from_client": 517,
+                "bytes_from_upstream": 4096,
+                "duration_ms": 120,
+                "result": "client-disconnect",
+            })
+        );
+        assert_eq!(
+            access_log_line(&outcome),
+            "Port 9000 CONNECT from 10.0.0.7:51234 via http://proxy:3128: client-disconnect \
+             after 120 ms, client->upstream: 517 bytes, upstream->client: 4096 bytes"
+        );
+    }
+
+    #[test]
+    fn test_event_serialization() {
+        let event = BindingEvent {
+            kind: BindingEventKind::Paused,
+            port: 9000,
+            upstream: String::new(),
+        };
+        assert_eq!(
+            serde_json::to_value(&event).unwrap(),
+            serde_json::json!({"type": "paused", "port": 9000, "upstream": ""})
+        );
+        assert_eq!(event.kind.as_str(), "paused");
+    }
+}
@@ -0,0 +1,550 @@
+/*!
+ * # Request Body Framing Module
+ *
+ * This module works out where the body of a proxied HTTP/1.1 request ends,
+ * following RFC 9112 section 6.
+ *
+ * Requests whose framing is ambiguous, such as those with both
+ * `Transfer-Encoding` and `Content-Length`, are the basis of request
+ * smuggling: the proxy and the upstream could disagree on where the next
+ * request starts. They are rejected with [`request_body_framing`] before
+ * anything is sent upstream.
+ *
+ * The body itself is forwarded byte for byte. A [`BodyStream`] watches the
+ * client's bytes as they are relayed and fails the connection when a chunked
+ * body is malformed. It also records when the body is complete, i.e. after
+ * the last chunk and its trailers or after `Content-Length` bytes.
+ * [`BodyDecoder::feed`] tells how many bytes belong to the body, so that a
+ * next request on a keep-alive connection is parsed and routed on its own.
+ *
+ * A [`BodyDecoder`] can also hand out the data of the body without its chunk
+ * framing, which response compression uses to re-encode upstream bodies.
+ */
+
+use crate::error::{Error, Result};
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+/// The longest chunk size line, including chunk extensions
+const MAX_CHUNK_LINE: usize = 4096;
+
+/// The most bytes of trailer fields after the last chunk
+const MAX_TRAILERS: usize = 8192;
+
+/// How the end of a request body is determined
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BodyFraming {
+    /// The request has no body
+    Empty,
+    /// The body is as long as its `Content-Length`
+    Length(u64),
+    /// The body is sent in chunks, the last of which has size 0
+    Chunked,
+}
+
+/// Determine the framing of a request body from its headers
+///
+/// # Arguments
+///
+/// * `headers` - The request headers
+///
+/// # Returns
+///
+/// A result containing the framing, or an error if the request has both
+/// `Transfer-Encoding` and `Content-Length`, a transfer coding other than a
+/// final `chunked`, or an invalid or conflicting `Content-Length`
+pub fn request_body_framing(headers: &[httparse::Header]) -> Result<BodyFraming> {
+    let mut codings = Vec::new();
+    let mut length = None;
+    for header in headers {
+        let value = std::str::from_utf8(header.value)
+            .map_err(|_| Error::Custom(format!("Invalid {} header", header.name)))?;
+        if header.name.eq_ignore_ascii_case("transfer-encoding") {
+            codings.extend(
+                value
+                    .split(',')
+                    .map(|coding| coding.trim().to_ascii_lowercase())
+                    .filter(|coding| !coding.is_empty()),
+            );
+        } else if header.name.eq_ignore_ascii_case("content-length") {
+            // A list of identical lengths counts as one
+            for value in value.split(',').map(str::trim) {
+                if value.is_empty() || !value.bytes().all(|b| b.is_ascii_digit()) {
+                    return Err(Error::Custom(format!("Invalid Content-Length: {}", value)));
+                }
+                let value = value
+                    .parse::<u64>()
+                    .map_err(|_| Error::Custom(format!("Invalid Content-Length: {}", value)))?;
+                if length.is_some_and(|length| length != value) {
+                    return Err(Error::Custom(
+                        "Conflicting Content-Length headers".to_string(),
+                    ));
+                }
+                length = Some(value);
+            }
+        }
+    }
+
+    match (codings.last(), length) {
+        (None, None) => Ok(BodyFraming::Empty),
+        (None, Some(length)) => Ok(BodyFraming::Length(length)),
+        (Some(_), Some(_)) => Err(Error::Custom(
+            "Request has both Transfer-Encoding and Content-Length".to_string(),
+        )),
+        (Some(coding), None) if coding == "chunked" => {
+            if codings.iter().filter(|coding| *coding == "chunked").count() > 1 {
+                return Err(Error::Custom(
+                    "Request body is chunked more than once".to_string(),
+                ));
+            }
+            Ok(BodyFraming::Chunked)
+        }
+        (Some(coding), None) => Err(Error::Custom(format!(
+            "Unsupported final transfer coding: {}",
+            coding
+        ))),
+    }
+}
+
+/// Where a [`BodyDecoder`] is in the request body
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    /// Counting down the bytes of a `Content-Length` body
+    Length(u64),
+    /// Reading the hex digits of a chunk size
+    Size {
+        size: u64,
+        digits: usize,
+        line: usize,
+    },
+    /// Skipping a chunk extension up to the end of the size line
+    Extension { size: u64, line: usize },
+    /// Expecting the LF ending a chunk size line
+    SizeLf { size: u64 },
+    /// Skipping the data of a chunk
+    Data(u64),
+    /// Expecting the CR after the data of a chunk
+    DataCr,
+    /// Expecting the LF after the data of a chunk
+    DataLf,
+    /// Reading a trailer line after the last chunk
+    Trailer { line: usize, total: usize },
+    /// Expecting the LF ending a trailer line, or the trailer section if it was empty
+    TrailerLf { line: usize, total: usize },
+    /// The body is complete
+    Done,
+}
+
+/// Tracks the framing of a request body through the bytes that carry it
+#[derive(Debug, Clone)]
+pub struct BodyDecoder {
+    /// Where the decoder is in the body
+    state: State,
+}
+
+impl BodyDecoder {
+    /// Create a decoder at the start of a request body
+    ///
+    /// # Arguments
+    ///
+    /// * `framing` - How the end of the body is determined
+    ///
+    /// # Returns
+    ///
+    /// A new `BodyDecoder`
+    pub fn new(framing: BodyFraming) -> Self {
+        let state = match framing {
+            BodyFraming::Empty | BodyFraming::Length(0) => State::Done,
+            BodyFraming::Length(length) => State::Length(length),
+            BodyFraming::Chunked => State::Size {
+                size: 0,
+                digits: 0,
+                line: 0,
+            },
+        };
+        BodyDecoder { state }
+    }
+
+    /// Check whether the whole body has been seen
+    pub fn is_complete(&self) -> bool {
+        self.state == State::Done
+    }
+
+    /// Advance the decoder over the next bytes from the client
+    ///
+    /// Bytes after the end of the body belong to the next request on the
+    /// connection and are ignored.
+    ///
+    /// # Arguments
+    ///
+    /// * `bytes` - The next bytes sent by the client
+    ///
+    /// # Returns
+    ///
+    /// A result containing how many of the bytes belong to the body, or an
+    /// `InvalidData` error describing the malformed chunk
+    pub fn feed(&mut self, bytes: &[u8]) -> io::Result<usize> {
+        self.advance(bytes, None)
+    }
+
+    /// Advance the decoder over the next bytes of the body, collecting its data
+    ///
+    /// Like [`BodyDecoder::feed`], but the data of the body, without chunk
+    /// size lines and trailers, is appended to `data`.
+    ///
+    /// # Arguments
+    ///
+    /// * `bytes` - The next bytes of the body
+    /// * `data` - Receives the data carried by `bytes`
+    ///
+    /// # Returns
+    ///
+    /// A result indicating whether the bytes are valid framing, or an
+    /// `InvalidData` error describing the malformed chunk
+    pub fn decode(&mut self, bytes: &[u8], data: &mut Vec<u8>) -> io::Result<()> {
+        self.advance(bytes, Some(data)).map(|_| ())
+    }
+
+    /// Advance the decoder over the next bytes, appending the body data to `data` if given
+    ///
+    /// Returns how many of the bytes belong to the body.
+    fn advance(&mut self, bytes: &[u8], mut data: Option<&mut Vec<u8>>) -> io::Result<usize> {
+        let mut i = 0;
+        while i < bytes.len() {
+            let byte = bytes[i];
+            self.state = match self.state {
+                State::Done => return Ok(i),
+                State::Length(remaining) => {
+                    let taken = remaining.min((bytes.len() - i) as u64);
+                    if let Some(data) = data.as_deref_mut() {
+                        data.extend_from_slice(&bytes[i..i + taken as usize]);
+                    }
+                    i += taken as usize;
+                    match remaining - taken {
+                        0 => State::Done,
+                        remaining => State::Length(remaining),
+                    }
+                }
+                State::Data(remaining) => {
+                    let taken = remaining.min((bytes.len() - i) as u64);
+                    if let Some(data) = data.as_deref_mut() {
+                        data.extend_from_slice(&bytes[i..i + taken as usize]);
+                    }
+                    i += taken as usize;
+                    match remaining - taken {
+                        0 => State::DataCr,
+                        remaining => State::Data(remaining),
+                    }
+                }
+                state => {
+                    i += 1;
+                    Self::step(state, byte)?
+                }
+            };
+        }
+        Ok(i)
+    }
+
+    /// Advance a chunk framing state over a single byte
+    fn step(state: State, byte: u8) -> io::Result<State> {
+        let invalid = |message: &str| Err(io::Error::new(io::ErrorKind::InvalidData, message));
+        Ok(match state {
+            State::Size { size, digits, line } => {
+                if line >= MAX_CHUNK_LINE {
+                    return invalid("chunk size line too long");
+                }
+                match (byte as char).to_digit(16) {
+                    Some(digit) if digits < 16 => State::Size {
+                        size: size << 4 | u64::from(digit),
+                        digits: digits + 1,
+                        line: line + 1,
+                    },
+                    Some(_) => return invalid("chunk size too large"),
+                    None if digits == 0 => return invalid("invalid chunk size"),
+                    None => match byte {
+                        b'\r' => State::SizeLf { size },
+                        b';' | b' ' | b'\t' => State::Extension {
+                            size,
+                            line: line + 1,
+                        },
+                        _ => return invalid("invalid chunk size"),
+                    },
+                }
+            }
+            State::Extension { size, line } => match byte {
+                _ if line >= MAX_CHUNK_LINE => return invalid("chunk size line too long"),
+                b'\r' => State::SizeLf { size },
+                b'\n' => return invalid("chunk size line without CR"),
+                _ => State::Extension {
+                    size,
+                    line: line + 1,
+                },
+            },
+            State::SizeLf { size } => match byte {
+                b'\n' if size == 0 => State::Trailer { line: 0, total: 0 },
+                b'\n' => State::Data(size),
+                _ => return invalid("chunk size line without LF"),
+            },
+            State::DataCr => match byte {
+                b'\r' => State::DataLf,
+                _ => return invalid("chunk data longer than its size"),
+            },
+            State::DataLf => match byte {
+                b'\n' => State::Size {
+                    size: 0,
+                    digits: 0,
+                    line: 0,
+                },
+                _ => return invalid("chunk data without CRLF"),
+            },
+            State::Trailer { line, total } => match byte {
+                _ if total >= MAX_TRAILERS => return invalid("chunked trailers too large"),
+                b'\r' => State::TrailerLf { line, total },
+                b'\n' => return invalid("trailer line without CR"),
+                _ => State::Trailer {
+                    line: line + 1,
+                    total: total + 1,
+                },
+            },
+            State::TrailerLf { line, total } => match byte {
+                b'\n' if line == 0 => State::Done,
+                b'\n' => State::Trailer {
+                    line: 0,
+                    total: total + 2,
+                },
+                _ => return invalid("trailer line without LF"),
+            },
+            State::Length(_) | State::Data(_) | State::Done => state,
+        })
+    }
+}
+
+/// A client stream that checks the framing of the request body read from it
+///
+/// Bytes pass through unchanged; a malformed chunked body fails the read
+/// with an `InvalidData` error instead of reaching the upstream.
+#[derive(Debug)]
+pub struct BodyStream<S> {
+    /// The wrapped client stream
+    inner: S,
+    /// Tracks the body through the bytes read
+    decoder: BodyDecoder,
+}
+
+impl<S> BodyStream<S> {
+    /// Wrap a client stream
+    ///
+    /// # Arguments
+    ///
+    /// * `inner` - The client stream, positioned after any body bytes already fed to `decoder`
+    /// * `decoder` - Tracks the body of the request being relayed
+    ///
+    /// # Returns
+    ///
+    /// A new `BodyStream` over `inner`
+    pub fn new(inner: S, decoder: BodyDecoder) -> Self {
+        BodyStream { inner, decoder }
+    }
+
+    /// Check whether the whole request body has been read
+    pub fn is_complete(&self) -> bool {
+        self.decoder.is_complete()
+    }
+}
+
+impl<S: AsyncRead + Unpin> AsyncRead for BodyStream<S> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        let before = buf.filled().len();
+        match Pin::new(&mut this.inner).poll_read(cx, buf) {
+            Poll::Ready(Ok(())) => {
+                let result = this.decoder.feed(&buf.filled()[before..]).map(|_| ());
+                if result.is_err() {
+                    buf.set_filled(before);
+                }
+                Poll::Ready(result)
+            }
+            result => result,
+        }
+    }
+}
+
+impl<S: AsyncWrite + Unpin> AsyncWrite for BodyStream<S> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.get_mut().inner).poll_write(cx, buf)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_shutdown(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    fn framing(headers: &[(&str, &str)]) -> Result<BodyFraming> {
+        let headers: Vec<httparse::Header> = headers
+            .iter()
+            .map(|(name, value)| httparse::Header {
+                name,
+                value: value.as_bytes(),
+            })
+            .collect();
+        request_body_framing(&headers)
+    }
+
+    /// Feed a body to a fresh decoder a byte at a time and all at once
+    fn decode(framing: BodyFraming, body: &[u8]) -> io::Result<bool> {
+        let mut bytewise = BodyDecoder::new(framing);
+        for byte in body {
+            bytewise.feed(std::slice::from_ref(byte))?;
+        }
+        let mut whole = BodyDecoder::new(framing);
+        whole.feed(body)?;
+        assert_eq!(bytewise.is_complete(), whole.is_complete());
+        Ok(whole.is_complete())
+    }
+
+    #[test]
+    fn test_request_body_framing() {
+        assert_eq!(framing(&[("Host", "a")]).unwrap(), BodyFraming::Empty);
+        assert_eq!(
+            framing(&[("Content-Length", "42")]).unwrap(),
+            BodyFraming::Length(42)
+        );
+        assert_eq!(
+            framing(&[("Content-Length", "7, 7"), ("content-length", "7")]).unwrap(),
+            BodyFraming::Length(7)
+        );
+        assert_eq!(
+            framing(&[("Transfer-Encoding", "gzip, Chunked")]).unwrap(),
+            BodyFraming::Chunked
+        );
+        assert_eq!(
+            framing(&[
+                ("Transfer-Encoding", "gzip"),
+                ("Transfer-Encoding", "chunked")
+            ])
+            .unwrap(),
+            BodyFraming::Chunked
+        );
+
+        for rejected in [
+            &[("Transfer-Encoding", "chunked"), ("Content-Length", "5")][..],
+            &[("Transfer-Encoding", "gzip")],
+            &[("Transfer-Encoding", "chunked, gzip")],
+            &[("Transfer-Encoding", "chunked, chunked")],
+            &[("Content-Length", "5"), ("Content-Length", "6")],
+            &[("Content-Length", "+5")],
+            &[("Content-Length", "")],
+            &[("Content-Length", "99999999999999999999999")],
+        ] {
+            assert!(framing(rejected).is_err(), "{:?}", rejected);
+        }
+    }
+
+    #[test]
+    fn test_decode_length() {
+        assert!(decode(BodyFraming::Empty, b"").unwrap());
+        assert!(!decode(BodyFraming::Length(5), b"hell").unwrap());
+        assert!(decode(BodyFraming::Length(5), b"hello").unwrap());
+        // Bytes after the body belong to the next request
+        assert!(decode(BodyFraming::Length(5), b"helloGET / HTTP/1.1").unwrap());
+    }
+
+    #[test]
+    fn test_decode_chunked() {
+        let body = b"5\r\nhello\r\n1A;name=value\r\nabcdefghijklmnopqrstuvwxyz\r\n0\r\n\r\n";
+        assert!(decode(BodyFraming::Chunked, body).unwrap());
+        assert!(!decode(BodyFraming::Chunked, &body[..body.len() - 1]).unwrap());
+        assert!(decode(
+            BodyFraming::Chunked,
+            b"3\r\nabc\r\n0\r\nExpires: never\r\nX-Sum: 1\r\n\r\nGET / HTTP/1.1\r\n"
+        )
+        .unwrap());
+
+        for malformed in [
+            &b"\r\n"[..],
+            b"g\r\n",
+            b"5\nhello\r\n",
+            b"3\r\nabcd\r\n",
+            b"3\r\nabc\n0\r\n\r\n",
+            b"11111111111111111\r\n",
+            b"0\r\nX-Trailer: 1\n\r\n",
+        ] {
+            assert!(
+                decode(BodyFraming::Chunked, malformed).is_err(),
+                "{:?}",
+                String::from_utf8_lossy(malformed)
+            );
+        }
+
+        let mut long_extension = b"1;".to_vec();
+        long_extension.extend(std::iter::repeat_n(b'x', MAX_CHUNK_LINE));
+        assert!(decode(BodyFraming::Chunked, &long_extension).is_err());
+    }
+
+    #[test]
+    fn test_decode_collects_body_data() {
+        let body = b"5\r\nhello\r\n1;ext\r\n \r\n5\r\nworld\r\n0\r\nX-Sum: 1\r\n\r\nnext";
+        let mut decoder = BodyDecoder::new(BodyFraming::Chunked);
+        let mut data = Vec::new();
+        for piece in body.chunks(3) {
+            decoder.decode(piece, &mut data).unwrap();
+        }
+        assert!(decoder.is_complete());
+        assert_eq!(data, b"hello world");
+
+        let mut decoder = BodyDecoder::new(BodyFraming::Length(5));
+        let mut data = Vec::new();
+        decoder.decode(b"helloGET", &mut data).unwrap();
+        assert!(decoder.is_complete());
+        assert_eq!(data, b"hello");
+    }
+
+    #[tokio::test]
+    async fn test_body_stream_rejects_malformed_chunks() {
+        let (client, mut peer) = tokio::io::duplex(64);
+        let mut stream = BodyStream::new(client, BodyDecoder::new(BodyFraming::Chunked));
+
+        peer.write_all(b"3\r\nabc\r\n").await.unwrap();
+        let mut buf = [0u8; 8];
+        stream.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"3\r\nabc\r\n");
+        assert!(!stream.is_complete());
+
+        peer.write_all(b"zz\r\n").await.unwrap();
+        let error = stream.read(&mut buf).await.unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn test_feed_counts_body_bytes() {
+        // The end of the body arrives along with the start of a next request
+        let mut decoder = BodyDecoder::new(BodyFraming::Length(5));
+        assert_eq!(decoder.feed(b"hel").unwrap(), 3);
+        assert_eq!(decoder.feed(b"loGET / HTTP/1.1\r\n").unwrap(), 2);
+        assert!(decoder.is_complete());
+
+        let mut decoder = BodyDecoder::new(BodyFraming::Chunked);
+        assert_eq!(decoder.feed(b"2\r\nhi\r\n0\r\n\r\nGET").unwrap(), 12);
+        assert!(decoder.is_complete());
+        assert_eq!(decoder.feed(b"GET").unwrap(), 0);
+    }
+}
@@ -0,0 +1,276 @@
+/*!
+ * # Header Rewriting Module
+ *
+ * This module defines per-binding header rewriting rules and the functions
+ * that apply them to a raw HTTP message head (start line plus headers).
+ *
+ * Rules are configured through the API as JSON objects tagged by `op`:
+ *
+ * ```json
+ * [
+ *   {"op": "remove", "name": "Server"},
+ *   {"op": "set", "name": "X-Frame-Options", "value": "DENY"},
+ *   {"op": "add", "name": "Via", "value": "metaproxy"},
+ *   {"op": "rewrite", "name": "Location", "from": "http://backend:8080", "to": "https://example.com"}
+ * ]
+ * ```
+ */
+
+use crate::error::{Error, Result};
+use serde::{Deserialize, Serialize};
+use warp::http::header::{HeaderName, HeaderValue};
+
+/// A single header rewriting rule
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "lowercase")]
+pub enum HeaderRule {
+    /// Replace every occurrence of a header with a single value
+    Set {
+        /// The header name
+        name: String,
+        /// The new header value
+        value: String,
+    },
+    /// Append a header, keeping any existing occurrences
+    Add {
+        /// The header name
+        name: String,
+        /// The header value to append
+        value: String,
+    },
+    /// Remove every occurrence of a header
+    Remove {
+        /// The header name
+        name: String,
+    },
+    /// Replace a substring in the value of every occurrence of a header
+    Rewrite {
+        /// The header name
+        name: String,
+        /// The substring to look for
+        from: String,
+        /// The replacement text
+        to: String,
+    },
+}
+
+impl HeaderRule {
+    /// Get the name of the header this rule applies to
+    pub fn name(&self) -> &str {
+        match self {
+            HeaderRule::Set { name, .. }
+            | HeaderRule::Add { name, .. }
+            | HeaderRule::Remove { name }
+            | HeaderRule::Rewrite { name, .. } => name,
+        }
+    }
+
+    /// Check that the rule names a valid header and produces valid header values
+    ///
+    /// # Returns
+    ///
+    /// A result indicating whether the rule is valid, with a descriptive error if not
+    pub fn validate(&self) -> Result<()> {
+        HeaderName::from_bytes(self.name().as_bytes())
+            .map_err(|_| Error::Custom(format!("Invalid header name: {:?}", self.name())))?;
+
+        let values: &[&str] = match self {
+            HeaderRule::Set { value, .. } | HeaderRule::Add { value, .. } => &[value],
+            HeaderRule::Rewrite { from, to, .. } => {
+                if from.is_empty() {
+                    return Err(Error::Custom(format!(
+                        "Rewrite rule for header {} has an empty 'from' value",
+                        self.name()
+                    )));
+                }
+                &[from, to]
+            }
+            HeaderRule::Remove { .. } => &[],
+        };
+
+        for value in values {
+            HeaderValue::from_str(value).map_err(|_| {
+                Error::Custom(format!(
+                    "Invalid value for header {}: {:?}",
+                    self.name(),
+                    value
+                ))
+            })?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Validate a list of header rules
+///
+/// # Arguments
+///
+/// * `rules` - The rules to validate
+///
+/// # Returns
+///
+/// A result indicating success, or the error for the first invalid rule
+pub fn validate_rules(rules: &[HeaderRule]) -> Result<()> {
+    rules.iter().try_for_each(HeaderRule::validate)
+}
+
+/// Apply header rules, in order, to a list of header name/value pairs
+///
+/// Header names are matched case-insensitively.
+///
+/// # Arguments
+///
+/// * `headers` - The headers to modify
+/// * `rules` - The rules to apply
+pub fn apply_rules(headers: &mut Vec<(String, String)>, rules: &[HeaderRule]) {
+    for rule in rules {
+        let matches = |name: &str| name.eq_ignore_ascii_case(rule.name());
+        match rule {
+            HeaderRule::Set { name, value } => {
+                headers.retain(|(existing, _)| !matches(existing));
+                headers.push((name.clone(), value.clone()));
+            }
+            HeaderRule::Add { name, value } => {
+                headers.push((name.clone(), value.clone()));
+            }
+            HeaderRule::Remove { .. } => {
+                headers.retain(|(existing, _)| !matches(existing));
+            }
+            HeaderRule::Rewrite { from, to, .. } => {
+                for (existing, value) in headers.iter_mut() {
+                    if matches(existing) {
+                        *value = value.replace(from.as_str(), to);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Apply header rules to a raw HTTP message head
+///
+/// The head must contain the start line (request or status line) and the
+/// headers, terminated by an empty line. The start line is kept verbatim.
+///
+/// # Arguments
+///
+/// * `head` - The raw message head, including the terminating `\r\n\r\n`
+/// * `rules` - The rules to apply
+///
+/// # Returns
+///
+/// A result containing the rewritten message head
+pub fn rewrite_head(head: &[u8], rules: &[HeaderRule]) -> Result<Vec<u8>> {
+    let start_line_end = head
+        .windows(2)
+        .position(|window| window == b"\r\n")
+        .ok_or_else(|| Error::Custom("Invalid HTTP message head".to_string()))?;
+
+    let mut headers = Vec::with_capacity(16);
+    for line in head[start_line_end + 2..].split(|&b| b == b'\n') {
+        let line = line.strip_suffix(b"\r").unwrap_or(line);
+        if line.is_empty() {
+            continue;
+        }
+        let colon = line
+            .iter()
+            .position(|&b| b == b':')
+            .ok_or_else(|| Error::Custom("Invalid HTTP header line".to_string()))?;
+        let name = String::from_utf8_lossy(&line[..colon]).trim().to_string();
+        let value = String::from_utf8_lossy(&line[colon + 1..])
+            .trim()
+            .to_string();
+        headers.push((name, value));
+    }
+
+    apply_rules(&mut headers, rules);
+
+    let mut rewritten = Vec::with_capacity(head.len() + 64);
+    rewritten.extend_from_slice(&head[..start_line_end + 2]);
+    for (name, value) in headers {
+        rewritten.extend_from_slice(name.as_bytes());
+        rewritten.extend_from_slice(b": ");
+        rewritten.extend_from_slice(value.as_bytes());
+        rewritten.extend_from_slice(b"\r\n");
+    }
+    rewritten.extend_from_slice(b"\r\n");
+    Ok(rewritten)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rules(json: &str) -> Vec<HeaderRule> {
+        serde_json::from_str(json).unwrap()
+    }
+
+    #[test]
+    fn test_deserialize_rules() {
+        let parsed = rules(r#"[{"op": "remove", "name": "Server"}]"#);
+        assert_eq!(
+            parsed,
+            vec![HeaderRule::Remove {
+                name: "Server".to_string()
+            }]
+        );
+    }
+
+    #[test]
+    fn test_validate_rules() {
+        assert!(validate_rules(&rules(r#"[{"op": "set", "name": "X-Ok", "value": "1"}]"#)).is_ok());
+        assert!(validate_rules(&rules(
+            r#"[{"op": "set", "name": "Bad Name", "value": "1"}]"#
+        ))
+        .is_err());
+        assert!(validate_rules(&rules(
+            r#"[{"op": "add", "name": "X-Bad", "value": "a\nb"}]"#
+        ))
+        .is_err());
+        assert!(validate_rules(&rules(
+            r#"[{"op": "rewrite", "name": "Location", "from": "", "to": "x"}]"#
+        ))
+        .is_err());
+    }
+
+    #[test]
+    fn test_rewrite_response_head() {
+        let head = b"HTTP/1.1 302 Found\r\nServer: backend\r\nLocation: http://backend:8080/login\r\nSet-Cookie: a=1\r\n\r\n";
+        let rules = rules(
+            r#"[
+                {"op": "remove", "name": "server"},
+                {"op": "rewrite", "name": "Location", "from": "http://backend:8080", "to": "https://example.com"},
+                {"op": "add", "name": "Set-Cookie", "value": "b=2"},
+                {"op": "set", "name": "X-Proxy", "value": "metaproxy"}
+            ]"#,
+        );
+
+        let rewritten = String::from_utf8(rewrite_head(head, &rules).unwrap()).unwrap();
+        assert_eq!(
+            rewritten,
+            "HTTP/1.1 302 Found\r\n\
+             Location: https://example.com/login\r\n\
+             Set-Cookie: a=1\r\n\
+             Set-Cookie: b=2\r\n\
+             X-Proxy: metaproxy\r\n\
+             \r\n"
+        );
+    }
+
+    #[test]
+    fn test_set_replaces_all_occurrences() {
+        let mut headers = vec![
+            ("Accept-Encoding".to_string(), "gzip".to_string()),
+            ("accept-encoding".to_string(), "br".to_string()),
+        ];
+        apply_rules(
+            &mut headers,
+            &rules(r#"[{"op": "set", "name": "Accept-Encoding", "value": "identity"}]"#),
+        );
+        assert_eq!(
+            headers,
+            vec![("Accept-Encoding".to_string(), "identity".to_string())]
+        );
+    }
+}
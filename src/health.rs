@@ -0,0 +1,301 @@
+/*!
+ * # Health Check Module
+ *
+ * This module probes the weighted upstreams of a binding in the background,
+ * so that connections are not sent to upstreams that are down.
+ *
+ * Health checks are configured per binding:
+ *
+ * ```json
+ * {"interval": 10, "timeout": 2, "when_all_unhealthy": "fail"}
+ * ```
+ *
+ * Every `interval` seconds each upstream of `upstreams` is probed by opening a
+ * connection to it, which has to succeed within `timeout` seconds. Upstreams
+ * failing their probe are skipped by the [`Balancer`] until a later probe
+ * succeeds, without restarting the binding. When every upstream is unhealthy,
+ * `when_all_unhealthy` decides whether new connections are closed or sent to
+ * an upstream anyway.
+ */
+
+use crate::balancer::Balancer;
+use crate::dns::Resolver;
+use crate::error::{Error, Result};
+use crate::listeners::LocalListeners;
+use crate::proxy::connect_upstream;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::net::IpAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Mutex;
+use tokio::task::JoinSet;
+use tokio::time::timeout;
+use url::Url;
+
+/// The default number of seconds between two probes of an upstream
+fn default_interval() -> u64 {
+    10
+}
+
+/// The default number of seconds a probe may take
+fn default_timeout() -> u64 {
+    2
+}
+
+/// How a binding probes its upstreams
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HealthCheck {
+    /// The number of seconds between two probes of each upstream
+    #[serde(default = "default_interval")]
+    pub interval: u64,
+    /// The number of seconds a probe may take before the upstream counts as unhealthy
+    #[serde(default = "default_timeout")]
+    pub timeout: u64,
+    /// What new connections do when every upstream is unhealthy
+    #[serde(default)]
+    pub when_all_unhealthy: AllUnhealthy,
+}
+
+impl Default for HealthCheck {
+    fn default() -> Self {
+        HealthCheck {
+            interval: default_interval(),
+            timeout: default_timeout(),
+            when_all_unhealthy: AllUnhealthy::default(),
+        }
+    }
+}
+
+impl HealthCheck {
+    /// Check that the interval and timeout are positive
+    ///
+    /// # Returns
+    ///
+    /// A result indicating whether the settings are valid, with a descriptive error if not
+    pub fn validate(&self) -> Result<()> {
+        if self.interval == 0 || self.timeout == 0 {
+            return Err(Error::Custom(
+                "health_check interval and timeout must be at least 1 second".to_string(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// What new connections do when every upstream of a binding is unhealthy
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AllUnhealthy {
+    /// Close new connections until an upstream recovers
+    #[default]
+    Fail,
+    /// Pick an upstream as if none were unhealthy
+    TryAnyway,
+}
+
+/// The outcome of probing an upstream
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ProbeResult {
+    /// The upstream server address
+    pub url: String,
+    /// Whether the upstream accepted a connection in time
+    pub healthy: bool,
+    /// Why the probe failed, if it did
+    pub error: Option<String>,
+}
+
+/// Probe an upstream by opening a connection to it
+///
+/// # Arguments
+///
+/// * `url` - The upstream server address
+/// * `probe_timeout` - How long the connection may take
+/// * `resolver` - How the upstream's host is resolved
+/// * `source` - The local address the connection is opened from, if not left to the system
+/// * `listeners` - The addresses metaproxy listens on, which fail the probe
+///
+/// # Returns
+///
+/// The outcome of the probe
+pub async fn probe(
+    url: &str,
+    probe_timeout: Duration,
+    resolver: &Resolver,
+    source: Option<IpAddr>,
+    listeners: &LocalListeners,
+) -> ProbeResult {
+    let result = match Url::parse(url) {
+        Ok(parsed) => {
+            match timeout(
+                probe_timeout,
+                connect_upstream(&parsed, resolver, source, listeners),
+            )
+            .await
+            {
+                Ok(Ok(_)) => Ok(()),
+                Ok(Err(e)) => Err(e.to_string()),
+                Err(_) => Err(format!("no connection after {:?}", probe_timeout)),
+            }
+        }
+        Err(e) => Err(e.to_string()),
+    };
+    ProbeResult {
+        url: url.to_string(),
+        healthy: result.is_ok(),
+        error: result.err(),
+    }
+}
+
+/// Probe every upstream of a balancer at once and record which ones are healthy
+///
+/// # Arguments
+///
+/// * `port` - The port of the binding, for log messages
+/// * `balancer` - The binding's upstreams, updated with the outcomes
+/// * `check` - How the upstreams are probed
+/// * `resolver` - How upstream hosts are resolved
+/// * `source` - The local address probes are opened from, if not left to the system
+/// * `listeners` - The addresses metaproxy listens on, which fail the probes
+///
+/// # Returns
+///
+/// The outcome of each probe, in the order of the upstreams
+pub async fn check_upstreams(
+    port: u16,
+    balancer: &Mutex<Balancer>,
+    check: &HealthCheck,
+    resolver: &Resolver,
+    source: Option<IpAddr>,
+    listeners: &LocalListeners,
+) -> Vec<ProbeResult> {
+    let urls: Vec<String> = balancer
+        .lock()
+        .await
+        .targets()
+        .iter()
+        .map(|target| target.url.clone())
+        .collect();
+
+    let mut probes = JoinSet::new();
+    for (index, url) in urls.into_iter().enumerate() {
+        let resolver = resolver.clone();
+        let listeners = listeners.clone();
+        let probe_timeout = Duration::from_secs(check.timeout);
+        probes.spawn(async move {
+            let result = probe(&url, probe_timeout, &resolver, source, &listeners).await;
+            (index, result)
+        });
+    }
+    let mut results = Vec::with_capacity(probes.len());
+    while let Some(Ok(result)) = probes.join_next().await {
+        results.push(result);
+    }
+    results.sort_by_key(|(index, _)| *index);
+    let results: Vec<ProbeResult> = results.into_iter().map(|(_, result)| result).collect();
+
+    let mut balancer = balancer.lock().await;
+    for result in &results {
+        if !balancer.set_healthy(&result.url, result.healthy) {
+            continue;
+        }
+        match &result.error {
+            Some(error) => warn!(
+                "Upstream {} of port {} is unhealthy: {}",
+                result.url, port, error
+            ),
+            None => info!("Upstream {} of port {} is healthy again", result.url, port),
+        }
+    }
+    results
+}
+
+/// Probe a binding's upstreams at every interval, for as long as the binding runs
+///
+/// # Arguments
+///
+/// * `port` - The port of the binding, for log messages
+/// * `balancer` - The binding's upstreams, updated with the outcomes
+/// * `check` - How and how often the upstreams are probed
+/// * `resolver` - How upstream hosts are resolved
+/// * `source` - The local address probes are opened from, if not left to the system
+/// * `listeners` - The addresses metaproxy listens on, which fail the probes
+pub async fn run_health_checks(
+    port: u16,
+    balancer: Arc<Mutex<Balancer>>,
+    check: HealthCheck,
+    resolver: Resolver,
+    source: Option<IpAddr>,
+    listeners: LocalListeners,
+) {
+    let mut interval = tokio::time::interval(Duration::from_secs(check.interval));
+    loop {
+        interval.tick().await;
+        check_upstreams(port, &balancer, &check, &resolver, source, &listeners).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::balancer::{Strategy, UpstreamTarget};
+    use tokio::net::TcpListener;
+
+    #[test]
+    fn test_health_check_defaults() {
+        let check: HealthCheck = serde_json::from_str("{}").unwrap();
+        assert_eq!(check, HealthCheck::default());
+        assert_eq!(check.when_all_unhealthy, AllUnhealthy::Fail);
+        assert!(check.validate().is_ok());
+
+        let check: HealthCheck =
+            serde_json::from_str(r#"{"interval": 0, "when_all_unhealthy": "try_anyway"}"#).unwrap();
+        assert_eq!(check.when_all_unhealthy, AllUnhealthy::TryAnyway);
+        assert!(check.validate().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_check_upstreams_marks_unreachable_upstreams() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let up = format!("http://{}", listener.local_addr().unwrap());
+        // A port that was just released refuses connections
+        let down = {
+            let closed = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            format!("http://{}", closed.local_addr().unwrap())
+        };
+        let balancer = Mutex::new(Balancer::new(
+            vec![
+                UpstreamTarget {
+                    url: up.clone(),
+                    weight: 1,
+                },
+                UpstreamTarget {
+                    url: down.clone(),
+                    weight: 1,
+                },
+            ],
+            Strategy::RoundRobin,
+        ));
+
+        let results = check_upstreams(
+            9000,
+            &balancer,
+            &HealthCheck::default(),
+            &Resolver::System,
+            None,
+            &LocalListeners::default(),
+        )
+        .await;
+        assert_eq!(results[0].url, up);
+        assert!(results[0].healthy);
+        assert_eq!(results[1].url, down);
+        assert!(!results[1].healthy);
+        assert!(results[1].error.is_some());
+
+        // Only the healthy upstream is selected
+        let mut balancer = balancer.lock().await;
+        for _ in 0..4 {
+            assert_eq!(balancer.select().unwrap().0, up);
+        }
+    }
+}
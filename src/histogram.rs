@@ -0,0 +1,183 @@
+/*!
+ * # Histogram Module
+ *
+ * This module records how long proxied connections live, for capacity
+ * planning.
+ *
+ * Each binding keeps one [`DurationHistogram`] for CONNECT tunnels and one for
+ * plain HTTP connections, since tunnels usually outlive single requests by
+ * orders of magnitude. A histogram counts durations into fixed buckets, so its
+ * memory stays the same however many connections it has seen, and estimates
+ * percentiles by interpolating within the bucket they fall into:
+ *
+ * ```json
+ * {"count": 1520, "p50_ms": 84.2, "p90_ms": 412.7, "p99_ms": 2210.0, "max_ms": 61234.0}
+ * ```
+ */
+
+use serde::Serialize;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+/// The upper bounds of the buckets, in milliseconds; longer durations fall into a last, unbounded bucket
+const BUCKET_BOUNDS_MS: [u64; 16] = [
+    10, 50, 100, 250, 500, 1_000, 2_500, 5_000, 10_000, 30_000, 60_000, 300_000, 900_000,
+    1_800_000, 3_600_000, 14_400_000,
+];
+
+/// Counts of durations in fixed buckets
+#[derive(Debug, Default)]
+pub struct DurationHistogram {
+    /// The number of durations in each bucket, the last one being unbounded
+    buckets: [AtomicU64; BUCKET_BOUNDS_MS.len() + 1],
+    /// The number of durations recorded
+    count: AtomicU64,
+    /// The longest duration recorded, in microseconds
+    max_micros: AtomicU64,
+}
+
+/// Percentiles of the durations in a histogram
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DurationSummary {
+    /// The number of durations recorded
+    pub count: u64,
+    /// The estimated median, in milliseconds
+    pub p50_ms: f64,
+    /// The estimated 90th percentile, in milliseconds
+    pub p90_ms: f64,
+    /// The estimated 99th percentile, in milliseconds
+    pub p99_ms: f64,
+    /// The longest duration recorded, in milliseconds
+    pub max_ms: f64,
+}
+
+impl DurationHistogram {
+    /// Count a duration into its bucket
+    ///
+    /// # Arguments
+    ///
+    /// * `duration` - The duration to record
+    pub fn record(&self, duration: Duration) {
+        let millis = u64::try_from(duration.as_millis()).unwrap_or(u64::MAX);
+        let bucket = BUCKET_BOUNDS_MS
+            .iter()
+            .position(|bound| millis < *bound)
+            .unwrap_or(BUCKET_BOUNDS_MS.len());
+        self.buckets[bucket].fetch_add(1, Ordering::Relaxed);
+        self.count.fetch_add(1, Ordering::Relaxed);
+        let micros = u64::try_from(duration.as_micros()).unwrap_or(u64::MAX);
+        self.max_micros.fetch_max(micros, Ordering::Relaxed);
+    }
+
+    /// Get the number of durations recorded
+    pub fn count(&self) -> u64 {
+        self.count.load(Ordering::Relaxed)
+    }
+
+    /// Estimate a percentile of the recorded durations
+    ///
+    /// The estimate assumes the durations of a bucket are spread evenly
+    /// across it, and never exceeds the longest duration recorded.
+    ///
+    /// # Arguments
+    ///
+    /// * `quantile` - The percentile as a fraction, from 0 to 1
+    ///
+    /// # Returns
+    ///
+    /// The estimated duration, or None if no duration was recorded
+    pub fn percentile(&self, quantile: f64) -> Option<Duration> {
+        let counts: Vec<u64> = self
+            .buckets
+            .iter()
+            .map(|bucket| bucket.load(Ordering::Relaxed))
+            .collect();
+        let total: u64 = counts.iter().sum();
+        if total == 0 {
+            return None;
+        }
+        let max = Duration::from_micros(self.max_micros.load(Ordering::Relaxed));
+        let rank = quantile.clamp(0.0, 1.0) * total as f64;
+
+        let mut below = 0;
+        for (index, &count) in counts.iter().enumerate() {
+            if count == 0 || ((below + count) as f64) < rank {
+                below += count;
+                continue;
+            }
+            let lower = match index {
+                0 => 0,
+                index => BUCKET_BOUNDS_MS[index - 1],
+            };
+            let upper = match BUCKET_BOUNDS_MS.get(index) {
+                Some(upper) => *upper as f64,
+                // The unbounded bucket ends at the longest duration
+                None => max.as_secs_f64() * 1000.0,
+            };
+            let fraction = (rank - below as f64) / count as f64;
+            let millis = lower as f64 + (upper - lower as f64).max(0.0) * fraction;
+            return Some(Duration::from_secs_f64(millis / 1000.0).min(max));
+        }
+        Some(max)
+    }
+
+    /// Summarize the recorded durations
+    ///
+    /// # Returns
+    ///
+    /// The count, estimated percentiles and longest duration, or None if no duration was recorded
+    pub fn summary(&self) -> Option<DurationSummary> {
+        let millis = |quantile| Some(self.percentile(quantile)?.as_secs_f64() * 1000.0);
+        Some(DurationSummary {
+            count: self.count(),
+            p50_ms: millis(0.5)?,
+            p90_ms: millis(0.9)?,
+            p99_ms: millis(0.99)?,
+            max_ms: Duration::from_micros(self.max_micros.load(Ordering::Relaxed)).as_secs_f64()
+                * 1000.0,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_percentiles_interpolate_within_buckets() {
+        let histogram = DurationHistogram::default();
+        assert!(histogram.summary().is_none());
+
+        // 90 connections between 100 and 250 ms, 10 of a few minutes
+        for _ in 0..90 {
+            histogram.record(Duration::from_millis(200));
+        }
+        for _ in 0..10 {
+            histogram.record(Duration::from_secs(200));
+        }
+
+        let summary = histogram.summary().unwrap();
+        assert_eq!(summary.count, 100);
+        // Half the connections are estimated halfway through 100 to 250 ms
+        assert!((summary.p50_ms - 183.3).abs() < 0.1, "{}", summary.p50_ms);
+        assert!(summary.p90_ms <= 250.0);
+        // Estimates are capped at the longest connection
+        assert_eq!(summary.p99_ms, 200_000.0);
+        assert_eq!(summary.max_ms, 200_000.0);
+    }
+
+    #[test]
+    fn test_unbounded_bucket_ends_at_the_longest_duration() {
+        let histogram = DurationHistogram::default();
+        histogram.record(Duration::from_secs(5 * 3600));
+        histogram.record(Duration::from_secs(6 * 3600));
+
+        assert_eq!(
+            histogram.percentile(1.0),
+            Some(Duration::from_secs(6 * 3600))
+        );
+        let median = histogram.percentile(0.5).unwrap();
+        assert!(median > Duration::from_secs(4 * 3600) && median < Duration::from_secs(6 * 3600));
+        assert_eq!(histogram.count(), 2);
+    }
+}
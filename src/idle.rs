@@ -0,0 +1,328 @@
+/*!
+ * # Idle Connection Module
+ *
+ * This module closes proxied connections that stopped carrying data, such as
+ * CONNECT tunnels whose peers went away without closing them.
+ *
+ * When `--idle-timeout` is set, every accepted connection is registered with
+ * an [`IdleTracker`] along with its cancellation token, and its client stream
+ * is wrapped in an [`ActivityStream`] that notes when bytes last went through
+ * it in either direction. A background reaper scans the registered
+ * connections every `--idle-connection-scan-interval` seconds and cancels
+ * those idle for longer than the timeout, which closes both of their streams.
+ * The number of connections it closed is logged and counted for `/health`.
+ */
+
+use log::info;
+use std::collections::HashMap;
+use std::io;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio_util::sync::CancellationToken;
+
+/// When a single connection last carried data
+#[derive(Debug)]
+pub struct ConnectionActivity {
+    /// When the tracker the connection is registered with was created
+    started: Instant,
+    /// Milliseconds after `started` that the connection last carried data
+    last_active: AtomicU64,
+}
+
+impl ConnectionActivity {
+    /// Mark the connection as active now
+    pub fn touch(&self) {
+        let millis = u64::try_from(self.started.elapsed().as_millis()).unwrap_or(u64::MAX);
+        self.last_active.store(millis, Ordering::Relaxed);
+    }
+
+    /// Get how long the connection has gone without carrying data
+    pub fn idle_for(&self) -> Duration {
+        self.started.elapsed().saturating_sub(Duration::from_millis(
+            self.last_active.load(Ordering::Relaxed),
+        ))
+    }
+}
+
+/// A connection registered with an [`IdleTracker`]
+#[derive(Debug)]
+struct Tracked {
+    /// When the connection last carried data
+    activity: Arc<ConnectionActivity>,
+    /// Token that closes the connection when cancelled
+    cancel: CancellationToken,
+}
+
+/// The active proxied connections of every binding and when each last carried data
+#[derive(Debug)]
+pub struct IdleTracker {
+    /// When the tracker was created, on the monotonic clock
+    started: Instant,
+    /// The identifier given to the next registered connection
+    next_id: AtomicU64,
+    /// The registered connections by identifier
+    connections: Mutex<HashMap<u64, Tracked>>,
+    /// Connections closed for being idle since the tracker was created
+    reaped: AtomicU64,
+}
+
+impl Default for IdleTracker {
+    fn default() -> Self {
+        IdleTracker {
+            started: Instant::now(),
+            next_id: AtomicU64::new(0),
+            connections: Mutex::new(HashMap::new()),
+            reaped: AtomicU64::new(0),
+        }
+    }
+}
+
+impl IdleTracker {
+    /// Register a connection, active as of now
+    ///
+    /// # Arguments
+    ///
+    /// * `cancel` - Token that closes the connection when cancelled
+    ///
+    /// # Returns
+    ///
+    /// A guard that unregisters the connection when dropped
+    pub fn register(self: &Arc<Self>, cancel: CancellationToken) -> TrackedConnection {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let activity = Arc::new(ConnectionActivity {
+            started: self.started,
+            last_active: AtomicU64::new(0),
+        });
+        activity.touch();
+        self.connections.lock().unwrap().insert(
+            id,
+            Tracked {
+                activity: activity.clone(),
+                cancel,
+            },
+        );
+        TrackedConnection {
+            id,
+            tracker: self.clone(),
+            activity,
+        }
+    }
+
+    /// Cancel every connection that has been idle for longer than a timeout
+    ///
+    /// Connections that were already cancelled are skipped, so each one is
+    /// counted once.
+    ///
+    /// # Arguments
+    ///
+    /// * `idle_timeout` - Idle time after which a connection is closed
+    ///
+    /// # Returns
+    ///
+    /// The number of connections cancelled
+    pub fn reap(&self, idle_timeout: Duration) -> usize {
+        let connections = self.connections.lock().unwrap();
+        let mut reaped = 0;
+        for tracked in connections.values() {
+            if !tracked.cancel.is_cancelled() && tracked.activity.idle_for() > idle_timeout {
+                tracked.cancel.cancel();
+                reaped += 1;
+            }
+        }
+        self.reaped.fetch_add(reaped as u64, Ordering::Relaxed);
+        reaped
+    }
+
+    /// Get the number of registered connections
+    pub fn len(&self) -> usize {
+        self.connections.lock().unwrap().len()
+    }
+
+    /// Check whether no connection is registered
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Get the number of connections closed for being idle since the tracker was created
+    pub fn reaped(&self) -> u64 {
+        self.reaped.load(Ordering::Relaxed)
+    }
+}
+
+/// A connection's registration with an [`IdleTracker`], removed when dropped
+#[derive(Debug)]
+pub struct TrackedConnection {
+    /// The connection's identifier in the tracker
+    id: u64,
+    /// The tracker the connection is registered with
+    tracker: Arc<IdleTracker>,
+    /// When the connection last carried data
+    activity: Arc<ConnectionActivity>,
+}
+
+impl TrackedConnection {
+    /// Get the connection's activity, to be updated by its streams
+    pub fn activity(&self) -> Arc<ConnectionActivity> {
+        self.activity.clone()
+    }
+}
+
+impl Drop for TrackedConnection {
+    fn drop(&mut self) {
+        self.tracker.connections.lock().unwrap().remove(&self.id);
+    }
+}
+
+/// Close idle connections every `interval` for as long as the server runs
+///
+/// # Arguments
+///
+/// * `tracker` - The connections to check
+/// * `idle_timeout` - Idle time after which a connection is closed
+/// * `interval` - Time between scans for idle connections
+pub async fn run_idle_reaper(
+    tracker: Arc<IdleTracker>,
+    idle_timeout: Duration,
+    interval: Duration,
+) {
+    let mut ticker = tokio::time::interval(interval);
+    ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    loop {
+        ticker.tick().await;
+        let reaped = tracker.reap(idle_timeout);
+        if reaped > 0 {
+            info!(
+                "Closed {} connections idle for over {} seconds",
+                reaped,
+                idle_timeout.as_secs()
+            );
+        }
+    }
+}
+
+/// A client stream that marks its connection as active whenever bytes go through it
+///
+/// Without an activity, reads and writes pass through unchanged.
+#[derive(Debug)]
+pub struct ActivityStream<S> {
+    /// The wrapped client stream
+    inner: S,
+    /// The activity updated by reads and writes, if the connection is tracked
+    activity: Option<Arc<ConnectionActivity>>,
+}
+
+impl<S> ActivityStream<S> {
+    /// Wrap a client stream
+    ///
+    /// # Arguments
+    ///
+    /// * `inner` - The client stream
+    /// * `activity` - The activity to update, or `None` to track nothing
+    ///
+    /// # Returns
+    ///
+    /// A new `ActivityStream` over `inner`
+    pub fn new(inner: S, activity: Option<Arc<ConnectionActivity>>) -> Self {
+        ActivityStream { inner, activity }
+    }
+
+    /// Get the wrapped client stream
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+}
+
+impl<S: AsyncRead + Unpin> AsyncRead for ActivityStream<S> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        let before = buf.filled().len();
+        let result = Pin::new(&mut this.inner).poll_read(cx, buf);
+        if let (Poll::Ready(Ok(())), Some(activity)) = (&result, &this.activity) {
+            if buf.filled().len() > before {
+                activity.touch();
+            }
+        }
+        result
+    }
+}
+
+impl<S: AsyncWrite + Unpin> AsyncWrite for ActivityStream<S> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        let result = Pin::new(&mut this.inner).poll_write(cx, buf);
+        if let (Poll::Ready(Ok(written)), Some(activity)) = (&result, &this.activity) {
+            if *written > 0 {
+                activity.touch();
+            }
+        }
+        result
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_shutdown(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    #[test]
+    fn test_reap_cancels_idle_connections() {
+        let tracker = Arc::new(IdleTracker::default());
+        let idle = CancellationToken::new();
+        let busy = CancellationToken::new();
+        let _idle_connection = tracker.register(idle.clone());
+        let busy_connection = tracker.register(busy.clone());
+        assert_eq!(tracker.len(), 2);
+
+        std::thread::sleep(Duration::from_millis(30));
+        busy_connection.activity().touch();
+        assert_eq!(tracker.reap(Duration::from_millis(20)), 1);
+        assert!(idle.is_cancelled());
+        assert!(!busy.is_cancelled());
+
+        // A connection already cancelled is not counted again
+        assert_eq!(tracker.reap(Duration::from_millis(20)), 0);
+        assert_eq!(tracker.reaped(), 1);
+
+        drop(busy_connection);
+        assert_eq!(tracker.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_activity_stream_touches_on_traffic() {
+        let tracker = Arc::new(IdleTracker::default());
+        let connection = tracker.register(CancellationToken::new());
+        let (mut client, server) = tokio::io::duplex(64);
+        let mut stream = ActivityStream::new(server, Some(connection.activity()));
+
+        std::thread::sleep(Duration::from_millis(20));
+        assert!(connection.activity().idle_for() >= Duration::from_millis(20));
+        client.write_all(b"ping").await.unwrap();
+        let mut buf = [0u8; 4];
+        stream.read_exact(&mut buf).await.unwrap();
+        assert!(connection.activity().idle_for() < Duration::from_millis(20));
+
+        std::thread::sleep(Duration::from_millis(20));
+        stream.write_all(b"pong").await.unwrap();
+        assert!(connection.activity().idle_for() < Duration::from_millis(20));
+    }
+}
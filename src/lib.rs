@@ -11,13 +11,24 @@
  * - **Modular Architecture** 🧩: Clean separation of concerns for better maintainability and testability
  * - **Async I/O** ⚡: Built on Tokio for high-performance asynchronous I/O
  * - **Request Timeouts** ⏱️: Configurable timeouts for upstream requests
+ * - **Config File** 📄: Load bindings from a JSON file and reload it on SIGHUP
  *
  * ## Modules 📦
  *
  * - `api`: API routes and handlers for managing proxy bindings
+ * - `auth`: Per-binding authentication to upstream proxies
+ * - `balancer`: Weighted load balancing across a binding's upstreams
+ * - `capture`: Opt-in debug captures of proxied traffic
  * - `config`: Configuration handling and command line argument parsing
  * - `error`: Error types and handling
+ * - `events`: Notifications of binding changes and connection outcomes
+ * - `framing`: Framing of proxied request bodies
+ * - `headers`: Per-binding header rewriting rules
+ * - `idle`: Closing of proxied connections that stopped carrying data
+ * - `listeners`: Refusal of connections looping back into the proxy's own listeners
  * - `proxy`: Core proxy functionality including request handling and connection management
+ * - `rate_limit`: Rate limiting of management API requests
+ * - `signing`: HMAC signatures of management API requests
  *
  * ## Quick Start 🚀
  *
@@ -31,7 +42,8 @@
  *     // or create a custom configuration
  *     let config = Config {
  *         bind: "127.0.0.1:9999".to_string(),
- *         request_timeout: 30, // seconds
+ *         connect_timeout: 10, // seconds
+ *         ..Default::default()
  *     };
  *
  *     // Run the proxy server
@@ -73,24 +85,67 @@
  * The proxy server uses Tokio for asynchronous I/O and Warp for the REST API.
  */
 
+// The OpenAPI document is a single `json!` literal deeper than the default limit
+#![recursion_limit = "256"]
+
 /// API module for managing proxy bindings via REST endpoints
 pub mod api;
+/// In-memory audit log of changes made through the management API
+pub mod audit;
+/// Authentication of bindings to their upstream proxies
+pub mod auth;
+/// Load balancing of connections across several upstreams
+pub mod balancer;
+/// Debug captures of the bytes proxied by a binding
+pub mod capture;
+/// Gzip compression of proxied HTTP responses
+pub mod compression;
 /// Configuration module for handling command line arguments and settings
 pub mod config;
+/// Resolution of upstream and target hosts
+pub mod dns;
 /// Error handling module with custom error types
 pub mod error;
+/// Notifications of changes to proxy bindings and of proxied connection outcomes
+pub mod events;
+/// Framing of the bodies of proxied HTTP requests
+pub mod framing;
+/// Header rewriting rules applied to proxied HTTP messages
+pub mod headers;
+/// Background health checks of weighted upstreams
+pub mod health;
+/// Distributions of how long proxied connections live
+pub mod histogram;
+/// Reaping of proxied connections that stopped carrying data
+pub mod idle;
+/// Detection of connections looping back to metaproxy's own listeners
+pub mod listeners;
 /// Core proxy functionality module for handling connections and data transfer
 pub mod proxy;
+/// Token-bucket rate limiting of management API requests
+pub mod rate_limit;
+/// Routing of requests to upstreams by target host
+pub mod routing;
+/// HMAC signing of management API requests
+pub mod signing;
 
-use log::{info, warn};
+use log::{debug, error, info, warn, LevelFilter};
 use std::collections::HashMap;
+use std::future::Future;
 use std::sync::Arc;
-use tokio::sync::Mutex;
+use std::time::{Duration, Instant};
+use tokio::sync::{oneshot, Mutex};
 
-use crate::api::create_routes;
-use crate::config::Config;
-use crate::error::Result;
-use crate::proxy::BindingMap;
+use crate::api::{create_routes, ApiConfig};
+use crate::audit::AuditLog;
+use crate::config::{load_bindings, Config};
+use crate::error::{Error, Result};
+use crate::events::{deliver_to_webhook, log_connection_outcomes, EventBus};
+use crate::idle::{run_idle_reaper, IdleTracker};
+use crate::listeners::{ListenerKind, LocalListeners};
+use crate::proxy::{
+    drain_bindings, reconcile_bindings, remove_idle_bindings, BindingMap, ProxyContext,
+};
 
 /// Run the metaproxy server with the given configuration
 ///
@@ -118,9 +173,28 @@ use crate::proxy::BindingMap;
 /// }
 /// ```
 pub async fn run(config: Config) -> Result<()> {
-    info!("Starting proxy server on {}", config.bind);
+    init_logger(config.log_level);
+    info!("Starting proxy server with configuration: {:?}", config);
+
+    // Bind the API first, so a taken address fails startup before anything runs
+    let api_listener = bind_api(&config).await?;
+
+    // Never proxy connections to the API
+    let listeners = LocalListeners::default();
+    let _api_registration = match &api_listener {
+        ApiListener::Tcp(listener) => {
+            Some(listeners.register(listener.local_addr()?, ListenerKind::Api))
+        }
+        #[cfg(unix)]
+        ApiListener::Unix(..) => None,
+    };
 
     // Log the timeout configuration
+    if let Some(timeout) = config.get_connect_timeout() {
+        info!("Connect timeout set to {} seconds", timeout.as_secs());
+    } else {
+        info!("No connect timeout configured");
+    }
     if let Some(timeout) = config.get_request_timeout() {
         info!("Request timeout set to {} seconds", timeout.as_secs());
     } else {
@@ -131,27 +205,491 @@ pub async fn run(config: Config) -> Result<()> {
     let bindings: BindingMap = Arc::new(Mutex::new(HashMap::new()));
     info!("Initialized empty binding map");
 
-    // Store the timeout configuration for use in proxy handlers
-    let timeout = config.get_request_timeout();
+    // Share one connection limit between every binding
+    let connection_limit = config.get_connection_limit();
+    if let Some(limit) = &connection_limit {
+        info!(
+            "Limiting proxied connections to {} server-wide",
+            limit.max()
+        );
+    }
+
+    if config.restrict_listen_loopback {
+        info!("Restricting proxy listeners to the loopback interface");
+    }
+
+    if let Some(dns_server) = config.dns_server {
+        info!("Resolving upstream hosts through nameserver {}", dns_server);
+    }
+
+    // Track the activity of every proxied connection if idle ones are closed
+    let idle_tracker = config
+        .get_idle_timeout()
+        .map(|_| Arc::new(IdleTracker::default()));
+
+    // Settings shared by the API and every proxy listener
+    let context = Arc::new(ProxyContext {
+        bindings: bindings.clone(),
+        connect_timeout: config.get_connect_timeout(),
+        request_timeout: config.get_request_timeout(),
+        copy_buffer_size: config.copy_buffer_size,
+        max_header_size: config.max_header_size,
+        max_request_line: config.max_request_line_bytes,
+        max_upstream_header_size: config.max_upstream_header_size,
+        host_header: config.host_header,
+        http10_requests: config.http10_requests,
+        socket_options: config.get_socket_options(),
+        connection_limit,
+        listen_ip: config.get_listen_ip(),
+        reuse_port: config.reuse_port,
+        listen_backlog: config.listen_backlog,
+        bind_concurrency: config.bind_concurrency,
+        resolver: config.get_resolver(),
+        connect_source: config.connect_bind,
+        listeners,
+        events: EventBus::default(),
+        outcomes: EventBus::default(),
+        idle_tracker: idle_tracker.clone(),
+    });
+
+    // Write the access log line of each proxied connection once it closed
+    tokio::spawn(log_connection_outcomes(context.outcomes.subscribe()));
+
+    // Deliver binding events to the webhook, subscribing before any binding
+    // is created so none are missed
+    if let Some(url) = config.event_webhook.clone() {
+        info!("Delivering binding events to {}", url);
+        tokio::spawn(deliver_to_webhook(context.events.subscribe(), url));
+    }
 
-    // Create API routes
-    let routes = create_routes(bindings.clone(), timeout);
+    // Create the bindings listed in the config file and reload it on SIGHUP
+    if let Some(path) = config.config_file.clone() {
+        let specs = load_bindings(&path)?;
+        let started = Instant::now();
+        let summary = reconcile_bindings(&context, &specs).await;
+        info!(
+            "Loaded {} of {} bindings from config file {} in {:?}",
+            summary.created.len(),
+            specs.len(),
+            path,
+            started.elapsed()
+        );
+        if !summary.failed.is_empty() {
+            warn!("Failed to create bindings on ports {:?}", summary.failed);
+        }
+        tokio::spawn(reload_on_sighup(path, context.clone()));
+    }
+
+    // Delete bindings once they have been idle for longer than the TTL
+    if let Some(ttl) = config.get_idle_binding_ttl() {
+        let interval = config.get_idle_scan_interval();
+        info!(
+            "Deleting bindings idle for over {} seconds, checking every {} seconds",
+            ttl.as_secs(),
+            interval.as_secs()
+        );
+        tokio::spawn(remove_idle_bindings_periodically(
+            context.clone(),
+            ttl,
+            interval,
+        ));
+    }
+
+    // Close connections once they have been idle for longer than the timeout
+    if let (Some(idle_timeout), Some(tracker)) = (config.get_idle_timeout(), idle_tracker) {
+        let interval = config.get_idle_connection_scan_interval();
+        info!(
+            "Closing connections idle for over {} seconds, checking every {} seconds",
+            idle_timeout.as_secs(),
+            interval.as_secs()
+        );
+        tokio::spawn(run_idle_reaper(tracker, idle_timeout, interval));
+    }
+
+    // Create API routes, requiring signed /proxy requests if a secret is set
+    let signer = config.get_request_signer();
+    if signer.is_some() {
+        info!(
+            "Requiring signed /proxy requests (max clock skew {}s)",
+            config.api_hmac_max_skew
+        );
+    }
+    let rate_limiter = config.get_rate_limiter();
+    if let Some(limiter) = &rate_limiter {
+        info!(
+            "Limiting API requests to {} per second{}",
+            limiter.rate(),
+            if limiter.per_client() {
+                " per client"
+            } else {
+                ""
+            }
+        );
+    }
+    if let Some(max) = config.get_max_bindings() {
+        info!("Limiting the API to {} bindings", max);
+    }
+    let connection_limit = context.connection_limit.clone();
+    let routes = create_routes(
+        context,
+        ApiConfig {
+            max_bindings: config.get_max_bindings(),
+            signer,
+            rate_limiter,
+            audit_log: AuditLog::new(config.audit_log_size),
+        },
+    );
     info!("Created API routes");
 
-    // Start the API server on the specified bind address.
+    // Serve the API until the shutdown signal is received
+    let shutdown_started = serve_api(routes, api_listener, config.get_shutdown_timeout()).await;
+
+    // Stop every proxy listener and let in-flight connections finish,
+    // within what is left of the shutdown timeout
+    let drain_timeout = config.get_remaining_drain_timeout(shutdown_started.elapsed());
+    let cancelled = drain_bindings(&bindings, drain_timeout).await;
+    if cancelled > 0 {
+        warn!(
+            "Cancelled {} proxy connections that outlived the drain timeout",
+            cancelled
+        );
+    }
+
+    // Wait for every connection permit to be returned, including those held
+    // by connections of bindings that were deleted before shutdown
+    if let Some(limit) = connection_limit {
+        let drain_timeout = config.get_remaining_drain_timeout(shutdown_started.elapsed());
+        let active = limit.drain(drain_timeout).await;
+        if active > 0 {
+            warn!(
+                "{} proxied connections were still active at the shutdown deadline",
+                active
+            );
+        }
+    }
+
+    info!("Server shutdown complete");
+    Ok(())
+}
+
+/// Install the process-wide logger
+///
+/// `--log-level` takes precedence over `RUST_LOG`, which may also hold
+/// per-module directives such as `metaproxy::proxy=debug`. Without either,
+/// messages at `info` and above are logged. A logger that was already
+/// installed, e.g. by an application embedding metaproxy, is kept.
+///
+/// # Arguments
+///
+/// * `log_level` - The level given with `--log-level`, if any
+fn init_logger(log_level: Option<LevelFilter>) {
+    let mut builder = match log_level {
+        Some(level) => {
+            let mut builder = env_logger::Builder::new();
+            builder.filter_level(level);
+            builder
+        }
+        None => env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")),
+    };
+    if builder.try_init().is_err() {
+        debug!("A logger is already installed, keeping it");
+    }
+}
+
+/// Delete idle bindings every `interval` for as long as the server runs
+///
+/// # Arguments
+///
+/// * `context` - Server-wide settings and the bindings to check
+/// * `ttl` - Idle time after which a binding is deleted
+/// * `interval` - Time between scans for idle bindings
+async fn remove_idle_bindings_periodically(
+    context: Arc<ProxyContext>,
+    ttl: Duration,
+    interval: Duration,
+) {
+    let mut ticker = tokio::time::interval(interval);
+    ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    loop {
+        ticker.tick().await;
+        remove_idle_bindings(&context, ttl).await;
+    }
+}
+
+/// The bound listener the management API is served on
+enum ApiListener {
+    /// A TCP listener on the bind address
+    Tcp(tokio::net::TcpListener),
+    /// A Unix domain socket listener and the path of its socket file
+    #[cfg(unix)]
+    Unix(tokio::net::UnixListener, String),
+}
+
+/// Bind the listener the management API is served on
+///
+/// The API is bound to the configured Unix domain socket if there is one,
+/// and to the TCP bind address otherwise. A stale socket file left at the
+/// socket path by a previous run is removed before binding. Binding before anything else is
+/// started lets an address that is already in use fail startup with a clear
+/// error.
+///
+/// # Arguments
+///
+/// * `config` - The server configuration
+///
+/// # Returns
+///
+/// A `Result` containing the bound listener, or an error naming the address
+/// that could not be bound
+async fn bind_api(config: &Config) -> Result<ApiListener> {
+    #[cfg(unix)]
+    if let Some(socket_path) = config.api_socket.as_deref() {
+        remove_stale_socket(socket_path)?;
+
+        let listener = tokio::net::UnixListener::bind(socket_path).map_err(|e| {
+            Error::Custom(format!(
+                "Failed to bind the API to Unix socket {}: {}",
+                socket_path, e
+            ))
+        })?;
+        info!("Binding to Unix socket: {}", socket_path);
+        return Ok(ApiListener::Unix(listener, socket_path.to_string()));
+    }
+
     let bind_addr = config.get_bind_addr()?;
+    let listener = tokio::net::TcpListener::bind(bind_addr)
+        .await
+        .map_err(|e| Error::Custom(format!("Failed to bind the API to {}: {}", bind_addr, e)))?;
     info!("Binding to address: {}", bind_addr);
+    Ok(ApiListener::Tcp(listener))
+}
 
-    let (_, server) = warp::serve(routes).bind_with_graceful_shutdown(bind_addr, async {
+/// Remove a socket file left behind at the API socket path by a previous run
+///
+/// Only a socket nothing listens on any more is removed. Any other file, and
+/// the socket of a running instance, make startup fail instead.
+///
+/// # Arguments
+///
+/// * `socket_path` - The path of the API's Unix domain socket
+///
+/// # Returns
+///
+/// A `Result` that is an error if the path is taken by something else
+#[cfg(unix)]
+fn remove_stale_socket(socket_path: &str) -> Result<()> {
+    use std::os::unix::fs::FileTypeExt;
+
+    let metadata = match std::fs::symlink_metadata(socket_path) {
+        Ok(metadata) => metadata,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e.into()),
+    };
+    if !metadata.file_type().is_socket() {
+        return Err(Error::Custom(format!(
+            "Refusing to replace {}, which is not a Unix socket",
+            socket_path
+        )));
+    }
+    match std::os::unix::net::UnixStream::connect(socket_path) {
+        Ok(_) => Err(Error::Custom(format!(
+            "Unix socket {} is in use by another running instance",
+            socket_path
+        ))),
+        Err(e) if e.kind() == std::io::ErrorKind::ConnectionRefused => {
+            std::fs::remove_file(socket_path)?;
+            Ok(())
+        }
+        Err(e) => Err(Error::Custom(format!(
+            "Failed to check whether Unix socket {} is in use: {}",
+            socket_path, e
+        ))),
+    }
+}
+
+/// Serve the API routes until the shutdown signal is received
+///
+/// Connections may speak HTTP/1.1 or cleartext HTTP/2 with prior knowledge
+/// (h2c). The socket file of a Unix domain socket listener is removed once
+/// the server has shut down.
+///
+/// # Arguments
+///
+/// * `routes` - The API routes to serve
+/// * `listener` - The listener bound by [`bind_api`]
+/// * `shutdown_timeout` - Optional bound on waiting for in-flight requests after the signal
+///
+/// # Returns
+///
+/// The time the shutdown signal was received
+async fn serve_api<F>(
+    routes: F,
+    listener: ApiListener,
+    shutdown_timeout: Option<Duration>,
+) -> Instant
+where
+    F: warp::Filter + Clone + Send + Sync + 'static,
+    F::Extract: warp::Reply,
+{
+    let (signalled_tx, signalled_rx) = oneshot::channel();
+    match listener {
+        ApiListener::Tcp(listener) => {
+            let incoming = tokio_stream::wrappers::TcpListenerStream::new(listener);
+            let server = warp::serve(routes)
+                .serve_incoming_with_graceful_shutdown(incoming, notify_shutdown(signalled_tx));
+
+            info!("Server started, waiting for connections");
+            wait_for_server(server, signalled_rx, shutdown_timeout).await
+        }
+        #[cfg(unix)]
+        ApiListener::Unix(listener, socket_path) => {
+            let incoming = tokio_stream::wrappers::UnixListenerStream::new(listener);
+            let server = warp::serve(routes)
+                .serve_incoming_with_graceful_shutdown(incoming, notify_shutdown(signalled_tx));
+
+            info!("Server started, waiting for connections");
+            let started = wait_for_server(server, signalled_rx, shutdown_timeout).await;
+
+            if let Err(e) = std::fs::remove_file(&socket_path) {
+                warn!("Failed to remove API socket {}: {}", socket_path, e);
+            }
+            started
+        }
+    }
+}
+
+/// Wait for the shutdown signal and report the time it was received
+///
+/// # Arguments
+///
+/// * `signalled` - Channel the time of the signal is sent on
+async fn notify_shutdown(signalled: oneshot::Sender<Instant>) {
+    shutdown_signal().await;
+    let _ = signalled.send(Instant::now());
+}
+
+/// Run the API server until it has shut down gracefully or the shutdown timeout passes
+///
+/// Once the shutdown signal is received, in-flight API requests are given up
+/// to `shutdown_timeout` to complete before the server is dropped with them.
+///
+/// # Arguments
+///
+/// * `server` - The server future, which completes after a graceful shutdown
+/// * `signalled` - Receives the time the shutdown signal was received
+/// * `shutdown_timeout` - Optional bound on waiting for in-flight requests
+///
+/// # Returns
+///
+/// The time the shutdown signal was received
+async fn wait_for_server(
+    server: impl Future<Output = ()>,
+    signalled: oneshot::Receiver<Instant>,
+    shutdown_timeout: Option<Duration>,
+) -> Instant {
+    tokio::pin!(server);
+
+    let started = tokio::select! {
+        _ = &mut server => return Instant::now(),
+        started = signalled => started.unwrap_or_else(|_| Instant::now()),
+    };
+    warn!("Received shutdown signal, stopping server");
+
+    match shutdown_timeout {
+        Some(limit) => {
+            if tokio::time::timeout(limit.saturating_sub(started.elapsed()), server)
+                .await
+                .is_err()
+            {
+                warn!(
+                    "Dropped API requests still in flight after the {:?} shutdown timeout",
+                    limit
+                );
+            }
+        }
+        None => server.await,
+    }
+    started
+}
+
+/// Reconcile the bindings against the config file every time SIGHUP is received
+///
+/// A config file that fails to load is logged and leaves the bindings untouched.
+/// On platforms without SIGHUP this returns immediately.
+///
+/// # Arguments
+///
+/// * `path` - Path of the JSON config file
+/// * `context` - Server-wide settings and the bindings to reconcile
+async fn reload_on_sighup(path: String, context: Arc<ProxyContext>) {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        let mut hangup = match signal(SignalKind::hangup()) {
+            Ok(hangup) => hangup,
+            Err(e) => {
+                warn!("Failed to install SIGHUP handler: {}", e);
+                return;
+            }
+        };
+
+        while hangup.recv().await.is_some() {
+            info!("Received SIGHUP, reloading config file {}", path);
+            let specs = match load_bindings(&path) {
+                Ok(specs) => specs,
+                Err(e) => {
+                    error!("Failed to reload config file {}: {}", path, e);
+                    continue;
+                }
+            };
+
+            let summary = reconcile_bindings(&context, &specs).await;
+            if summary.is_empty() {
+                info!("Config reload made no changes");
+            } else {
+                info!(
+                    "Config reload: created {:?}, updated {:?}, replaced {:?}, removed {:?}, failed {:?}",
+                    summary.created,
+                    summary.updated,
+                    summary.replaced,
+                    summary.removed,
+                    summary.failed
+                );
+            }
+        }
+    }
+
+    #[cfg(not(unix))]
+    let _ = (path, context);
+}
+
+/// Wait for a process shutdown signal
+///
+/// SIGINT (CTRL+C) shuts the server down on every platform, and SIGTERM,
+/// which container orchestrators send to stop a process, on Unix as well.
+async fn shutdown_signal() {
+    let ctrl_c = async {
         tokio::signal::ctrl_c()
             .await
             .expect("failed to install CTRL+C signal handler");
-    });
+    };
 
-    // Run the server
-    info!("Server started, waiting for connections");
-    server.await;
-    warn!("Received shutdown signal, stopping server");
-    info!("Server shutdown complete");
-    Ok(())
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        let mut terminate =
+            signal(SignalKind::terminate()).expect("failed to install SIGTERM signal handler");
+        tokio::select! {
+            _ = ctrl_c => info!("Received SIGINT, shutting down"),
+            _ = terminate.recv() => info!("Received SIGTERM, shutting down"),
+        }
+    }
+
+    #[cfg(not(unix))]
+    {
+        ctrl_c.await;
+        info!("Received CTRL+C, shutting down");
+    }
 }
@@ -0,0 +1,345 @@
+/*!
+ * # Local Listeners Module
+ *
+ * This module keeps metaproxy from proxying connections back into itself.
+ *
+ * Bindings may be chained, one binding's upstream being another binding of
+ * the same server. A binding whose upstream is itself, or a cycle of
+ * bindings, would however make the proxy connect to itself, accept that
+ * connection and forward it once more, until file descriptors or memory run
+ * out. A CONNECT request of a `direct` binding could also reach the
+ * management API, which is not meant to be proxied to.
+ *
+ * The API listener and every binding listener register their address with
+ * the server's [`LocalListeners`]. When a binding connects to another
+ * binding, the ports of the bindings the connection went through are
+ * recorded under the local address of the new connection, and picked up by
+ * the binding accepting it. Every address the proxy is about to connect to is
+ * checked first, and connecting to the API or to a binding the connection
+ * already went through fails with a [`ConnectionLoop`] error, which the proxy
+ * answers with `508 Loop Detected`.
+ *
+ * A listener bound to the unspecified address, as bindings are by default,
+ * covers its port on every address of the host.
+ */
+
+use crate::error::Error;
+use std::collections::HashMap;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, UdpSocket};
+use std::sync::{Arc, Mutex};
+
+/// What a registered address listens for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ListenerKind {
+    /// The management API
+    Api,
+    /// A proxy binding
+    Binding,
+}
+
+/// The state shared by every view of the listeners
+#[derive(Debug, Default)]
+struct Shared {
+    /// The registered listen addresses
+    listeners: Mutex<HashMap<SocketAddr, ListenerKind>>,
+    /// The binding ports each connection from one binding to another went
+    /// through, by the local address of the connection
+    hops: Mutex<HashMap<SocketAddr, Arc<[u16]>>>,
+}
+
+/// The addresses metaproxy listens on, as seen by one binding or connection
+///
+/// Clones share the same addresses. Each view also knows the bindings the
+/// connection it is used for went through, which it may not connect to again.
+#[derive(Debug, Clone, Default)]
+pub struct LocalListeners {
+    /// The addresses and connections shared by every view
+    shared: Arc<Shared>,
+    /// The ports of the bindings the connection went through, oldest first
+    path: Arc<[u16]>,
+}
+
+impl LocalListeners {
+    /// Register the address of a listener
+    ///
+    /// # Arguments
+    ///
+    /// * `addr` - The address the listener is bound to
+    /// * `kind` - What the listener listens for
+    ///
+    /// # Returns
+    ///
+    /// A guard that unregisters the address when dropped
+    pub fn register(&self, addr: SocketAddr, kind: ListenerKind) -> ListenerRegistration {
+        self.shared.listeners.lock().unwrap().insert(addr, kind);
+        ListenerRegistration {
+            addr,
+            listeners: self.clone(),
+        }
+    }
+
+    /// Get the view of the connections a binding accepts from clients
+    ///
+    /// # Arguments
+    ///
+    /// * `port` - The port of the binding
+    ///
+    /// # Returns
+    ///
+    /// A view sharing the addresses, whose connections went through the binding
+    pub fn for_binding(&self, port: u16) -> LocalListeners {
+        LocalListeners {
+            shared: self.shared.clone(),
+            path: Arc::from([port]),
+        }
+    }
+
+    /// Get the view of a connection accepted by a binding, if another binding opened it
+    ///
+    /// The bindings recorded for the connection are forgotten once picked up.
+    ///
+    /// # Arguments
+    ///
+    /// * `peer` - The address the connection came from
+    ///
+    /// # Returns
+    ///
+    /// A view whose connection went through the bindings it was recorded with
+    /// and then this view's, or None if the connection came from elsewhere
+    pub fn accepted(&self, peer: SocketAddr) -> Option<LocalListeners> {
+        let recorded = self.shared.hops.lock().unwrap().remove(&peer)?;
+        Some(LocalListeners {
+            shared: self.shared.clone(),
+            path: recorded.iter().chain(self.path.iter()).copied().collect(),
+        })
+    }
+
+    /// Get the ports of the bindings the connection went through
+    pub fn path(&self) -> &[u16] {
+        &self.path
+    }
+
+    /// Check whether an address may be connected to
+    ///
+    /// # Arguments
+    ///
+    /// * `addr` - The address about to be connected to
+    ///
+    /// # Returns
+    ///
+    /// A result containing whether the address is another binding of this
+    /// proxy, or an error wrapping [`ConnectionLoop`] if it is the API or a
+    /// binding the connection went through
+    pub fn check(&self, addr: SocketAddr) -> io::Result<bool> {
+        match self.listener_at(addr) {
+            None => Ok(false),
+            Some(ListenerKind::Binding) if !self.path.contains(&addr.port()) => Ok(true),
+            Some(kind) => Err(io::Error::new(
+                io::ErrorKind::ConnectionRefused,
+                ConnectionLoop { addr, kind },
+            )),
+        }
+    }
+
+    /// Record the bindings a connection to another binding went through
+    ///
+    /// # Arguments
+    ///
+    /// * `local` - The local address the connection is opened from
+    pub fn record_hop(&self, local: SocketAddr) {
+        self.shared
+            .hops
+            .lock()
+            .unwrap()
+            .insert(local, self.path.clone());
+    }
+
+    /// Forget the bindings recorded for a connection that failed to open
+    ///
+    /// # Arguments
+    ///
+    /// * `local` - The local address the connection was opened from
+    pub fn forget_hop(&self, local: SocketAddr) {
+        self.shared.hops.lock().unwrap().remove(&local);
+    }
+
+    /// Find the registered listener connecting to an address would reach
+    ///
+    /// # Arguments
+    ///
+    /// * `addr` - The address about to be connected to
+    ///
+    /// # Returns
+    ///
+    /// What the listener bound to the address, or to the unspecified address
+    /// on its port if the address belongs to this host, listens for
+    fn listener_at(&self, addr: SocketAddr) -> Option<ListenerKind> {
+        let listeners = self.shared.listeners.lock().unwrap();
+        if let Some(kind) = listeners.get(&addr) {
+            return Some(*kind);
+        }
+        let mut unspecified = listeners.iter().filter(|(listener, _)| {
+            listener.port() == addr.port() && listener.ip().is_unspecified()
+        });
+        let (_, kind) = unspecified.next()?;
+        is_local_ip(addr.ip()).then_some(*kind)
+    }
+}
+
+/// A listener's registration with [`LocalListeners`], removed when dropped
+#[derive(Debug)]
+pub struct ListenerRegistration {
+    /// The registered address
+    addr: SocketAddr,
+    /// The listeners the address is registered with
+    listeners: LocalListeners,
+}
+
+impl Drop for ListenerRegistration {
+    fn drop(&mut self) {
+        self.listeners
+            .shared
+            .listeners
+            .lock()
+            .unwrap()
+            .remove(&self.addr);
+    }
+}
+
+/// The error of a connection that would loop back into metaproxy
+#[derive(Debug)]
+pub struct ConnectionLoop {
+    /// The address that was not connected to
+    pub addr: SocketAddr,
+    /// What listens on the address
+    pub kind: ListenerKind,
+}
+
+impl std::fmt::Display for ConnectionLoop {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.kind {
+            ListenerKind::Api => write!(
+                f,
+                "refusing to connect to {}, the management API of this proxy",
+                self.addr
+            ),
+            ListenerKind::Binding => write!(
+                f,
+                "refusing to connect to {}, a binding of this proxy the connection already went through",
+                self.addr
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ConnectionLoop {}
+
+/// Check whether an error is a refused connection that would loop back into metaproxy
+///
+/// # Arguments
+///
+/// * `error` - The error of a connection attempt
+///
+/// # Returns
+///
+/// True if the error wraps [`ConnectionLoop`]
+pub fn is_connection_loop(error: &Error) -> bool {
+    match error {
+        Error::Io(e) => e
+            .get_ref()
+            .is_some_and(|inner| inner.is::<ConnectionLoop>()),
+        _ => false,
+    }
+}
+
+/// Get the local address a connection to one of this host's listeners is opened from
+///
+/// # Arguments
+///
+/// * `addr` - The address of the listener
+///
+/// # Returns
+///
+/// The listener's own address, or the loopback address if it is unspecified
+pub fn hop_source(addr: SocketAddr) -> IpAddr {
+    match addr.ip() {
+        IpAddr::V4(ip) if ip.is_unspecified() => IpAddr::V4(Ipv4Addr::LOCALHOST),
+        IpAddr::V6(ip) if ip.is_unspecified() => IpAddr::V6(Ipv6Addr::LOCALHOST),
+        ip => ip,
+    }
+}
+
+/// Check whether an IP address belongs to this host
+///
+/// # Arguments
+///
+/// * `ip` - The address to check
+///
+/// # Returns
+///
+/// True for loopback and unspecified addresses, and for addresses a socket
+/// can be bound to, which are assigned to one of the host's interfaces
+fn is_local_ip(ip: IpAddr) -> bool {
+    ip.is_loopback() || ip.is_unspecified() || UdpSocket::bind((ip, 0)).is_ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_listener_kinds_and_addresses() {
+        let listeners = LocalListeners::default();
+        let api = listeners.register("127.0.0.1:8000".parse().unwrap(), ListenerKind::Api);
+        let binding = listeners.register("0.0.0.0:9000".parse().unwrap(), ListenerKind::Binding);
+
+        // The API is never connected to
+        let error = Error::Io(
+            listeners
+                .check("127.0.0.1:8000".parse().unwrap())
+                .unwrap_err(),
+        );
+        assert!(is_connection_loop(&error));
+        assert!(error.to_string().contains("management API"));
+        assert!(!listeners.check("127.0.0.2:8000".parse().unwrap()).unwrap());
+
+        // A listener on the unspecified address covers every local address
+        assert!(listeners.check("127.0.0.1:9000".parse().unwrap()).unwrap());
+        assert!(listeners.check("0.0.0.0:9000".parse().unwrap()).unwrap());
+        assert!(!listeners.check("192.0.2.1:9000".parse().unwrap()).unwrap());
+        assert!(!listeners.check("127.0.0.1:9001".parse().unwrap()).unwrap());
+        assert!(!is_connection_loop(&Error::Custom("refused".to_string())));
+
+        // Addresses are removed with their registration
+        drop(binding);
+        drop(api);
+        assert!(!listeners.check("127.0.0.1:9000".parse().unwrap()).unwrap());
+        assert!(!listeners.check("127.0.0.1:8000".parse().unwrap()).unwrap());
+    }
+
+    #[test]
+    fn test_bindings_are_not_entered_twice() {
+        let listeners = LocalListeners::default();
+        let _a = listeners.register("0.0.0.0:9000".parse().unwrap(), ListenerKind::Binding);
+        let _b = listeners.register("0.0.0.0:9001".parse().unwrap(), ListenerKind::Binding);
+        let (a, b) = (listeners.for_binding(9000), listeners.for_binding(9001));
+
+        // A binding may not connect to itself, but to another binding
+        assert!(a.check("127.0.0.1:9000".parse().unwrap()).is_err());
+        assert!(a.check("127.0.0.1:9001".parse().unwrap()).unwrap());
+
+        // The connection from A to B is accepted by B as having gone through both
+        let local: SocketAddr = "127.0.0.1:40000".parse().unwrap();
+        a.record_hop(local);
+        let through_b = b.accepted(local).unwrap();
+        assert_eq!(through_b.path(), [9000, 9001]);
+        assert!(through_b.check("127.0.0.1:9000".parse().unwrap()).is_err());
+
+        // Recorded bindings are picked up once, and clients connect from elsewhere
+        assert!(b.accepted(local).is_none());
+        a.record_hop(local);
+        a.forget_hop(local);
+        assert!(b.accepted(local).is_none());
+    }
+}
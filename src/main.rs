@@ -1,17 +1,11 @@
-use log::info;
 use metaproxy::config::Config;
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    // Initialize the logger
-    env_logger::init();
-
     // Parse command line arguments
     let config = Config::from_args();
 
-    info!("Starting metaproxy with configuration: {:?}", config);
-
-    // Run the proxy server
+    // Run the proxy server, which also initializes the logger
     metaproxy::run(config).await?;
 
     Ok(())
@@ -0,0 +1,187 @@
+/*!
+ * # Rate Limiting Module
+ *
+ * This module provides the token-bucket rate limiter guarding the management
+ * API against runaway clients, such as a deployment script stuck in a loop.
+ *
+ * Each bucket holds up to one second's worth of requests and refills
+ * continuously at the configured rate, so short bursts are allowed while the
+ * sustained rate stays bounded. Requests share a single bucket, or each client
+ * IP address gets its own. A request over the limit is told how long until
+ * its bucket holds a token again, for the `Retry-After` header of the 429.
+ */
+
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+
+/// Number of per-client buckets kept before idle ones are pruned
+const MAX_IDLE_BUCKETS: usize = 1024;
+
+/// The tokens left in one bucket
+#[derive(Debug, Clone, Copy)]
+struct TokenBucket {
+    /// Requests that may still be made right now
+    tokens: f64,
+    /// When `tokens` was last refilled
+    updated: Instant,
+}
+
+/// A token-bucket rate limiter shared by every API route
+#[derive(Debug, Clone)]
+pub struct RateLimiter {
+    /// Requests allowed per second, which is also the bucket capacity
+    rate: u32,
+    /// Whether each client IP address gets its own bucket
+    per_client: bool,
+    /// The buckets, keyed by client IP address or `None` for the shared bucket
+    buckets: Arc<Mutex<HashMap<Option<IpAddr>, TokenBucket>>>,
+}
+
+impl RateLimiter {
+    /// Create a rate limiter
+    ///
+    /// # Arguments
+    ///
+    /// * `rate` - Requests allowed per second; must not be 0
+    /// * `per_client` - Whether each client IP address gets its own bucket
+    ///
+    /// # Returns
+    ///
+    /// A new `RateLimiter` with full buckets
+    pub fn new(rate: u32, per_client: bool) -> Self {
+        RateLimiter {
+            rate,
+            per_client,
+            buckets: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Get the number of requests allowed per second
+    pub fn rate(&self) -> u32 {
+        self.rate
+    }
+
+    /// Check whether each client IP address gets its own bucket
+    pub fn per_client(&self) -> bool {
+        self.per_client
+    }
+
+    /// Take a token for a request
+    ///
+    /// Requests whose client address is unknown, such as those received over a
+    /// Unix domain socket, share a single bucket.
+    ///
+    /// # Arguments
+    ///
+    /// * `client` - The IP address of the client making the request, if known
+    ///
+    /// # Returns
+    ///
+    /// `Ok` if the request is allowed, or an error holding how long until the
+    /// next token is available if it exceeds the rate
+    pub async fn try_acquire(&self, client: Option<IpAddr>) -> Result<(), Duration> {
+        self.try_acquire_at(client, Instant::now()).await
+    }
+
+    /// Take a token for a request made at a given time
+    ///
+    /// # Arguments
+    ///
+    /// * `client` - The IP address of the client making the request, if known
+    /// * `now` - The time of the request
+    ///
+    /// # Returns
+    ///
+    /// `Ok` if the request is allowed, or an error holding how long until the
+    /// next token is available if it exceeds the rate
+    pub async fn try_acquire_at(
+        &self,
+        client: Option<IpAddr>,
+        now: Instant,
+    ) -> Result<(), Duration> {
+        let key = if self.per_client { client } else { None };
+        let capacity = f64::from(self.rate);
+        let mut buckets = self.buckets.lock().await;
+
+        // Forget clients whose buckets have refilled, so the map stays bounded
+        if buckets.len() >= MAX_IDLE_BUCKETS {
+            buckets.retain(|_, bucket| {
+                let elapsed = now.saturating_duration_since(bucket.updated).as_secs_f64();
+                bucket.tokens + elapsed * capacity < capacity
+            });
+        }
+
+        let bucket = buckets.entry(key).or_insert(TokenBucket {
+            tokens: capacity,
+            updated: now,
+        });
+        let elapsed = now.saturating_duration_since(bucket.updated).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * capacity).min(capacity);
+        bucket.updated = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64((1.0 - bucket.tokens) / capacity))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_bucket_allows_burst_then_refills() {
+        let limiter = RateLimiter::new(5, false);
+        let start = Instant::now();
+
+        for _ in 0..5 {
+            assert!(limiter.try_acquire_at(None, start).await.is_ok());
+        }
+        // One token is back after a fifth of a second
+        assert_eq!(
+            limiter.try_acquire_at(None, start).await,
+            Err(Duration::from_millis(200))
+        );
+
+        let later = start + Duration::from_millis(200);
+        assert!(limiter.try_acquire_at(None, later).await.is_ok());
+        assert!(limiter.try_acquire_at(None, later).await.is_err());
+
+        // Part of the wait has passed
+        let wait = limiter
+            .try_acquire_at(None, later + Duration::from_millis(50))
+            .await
+            .unwrap_err();
+        assert!(wait > Duration::from_millis(149) && wait < Duration::from_millis(151));
+
+        // The bucket never holds more than one second's worth
+        let much_later = start + Duration::from_secs(60);
+        for _ in 0..5 {
+            assert!(limiter.try_acquire_at(None, much_later).await.is_ok());
+        }
+        assert!(limiter.try_acquire_at(None, much_later).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_global_and_per_client_keys() {
+        let first = Some(IpAddr::from([10, 0, 0, 1]));
+        let second = Some(IpAddr::from([10, 0, 0, 2]));
+        let now = Instant::now();
+
+        let global = RateLimiter::new(1, false);
+        assert!(global.try_acquire_at(first, now).await.is_ok());
+        assert!(global.try_acquire_at(second, now).await.is_err());
+
+        let per_client = RateLimiter::new(1, true);
+        assert!(per_client.try_acquire_at(first, now).await.is_ok());
+        assert!(per_client.try_acquire_at(first, now).await.is_err());
+        assert!(per_client.try_acquire_at(second, now).await.is_ok());
+    }
+}
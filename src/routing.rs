@@ -0,0 +1,327 @@
+/*!
+ * # Routing Module
+ *
+ * This module picks the upstream of each request of a `router` binding from
+ * the host the client asked for, so that a single port can fan out to
+ * different upstreams by destination.
+ *
+ * Routes are configured through the API as a list of host patterns, consulted
+ * in order:
+ *
+ * ```json
+ * [
+ *   {"match": "*.internal.example.com", "upstream": "http://corp-proxy:3128"},
+ *   {"match": "example.com", "upstream": "http://proxy-a:3128"}
+ * ]
+ * ```
+ *
+ * A `*` in a pattern matches any run of characters, dots included, so
+ * `*.example.com` matches every subdomain of `example.com` but not
+ * `example.com` itself. Hosts are matched case-insensitively. Requests whose
+ * host matches no route go to the binding's `upstream`.
+ *
+ * Like the `DIRECT` result of a PAC file, an `upstream` of [`DIRECT`] sends
+ * matching requests straight to the target server rather than through a proxy:
+ *
+ * ```json
+ * [
+ *   {"match": "*.corp.example.com", "upstream": "DIRECT"},
+ *   {"match": "*", "upstream": "http://proxy-a:3128"}
+ * ]
+ * ```
+ *
+ * Plain HTTP requests can also pick their upstream by the value of a request
+ * header, e.g. to send requests marked `X-Route: canary` to a canary proxy:
+ *
+ * ```json
+ * {"header": "X-Route", "upstreams": {"canary": "http://canary-proxy:3128"}}
+ * ```
+ *
+ * CONNECT requests carry no headers of the tunneled traffic, so header
+ * routes never apply to them.
+ */
+
+use crate::error::{Error, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use url::Url;
+use warp::http::header::HeaderName;
+
+/// The `upstream` of routes that connect to the target server directly
+pub const DIRECT: &str = "DIRECT";
+
+/// A rule sending requests for matching hosts to an upstream
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Route {
+    /// The host pattern, where `*` matches any run of characters
+    #[serde(rename = "match")]
+    pub pattern: String,
+    /// The upstream server address requests for matching hosts are sent to,
+    /// or [`DIRECT`] to send them straight to the target
+    pub upstream: String,
+}
+
+impl Route {
+    /// Check whether the route applies to a host
+    ///
+    /// # Arguments
+    ///
+    /// * `host` - The target host, without a port
+    ///
+    /// # Returns
+    ///
+    /// `true` if the host matches the route's pattern
+    pub fn matches(&self, host: &str) -> bool {
+        glob_match(
+            self.pattern.to_ascii_lowercase().as_bytes(),
+            host.to_ascii_lowercase().as_bytes(),
+        )
+    }
+
+    /// Check whether the route connects to the target server directly
+    pub fn is_direct(&self) -> bool {
+        self.upstream.eq_ignore_ascii_case(DIRECT)
+    }
+
+    /// Check that the route has a pattern and a valid upstream URL or `DIRECT`
+    ///
+    /// # Returns
+    ///
+    /// A result indicating whether the route is valid, with a descriptive error if not
+    pub fn validate(&self) -> Result<()> {
+        if self.pattern.is_empty() {
+            return Err(Error::Custom(
+                "Route match pattern must not be empty".to_string(),
+            ));
+        }
+        if !self.is_direct() {
+            Url::parse(&self.upstream)
+                .map_err(|_| Error::Custom(format!("Invalid upstream URL: {}", self.upstream)))?;
+        }
+        Ok(())
+    }
+}
+
+/// Rules picking the upstream of plain HTTP requests by a request header
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HeaderRoutes {
+    /// The name of the request header, matched case-insensitively
+    pub header: String,
+    /// The upstream server address for each header value, matched exactly
+    pub upstreams: BTreeMap<String, String>,
+}
+
+impl HeaderRoutes {
+    /// Find the upstream for the value of the routing header
+    ///
+    /// # Arguments
+    ///
+    /// * `headers` - The request headers
+    ///
+    /// # Returns
+    ///
+    /// The upstream of the first routing header whose value is mapped, or
+    /// None if the request has no such header
+    pub fn select(&self, headers: &[httparse::Header]) -> Option<&str> {
+        headers
+            .iter()
+            .filter(|header| header.name.eq_ignore_ascii_case(&self.header))
+            .filter_map(|header| std::str::from_utf8(header.value).ok())
+            .find_map(|value| self.upstreams.get(value.trim()))
+            .map(String::as_str)
+    }
+
+    /// Check that the header name and every upstream URL are valid
+    ///
+    /// # Returns
+    ///
+    /// A result indicating whether the routes are valid, with a descriptive error if not
+    pub fn validate(&self) -> Result<()> {
+        HeaderName::from_bytes(self.header.as_bytes())
+            .map_err(|_| Error::Custom(format!("Invalid header name: {:?}", self.header)))?;
+        if self.upstreams.is_empty() {
+            return Err(Error::Custom(
+                "Header routes must map at least one value to an upstream".to_string(),
+            ));
+        }
+        for upstream in self.upstreams.values() {
+            Url::parse(upstream)
+                .map_err(|_| Error::Custom(format!("Invalid upstream URL: {}", upstream)))?;
+        }
+        Ok(())
+    }
+}
+
+/// Validate a list of routes
+///
+/// # Arguments
+///
+/// * `routes` - The routes to validate
+///
+/// # Returns
+///
+/// A result indicating success, or the error for the first invalid route
+pub fn validate_routes(routes: &[Route]) -> Result<()> {
+    routes.iter().try_for_each(Route::validate)
+}
+
+/// Find the first route that applies to a host
+///
+/// # Arguments
+///
+/// * `routes` - The routes to consult, in order
+/// * `host` - The target host, without a port
+///
+/// # Returns
+///
+/// The matching route, or None if the host matches no route
+pub fn select_route<'a>(routes: &'a [Route], host: &str) -> Option<&'a Route> {
+    routes.iter().find(|route| route.matches(host))
+}
+
+/// Get the host of a `host:port` authority, as found in CONNECT targets and `Host` headers
+///
+/// # Arguments
+///
+/// * `authority` - The authority, with or without a port
+///
+/// # Returns
+///
+/// The host, without the brackets of an IPv6 address
+pub fn authority_host(authority: &str) -> &str {
+    if let Some(rest) = authority.strip_prefix('[') {
+        return rest.split(']').next().unwrap_or(rest);
+    }
+    match authority.rsplit_once(':') {
+        Some((host, port)) if port.bytes().all(|b| b.is_ascii_digit()) => host,
+        _ => authority,
+    }
+}
+
+/// Match a value against a glob pattern where `*` matches any run of bytes
+fn glob_match(pattern: &[u8], value: &[u8]) -> bool {
+    let (mut p, mut v) = (0, 0);
+    // Position of the last `*` seen, and the value position it was tried at
+    let mut backtrack = None;
+    while v < value.len() {
+        if p < pattern.len() && pattern[p] == b'*' {
+            backtrack = Some((p, v));
+            p += 1;
+        } else if p < pattern.len() && pattern[p] == value[v] {
+            p += 1;
+            v += 1;
+        } else if let Some((star, tried)) = backtrack {
+            // Let the last `*` swallow one more byte
+            p = star + 1;
+            v = tried + 1;
+            backtrack = Some((star, tried + 1));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|&b| b == b'*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn route(pattern: &str, upstream: &str) -> Route {
+        Route {
+            pattern: pattern.to_string(),
+            upstream: upstream.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_route_matches() {
+        let wildcard = route("*.example.com", "http://a:3128");
+        assert!(wildcard.matches("www.example.com"));
+        assert!(wildcard.matches("a.b.EXAMPLE.com"));
+        assert!(!wildcard.matches("example.com"));
+        assert!(!wildcard.matches("www.example.org"));
+
+        let exact = route("example.com", "http://a:3128");
+        assert!(exact.matches("example.com"));
+        assert!(!exact.matches("www.example.com"));
+
+        assert!(route("*", "http://a:3128").matches("anything.test"));
+        assert!(route("api-*.example.*", "http://a:3128").matches("api-eu.example.net"));
+    }
+
+    #[test]
+    fn test_select_route_in_order() {
+        let routes = vec![
+            route("internal.example.com", "http://corp:3128"),
+            route("*.example.com", "http://a:3128"),
+        ];
+        assert_eq!(
+            select_route(&routes, "internal.example.com")
+                .unwrap()
+                .upstream,
+            "http://corp:3128"
+        );
+        assert_eq!(
+            select_route(&routes, "www.example.com").unwrap().upstream,
+            "http://a:3128"
+        );
+        assert!(select_route(&routes, "example.org").is_none());
+    }
+
+    #[test]
+    fn test_validate_routes() {
+        assert!(validate_routes(&[route("*.example.com", "http://a:3128")]).is_ok());
+        assert!(validate_routes(&[route("", "http://a:3128")]).is_err());
+        assert!(validate_routes(&[route("*.example.com", "not a url")]).is_err());
+        assert!(validate_routes(&[route("*.example.com", "DIRECT")]).is_ok());
+        assert!(route("*", "direct").is_direct());
+        assert!(!route("*", "http://a:3128").is_direct());
+    }
+
+    #[test]
+    fn test_header_routes() {
+        let routes = HeaderRoutes {
+            header: "X-Route".to_string(),
+            upstreams: BTreeMap::from([
+                ("canary".to_string(), "http://canary:3128".to_string()),
+                ("beta".to_string(), "http://beta:3128".to_string()),
+            ]),
+        };
+        assert!(routes.validate().is_ok());
+
+        let header = |name, value: &'static str| httparse::Header {
+            name,
+            value: value.as_bytes(),
+        };
+        assert_eq!(
+            routes.select(&[header("Host", "example.com"), header("x-route", " canary")]),
+            Some("http://canary:3128")
+        );
+        assert_eq!(routes.select(&[header("X-Route", "stable")]), None);
+        assert_eq!(routes.select(&[header("Host", "example.com")]), None);
+
+        let invalid = HeaderRoutes {
+            header: "Bad Header".to_string(),
+            ..routes.clone()
+        };
+        assert!(invalid.validate().is_err());
+        let invalid = HeaderRoutes {
+            upstreams: BTreeMap::from([("canary".to_string(), "not a url".to_string())]),
+            ..routes.clone()
+        };
+        assert!(invalid.validate().is_err());
+        let invalid = HeaderRoutes {
+            upstreams: BTreeMap::new(),
+            ..routes
+        };
+        assert!(invalid.validate().is_err());
+    }
+
+    #[test]
+    fn test_authority_host() {
+        assert_eq!(authority_host("example.com:443"), "example.com");
+        assert_eq!(authority_host("example.com"), "example.com");
+        assert_eq!(authority_host("[::1]:8080"), "::1");
+        assert_eq!(authority_host("[::1]"), "::1");
+    }
+}
@@ -0,0 +1,242 @@
+/*!
+ * # Integration Test Harness
+ *
+ * Helpers for tests that proxy real traffic: a mock upstream proxy listening
+ * on an ephemeral port, bindings created through the API, and clients that
+ * talk to the bound port over TCP.
+ *
+ * ```ignore
+ * let upstream = MockUpstream::start().await;
+ * let bindings = new_bindings();
+ * let routes = api_routes(bindings.clone());
+ * let port = create_binding(&routes, serde_json::json!({
+ *     "port": 0,
+ *     "upstream": upstream.url()
+ * })).await;
+ * let mut tunnel = connect_through(port, "example.com:443").await;
+ * ```
+ */
+
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::Mutex;
+use tokio::time::timeout;
+use warp::http::StatusCode;
+use warp::{Filter, Rejection, Reply};
+
+use metaproxy::api::{self, ApiConfig};
+use metaproxy::proxy::{BindingMap, ProxyContext};
+
+/// How long the harness waits on any single network operation
+pub const IO_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A mock upstream proxy that tunnels CONNECT requests to an echo and
+/// answers every other request itself
+///
+/// CONNECT requests get a `200 Connection Established`, after which every byte
+/// of the tunnel is echoed back. Other requests get a `200 OK` whose body is
+/// the request line the upstream received.
+pub struct MockUpstream {
+    /// The address the upstream listens on
+    pub addr: SocketAddr,
+    /// The heads of the requests the upstream has received, in order
+    requests: Arc<Mutex<Vec<String>>>,
+}
+
+impl MockUpstream {
+    /// Start the upstream on an ephemeral port
+    pub async fn start() -> MockUpstream {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let requests = Arc::new(Mutex::new(Vec::new()));
+
+        let seen = requests.clone();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                tokio::spawn(serve_upstream(stream, seen.clone()));
+            }
+        });
+
+        MockUpstream { addr, requests }
+    }
+
+    /// The URL bindings use to reach the upstream
+    pub fn url(&self) -> String {
+        format!("http://{}", self.addr)
+    }
+
+    /// The heads of the requests the upstream has received so far
+    pub async fn requests(&self) -> Vec<String> {
+        self.requests.lock().await.clone()
+    }
+}
+
+/// Serve a single connection to the mock upstream
+async fn serve_upstream(mut stream: TcpStream, requests: Arc<Mutex<Vec<String>>>) {
+    let Some((head, rest)) = read_head(&mut stream).await else {
+        return;
+    };
+    let request_line = head.lines().next().unwrap_or_default().to_string();
+    requests.lock().await.push(head.clone());
+
+    if request_line.starts_with("CONNECT ") {
+        if stream
+            .write_all(b"HTTP/1.1 200 Connection Established\r\n\r\n")
+            .await
+            .is_err()
+            || stream.write_all(&rest).await.is_err()
+        {
+            return;
+        }
+        let mut buf = [0u8; 4096];
+        while let Ok(n) = stream.read(&mut buf).await {
+            if n == 0 || stream.write_all(&buf[..n]).await.is_err() {
+                break;
+            }
+        }
+    } else {
+        let response = format!(
+            "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            request_line.len(),
+            request_line
+        );
+        let _ = stream.write_all(response.as_bytes()).await;
+    }
+}
+
+/// Read a request or response head, returning it along with any bytes read past it
+pub async fn read_head(stream: &mut TcpStream) -> Option<(String, Vec<u8>)> {
+    let mut buf = Vec::new();
+    let mut chunk = [0u8; 1024];
+    loop {
+        if let Some(end) = buf.windows(4).position(|window| window == b"\r\n\r\n") {
+            let head = String::from_utf8_lossy(&buf[..end + 4]).to_string();
+            return Some((head, buf[end + 4..].to_vec()));
+        }
+        match timeout(IO_TIMEOUT, stream.read(&mut chunk)).await {
+            Ok(Ok(n)) if n > 0 => buf.extend_from_slice(&chunk[..n]),
+            _ => return None,
+        }
+    }
+}
+
+/// Create an empty binding map
+pub fn new_bindings() -> BindingMap {
+    Arc::new(Mutex::new(HashMap::new()))
+}
+
+/// Create the API routes with default settings
+///
+/// # Arguments
+///
+/// * `bindings` - The binding map the API manages
+pub fn api_routes(
+    bindings: BindingMap,
+) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    api::create_routes(Arc::new(ProxyContext::new(bindings)), ApiConfig::default())
+}
+
+/// Create a binding through the API, asserting that it succeeds
+///
+/// # Arguments
+///
+/// * `routes` - The API routes
+/// * `binding` - The `POST /proxy` request body, usually with a `port` of 0
+///
+/// # Returns
+///
+/// The port the binding listens on
+pub async fn create_binding<F>(routes: &F, binding: serde_json::Value) -> u16
+where
+    F: Filter + 'static,
+    F::Extract: Reply + Send,
+{
+    let resp = warp::test::request()
+        .method("POST")
+        .path("/proxy")
+        .json(&binding)
+        .reply(routes)
+        .await;
+    assert_eq!(
+        resp.status(),
+        StatusCode::OK,
+        "creating binding failed: {}",
+        String::from_utf8_lossy(resp.body())
+    );
+    let created: serde_json::Value = serde_json::from_slice(resp.body()).unwrap();
+    created["port"].as_u64().unwrap() as u16
+}
+
+/// Open a CONNECT tunnel through a bound port, asserting that it is established
+///
+/// # Arguments
+///
+/// * `port` - The bound port
+/// * `target` - The `host:port` to tunnel to
+///
+/// # Returns
+///
+/// The client stream, ready to carry tunneled bytes
+pub async fn connect_through(port: u16, target: &str) -> TcpStream {
+    let mut client = TcpStream::connect(("127.0.0.1", port)).await.unwrap();
+    client
+        .write_all(format!("CONNECT {target} HTTP/1.1\r\nHost: {target}\r\n\r\n").as_bytes())
+        .await
+        .unwrap();
+    let (head, rest) = read_head(&mut client).await.expect("no CONNECT response");
+    assert!(head.starts_with("HTTP/1.1 200"), "CONNECT failed: {head}");
+    assert!(rest.is_empty());
+    client
+}
+
+/// Send a raw request through a bound port and read the whole response
+///
+/// # Arguments
+///
+/// * `port` - The bound port
+/// * `request` - The raw request, which should ask the proxy to close the connection
+///
+/// # Returns
+///
+/// The raw response
+pub async fn request_through(port: u16, request: &str) -> String {
+    let mut client = TcpStream::connect(("127.0.0.1", port)).await.unwrap();
+    client.write_all(request.as_bytes()).await.unwrap();
+    let mut response = Vec::new();
+    timeout(IO_TIMEOUT, client.read_to_end(&mut response))
+        .await
+        .expect("timed out reading response")
+        .unwrap();
+    String::from_utf8_lossy(&response).to_string()
+}
+
+/// Write bytes to a stream and read back exactly as many
+///
+/// # Arguments
+///
+/// * `stream` - A tunnel to an echoing server
+/// * `data` - The bytes to send
+///
+/// # Returns
+///
+/// The bytes read back
+pub async fn round_trip(stream: &mut TcpStream, data: &[u8]) -> Vec<u8> {
+    stream.write_all(data).await.unwrap();
+    let mut echoed = vec![0u8; data.len()];
+    timeout(IO_TIMEOUT, stream.read_exact(&mut echoed))
+        .await
+        .expect("timed out reading echo")
+        .unwrap();
+    echoed
+}
+
+/// Shut down every binding in the map
+pub async fn shutdown_bindings(bindings: &BindingMap) {
+    for (_, binding) in bindings.lock().await.drain() {
+        let _ = binding.shutdown_tx.send(());
+    }
+}
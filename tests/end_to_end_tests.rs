@@ -0,0 +1,351 @@
+mod common;
+
+use common::{
+    api_routes, connect_through, create_binding, new_bindings, request_through, round_trip,
+    shutdown_bindings, MockUpstream, IO_TIMEOUT,
+};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use warp::http::StatusCode;
+
+#[tokio::test]
+async fn test_connect_through_upstream() {
+    let upstream = MockUpstream::start().await;
+    let bindings = new_bindings();
+    let routes = api_routes(bindings.clone());
+    let port = create_binding(
+        &routes,
+        serde_json::json!({"port": 0, "upstream": upstream.url()}),
+    )
+    .await;
+
+    let mut tunnel = connect_through(port, "example.com:443").await;
+    assert_eq!(round_trip(&mut tunnel, b"hello").await, b"hello");
+    assert_eq!(round_trip(&mut tunnel, b"world").await, b"world");
+
+    let requests = upstream.requests().await;
+    assert_eq!(requests.len(), 1);
+    assert!(requests[0].starts_with("CONNECT example.com:443 HTTP/1.1\r\n"));
+
+    shutdown_bindings(&bindings).await;
+}
+
+#[tokio::test]
+async fn test_http_request_through_upstream() {
+    let upstream = MockUpstream::start().await;
+    let bindings = new_bindings();
+    let routes = api_routes(bindings.clone());
+    let port = create_binding(
+        &routes,
+        serde_json::json!({
+            "port": 0,
+            "upstream": upstream.url(),
+            "request_headers": [{"op": "set", "name": "X-Harness", "value": "1"}]
+        }),
+    )
+    .await;
+
+    let response = request_through(
+        port,
+        "GET http://example.com/path HTTP/1.1\r\nHost: example.com\r\nConnection: close\r\n\r\n",
+    )
+    .await;
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+    assert!(response.ends_with("GET http://example.com/path HTTP/1.1"));
+
+    let requests = upstream.requests().await;
+    assert_eq!(requests.len(), 1);
+    assert!(requests[0].contains("X-Harness: 1\r\n"));
+
+    shutdown_bindings(&bindings).await;
+}
+
+#[tokio::test]
+async fn test_absolute_form_requests_from_proxy_clients() {
+    // Proxy-aware clients send absolute-form requests, forwarded unchanged
+    // through chained proxy bindings and reduced to origin-form for origins
+    let upstream = MockUpstream::start().await;
+    let bindings = new_bindings();
+    let routes = api_routes(bindings.clone());
+    let inner = create_binding(
+        &routes,
+        serde_json::json!({"port": 0, "upstream": upstream.url()}),
+    )
+    .await;
+    let outer = create_binding(
+        &routes,
+        serde_json::json!({"port": 0, "upstream": format!("http://127.0.0.1:{inner}")}),
+    )
+    .await;
+    let origin = create_binding(
+        &routes,
+        serde_json::json!({"port": 0, "upstream": upstream.url(), "upstream_mode": "origin"}),
+    )
+    .await;
+
+    let request = "GET http://example.com:8080/a?b=1 HTTP/1.1\r\n\
+                   Host: stale.example\r\n\
+                   Proxy-Connection: keep-alive\r\n\
+                   Connection: close\r\n\r\n";
+    let response = request_through(outer, request).await;
+    assert!(response.ends_with("\r\n\r\nGET http://example.com:8080/a?b=1 HTTP/1.1"));
+    let response = request_through(origin, request).await;
+    assert!(response.ends_with("\r\n\r\nGET /a?b=1 HTTP/1.1"));
+
+    let requests = upstream.requests().await;
+    assert_eq!(requests.len(), 2);
+    for request in &requests {
+        assert_eq!(request.matches("Host: ").count(), 1, "{}", request);
+        assert!(
+            request.contains("Host: example.com:8080\r\n"),
+            "{}",
+            request
+        );
+        assert!(!request.contains("Proxy-Connection"), "{}", request);
+    }
+
+    shutdown_bindings(&bindings).await;
+}
+
+#[tokio::test]
+async fn test_connect_through_chained_bindings() {
+    // A binding whose upstream is another binding reaches the mock upstream
+    // through both
+    let upstream = MockUpstream::start().await;
+    let bindings = new_bindings();
+    let routes = api_routes(bindings.clone());
+    let inner = create_binding(
+        &routes,
+        serde_json::json!({"port": 0, "upstream": upstream.url()}),
+    )
+    .await;
+    let outer = create_binding(
+        &routes,
+        serde_json::json!({"port": 0, "upstream": format!("http://127.0.0.1:{inner}")}),
+    )
+    .await;
+
+    let mut tunnel = connect_through(outer, "example.com:443").await;
+    assert_eq!(round_trip(&mut tunnel, b"ping").await, b"ping");
+    assert_eq!(upstream.requests().await.len(), 1);
+
+    shutdown_bindings(&bindings).await;
+}
+
+#[tokio::test]
+async fn test_binding_cycle_is_refused() {
+    // Chained bindings whose upstreams lead back to the first one
+    let upstream = MockUpstream::start().await;
+    let bindings = new_bindings();
+    let routes = api_routes(bindings.clone());
+    let inner = create_binding(
+        &routes,
+        serde_json::json!({"port": 0, "upstream": upstream.url()}),
+    )
+    .await;
+    let outer = create_binding(
+        &routes,
+        serde_json::json!({"port": 0, "upstream": format!("http://127.0.0.1:{inner}")}),
+    )
+    .await;
+    let resp = warp::test::request()
+        .method("PUT")
+        .path(&format!("/proxy/{inner}"))
+        .json(&serde_json::json!({"upstream": format!("http://127.0.0.1:{outer}")}))
+        .reply(&routes)
+        .await;
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    // The inner binding refuses to enter the outer one again
+    let request =
+        "GET http://example.com/ HTTP/1.1\r\nHost: example.com\r\nConnection: close\r\n\r\n";
+    let response = request_through(outer, request).await;
+    assert!(
+        response.starts_with("HTTP/1.1 508 Loop Detected\r\n"),
+        "{response}"
+    );
+    assert!(upstream.requests().await.is_empty());
+
+    shutdown_bindings(&bindings).await;
+}
+
+#[tokio::test]
+async fn test_paused_binding_keeps_active_tunnels() {
+    let upstream = MockUpstream::start().await;
+    let bindings = new_bindings();
+    let routes = api_routes(bindings.clone());
+    let port = create_binding(
+        &routes,
+        serde_json::json!({"port": 0, "upstream": upstream.url()}),
+    )
+    .await;
+    let mut tunnel = connect_through(port, "example.com:443").await;
+
+    let resp = warp::test::request()
+        .method("POST")
+        .path(&format!("/proxy/{port}/pause"))
+        .reply(&routes)
+        .await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let paused: serde_json::Value = serde_json::from_slice(resp.body()).unwrap();
+    assert_eq!(paused["status"], "paused");
+    assert_eq!(paused["active_connections"], 1);
+
+    let resp = warp::test::request()
+        .method("GET")
+        .path("/health")
+        .reply(&routes)
+        .await;
+    let health: serde_json::Value = serde_json::from_slice(resp.body()).unwrap();
+    assert_eq!(health["bindings"][0]["paused"], true);
+
+    // New connections are closed without a response, while the open tunnel
+    // keeps working
+    let mut client = TcpStream::connect(("127.0.0.1", port)).await.unwrap();
+    let _ = client
+        .write_all(b"CONNECT example.com:443 HTTP/1.1\r\nHost: example.com:443\r\n\r\n")
+        .await;
+    let mut response = Vec::new();
+    let _ = tokio::time::timeout(IO_TIMEOUT, client.read_to_end(&mut response))
+        .await
+        .expect("paused binding kept the connection open");
+    assert!(response.is_empty());
+    assert_eq!(round_trip(&mut tunnel, b"still open").await, b"still open");
+
+    let resp = warp::test::request()
+        .method("POST")
+        .path(&format!("/proxy/{port}/resume"))
+        .reply(&routes)
+        .await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let resumed: serde_json::Value = serde_json::from_slice(resp.body()).unwrap();
+    assert_eq!(resumed["status"], "resumed");
+
+    let mut tunnel = connect_through(port, "example.com:443").await;
+    assert_eq!(round_trip(&mut tunnel, b"hello").await, b"hello");
+    assert_eq!(upstream.requests().await.len(), 2);
+
+    shutdown_bindings(&bindings).await;
+}
+
+#[tokio::test]
+async fn test_header_routes_pick_upstream() {
+    let primary = MockUpstream::start().await;
+    let canary = MockUpstream::start().await;
+    let bindings = new_bindings();
+    let routes = api_routes(bindings.clone());
+    let port = create_binding(
+        &routes,
+        serde_json::json!({
+            "port": 0,
+            "upstream": primary.url(),
+            "header_routes": {"header": "X-Route", "upstreams": {"canary": canary.url()}}
+        }),
+    )
+    .await;
+
+    let response = request_through(
+        port,
+        "GET http://example.com/a HTTP/1.1\r\nHost: example.com\r\nX-Route: canary\r\nConnection: close\r\n\r\n",
+    )
+    .await;
+    assert!(response.ends_with("GET http://example.com/a HTTP/1.1"));
+
+    // Unmapped values and requests without the header go to the primary upstream
+    request_through(
+        port,
+        "GET http://example.com/b HTTP/1.1\r\nHost: example.com\r\nX-Route: stable\r\nConnection: close\r\n\r\n",
+    )
+    .await;
+    request_through(
+        port,
+        "GET http://example.com/c HTTP/1.1\r\nHost: example.com\r\nConnection: close\r\n\r\n",
+    )
+    .await;
+
+    // CONNECT requests are never routed by header
+    let mut client = TcpStream::connect(("127.0.0.1", port)).await.unwrap();
+    client
+        .write_all(
+            b"CONNECT example.com:443 HTTP/1.1\r\nHost: example.com:443\r\nX-Route: canary\r\n\r\n",
+        )
+        .await
+        .unwrap();
+    let mut response = [0u8; 64];
+    let n = client.read(&mut response).await.unwrap();
+    assert!(response[..n].starts_with(b"HTTP/1.1 200"));
+
+    let canary_requests = canary.requests().await;
+    assert_eq!(canary_requests.len(), 1);
+    assert!(canary_requests[0].contains("X-Route: canary\r\n"));
+    let primary_requests = primary.requests().await;
+    assert_eq!(primary_requests.len(), 3);
+    assert!(primary_requests[2].starts_with("CONNECT example.com:443"));
+
+    shutdown_bindings(&bindings).await;
+}
+
+#[tokio::test]
+async fn test_debug_capture_records_both_directions() {
+    let upstream = MockUpstream::start().await;
+    let bindings = new_bindings();
+    let routes = api_routes(bindings.clone());
+    let port = create_binding(
+        &routes,
+        serde_json::json!({"port": 0, "upstream": upstream.url(), "debug_capture": true}),
+    )
+    .await;
+
+    let mut tunnel = connect_through(port, "example.com:443").await;
+    assert_eq!(round_trip(&mut tunnel, b"hello").await, b"hello");
+
+    let resp = warp::test::request()
+        .method("GET")
+        .path(&format!("/proxy/{}/capture", port))
+        .reply(&routes)
+        .await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let body: serde_json::Value = serde_json::from_slice(resp.body()).unwrap();
+    assert_eq!(body["capture_bytes"], 4096);
+    let connection = &body["connections"][0];
+    assert_eq!(
+        connection["from_client"],
+        "CONNECT example.com:443 HTTP/1.1\\r\\nHost: example.com:443\\r\\n\\r\\nhello"
+    );
+    assert_eq!(
+        connection["from_upstream"],
+        "HTTP/1.1 200 Connection Established\\r\\n\\r\\nhello"
+    );
+    assert_eq!(connection["truncated"], false);
+
+    // Capturing is off by default
+    let other = create_binding(
+        &routes,
+        serde_json::json!({"port": 0, "upstream": upstream.url()}),
+    )
+    .await;
+    let resp = warp::test::request()
+        .method("GET")
+        .path(&format!("/proxy/{}/capture", other))
+        .reply(&routes)
+        .await;
+    assert_eq!(resp.status(), StatusCode::CONFLICT);
+
+    shutdown_bindings(&bindings).await;
+}
+
+#[tokio::test]
+async fn test_run_fails_when_api_address_is_taken() {
+    use clap::Parser;
+
+    let taken = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let bind = taken.local_addr().unwrap().to_string();
+    let config = metaproxy::config::Config::parse_from(["metaproxy", "--bind", &bind]);
+
+    let result = tokio::time::timeout(IO_TIMEOUT, metaproxy::run(config))
+        .await
+        .expect("run should fail instead of serving");
+    let error = result.unwrap_err().to_string();
+    assert!(error.contains("Failed to bind the API"), "{}", error);
+    assert!(error.contains(&bind), "{}", error);
+}
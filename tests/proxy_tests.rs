@@ -1,9 +1,16 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
 use tokio::sync::oneshot;
 use tokio::sync::Mutex;
 
-use metaproxy::proxy::{BindingMap, ProxyBinding};
+use metaproxy::events::BindingEventKind;
+use metaproxy::proxy::{
+    drain_bindings, remove_idle_bindings, BindingMap, BindingSpec, BindingState, ConnectionLimit,
+    ProxyBinding, ProxyContext,
+};
 
 #[tokio::test]
 async fn test_proxy_binding_creation() {
@@ -14,10 +21,16 @@ async fn test_proxy_binding_creation() {
     let (shutdown_tx, _) = oneshot::channel();
 
     // Create a proxy binding
-    let upstream = Arc::new(Mutex::new("http://127.0.0.1:8080".to_string()));
+    let spec = BindingSpec {
+        upstream: "http://127.0.0.1:8080".to_string(),
+        ..Default::default()
+    };
     let binding = ProxyBinding {
         port: 9000,
-        upstream: upstream.clone(),
+        state: Arc::new(BindingState::new(&spec)),
+        connect_bind: None,
+        tags: Arc::new(Mutex::new(BTreeMap::new())),
+        capture_bytes: None,
         shutdown_tx,
     };
 
@@ -34,25 +47,401 @@ async fn test_proxy_binding_creation() {
 
         // Check the upstream value
         let binding = bindings_lock.get(&9000).unwrap();
-        let upstream_value = binding.upstream.lock().await;
-        assert_eq!(*upstream_value, "http://127.0.0.1:8080");
+        assert_eq!(&*binding.state.upstream.load(), "http://127.0.0.1:8080");
     }
 
     // Update the upstream
     {
         let bindings_lock = bindings.lock().await;
         let binding = bindings_lock.get(&9000).unwrap();
-        let mut upstream_value = binding.upstream.lock().await;
-        *upstream_value = "http://127.0.0.1:9090".to_string();
+        binding.state.upstream.store("http://127.0.0.1:9090");
     }
 
     // Verify the update
     {
         let bindings_lock = bindings.lock().await;
         let binding = bindings_lock.get(&9000).unwrap();
-        let upstream_value = binding.upstream.lock().await;
-        assert_eq!(*upstream_value, "http://127.0.0.1:9090");
+        assert_eq!(&*binding.state.upstream.load(), "http://127.0.0.1:9090");
+    }
+}
+
+#[tokio::test]
+async fn test_drain_bindings_waits_for_connections() {
+    let bindings: BindingMap = Arc::new(Mutex::new(HashMap::new()));
+    let (shutdown_tx, shutdown_rx) = oneshot::channel();
+    let state = Arc::new(BindingState::new(&BindingSpec::default()));
+    let connections = state.connections.clone();
+
+    // Simulate an in-flight connection that finishes shortly after shutdown starts
+    connections.spawn(tokio::time::sleep(Duration::from_millis(50)));
+
+    bindings.lock().await.insert(
+        9000,
+        ProxyBinding {
+            port: 9000,
+            state,
+            connect_bind: None,
+            tags: Arc::new(Mutex::new(BTreeMap::new())),
+            capture_bytes: None,
+            shutdown_tx,
+        },
+    );
+
+    let remaining = drain_bindings(&bindings, Some(Duration::from_secs(5))).await;
+    assert_eq!(remaining, 0);
+    assert!(connections.is_empty());
+    assert!(bindings.lock().await.is_empty());
+    assert!(shutdown_rx.await.is_ok());
+}
+
+#[tokio::test]
+async fn test_drain_bindings_times_out() {
+    let bindings: BindingMap = Arc::new(Mutex::new(HashMap::new()));
+    let (shutdown_tx, _shutdown_rx) = oneshot::channel();
+    let state = Arc::new(BindingState::new(&BindingSpec::default()));
+    let connections = state.connections.clone();
+    connections.spawn(tokio::time::sleep(Duration::from_secs(60)));
+
+    bindings.lock().await.insert(
+        9000,
+        ProxyBinding {
+            port: 9000,
+            state,
+            connect_bind: None,
+            tags: Arc::new(Mutex::new(BTreeMap::new())),
+            capture_bytes: None,
+            shutdown_tx,
+        },
+    );
+
+    let remaining = drain_bindings(&bindings, Some(Duration::from_millis(50))).await;
+    assert_eq!(remaining, 1);
+}
+
+#[tokio::test]
+async fn test_reset_connections_keeps_listener() {
+    // An upstream proxy that accepts CONNECT requests but never answers them
+    let upstream = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let upstream_addr = upstream.local_addr().unwrap();
+    tokio::spawn(async move {
+        let mut held = Vec::new();
+        while let Ok((stream, _)) = upstream.accept().await {
+            held.push(stream);
+        }
+    });
+
+    let spec = BindingSpec {
+        port: 0,
+        upstream: format!("http://{}", upstream_addr),
+        ..Default::default()
+    };
+    let binding = ProxyBinding::bind(&spec, &Arc::new(ProxyContext::default()))
+        .await
+        .unwrap();
+    let proxy_addr = format!("127.0.0.1:{}", binding.port);
+
+    let connect = b"CONNECT example.com:443 HTTP/1.1\r\nHost: example.com:443\r\n\r\n";
+    let mut client = TcpStream::connect(&proxy_addr).await.unwrap();
+    client.write_all(connect).await.unwrap();
+    while binding.state.connections.len() != 1 {
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+
+    assert_eq!(binding.reset_connections().await, 1);
+
+    // The reset connection is closed by the proxy
+    let mut buf = [0u8; 64];
+    let read = tokio::time::timeout(Duration::from_secs(5), client.read(&mut buf))
+        .await
+        .unwrap();
+    assert!(matches!(read, Ok(0) | Err(_)));
+
+    // New connections are still accepted and are not affected by the reset
+    let mut client = TcpStream::connect(&proxy_addr).await.unwrap();
+    client.write_all(connect).await.unwrap();
+    while binding.state.connections.len() != 1 {
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+
+    let _ = binding.shutdown_tx.send(());
+}
+
+/// Start an upstream proxy that accepts every CONNECT and then echoes each
+/// read back prefixed with `tag`, so clients can tell upstreams apart
+async fn tagged_upstream(tag: &'static [u8]) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        while let Ok((mut stream, _)) = listener.accept().await {
+            tokio::spawn(async move {
+                let mut head = Vec::new();
+                let mut buf = [0u8; 1024];
+                while !head.ends_with(b"\r\n\r\n") {
+                    match stream.read(&mut buf).await {
+                        Ok(0) | Err(_) => return,
+                        Ok(n) => head.extend_from_slice(&buf[..n]),
+                    }
+                }
+                if stream
+                    .write_all(b"HTTP/1.1 200 Connection Established\r\n\r\n")
+                    .await
+                    .is_err()
+                {
+                    return;
+                }
+                while let Ok(n) = stream.read(&mut buf).await {
+                    if n == 0 {
+                        return;
+                    }
+                    let mut reply = tag.to_vec();
+                    reply.extend_from_slice(&buf[..n]);
+                    if stream.write_all(&reply).await.is_err() {
+                        return;
+                    }
+                }
+            });
+        }
+    });
+    format!("http://{}", addr)
+}
+
+/// Open a CONNECT tunnel through a proxy
+async fn open_tunnel(proxy_addr: &str) -> TcpStream {
+    let mut client = TcpStream::connect(proxy_addr).await.unwrap();
+    client
+        .write_all(b"CONNECT example.com:443 HTTP/1.1\r\nHost: example.com:443\r\n\r\n")
+        .await
+        .unwrap();
+    let mut head = Vec::new();
+    let mut byte = [0u8; 1];
+    while !head.ends_with(b"\r\n\r\n") {
+        client.read_exact(&mut byte).await.unwrap();
+        head.push(byte[0]);
     }
+    assert!(head.starts_with(b"HTTP/1.1 200"));
+    client
+}
+
+/// Send `ping` through a tunnel and return the upstream's reply
+async fn ping(client: &mut TcpStream) -> Vec<u8> {
+    client.write_all(b"ping").await.unwrap();
+    let mut reply = [0u8; 6];
+    tokio::time::timeout(Duration::from_secs(5), client.read_exact(&mut reply))
+        .await
+        .unwrap()
+        .unwrap();
+    reply.to_vec()
+}
+
+#[tokio::test]
+async fn test_upstream_update_applies_to_new_connections_only() {
+    let old_upstream = tagged_upstream(b"A:").await;
+    let new_upstream = tagged_upstream(b"B:").await;
+
+    let spec = BindingSpec {
+        port: 0,
+        upstream: old_upstream,
+        ..Default::default()
+    };
+    let binding = ProxyBinding::bind(&spec, &Arc::new(ProxyContext::default()))
+        .await
+        .unwrap();
+    let proxy_addr = format!("127.0.0.1:{}", binding.port);
+
+    let mut in_flight = open_tunnel(&proxy_addr).await;
+    assert_eq!(ping(&mut in_flight).await, b"A:ping");
+
+    binding.state.upstream.store(new_upstream.as_str());
+
+    // New connections use the new upstream
+    let mut client = open_tunnel(&proxy_addr).await;
+    assert_eq!(ping(&mut client).await, b"B:ping");
+
+    // The connection accepted before the update keeps its upstream
+    assert_eq!(ping(&mut in_flight).await, b"A:ping");
+    assert_eq!(binding.state.connections.len(), 2);
+
+    let _ = binding.shutdown_tx.send(());
+}
+
+#[tokio::test]
+async fn test_connections_do_not_wait_on_binding_map() {
+    let upstream = tagged_upstream(b"A:").await;
+    let context = Arc::new(ProxyContext::default());
+    let spec = BindingSpec {
+        port: 0,
+        upstream,
+        ..Default::default()
+    };
+    let binding = ProxyBinding::bind(&spec, &context).await.unwrap();
+    let proxy_addr = format!("127.0.0.1:{}", binding.port);
+
+    // Connections are accepted and relayed while the control plane holds the map
+    let bindings_lock = context.bindings.lock().await;
+    let mut client = tokio::time::timeout(Duration::from_secs(5), open_tunnel(&proxy_addr))
+        .await
+        .unwrap();
+    assert_eq!(ping(&mut client).await, b"A:ping");
+    drop(bindings_lock);
+
+    let _ = binding.shutdown_tx.send(());
+}
+
+#[tokio::test]
+async fn test_remove_idle_bindings() {
+    // An upstream proxy that accepts CONNECT requests but never answers them
+    let upstream = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let upstream_addr = upstream.local_addr().unwrap();
+    tokio::spawn(async move {
+        let mut held = Vec::new();
+        while let Ok((stream, _)) = upstream.accept().await {
+            held.push(stream);
+        }
+    });
+
+    let spec = BindingSpec {
+        port: 0,
+        upstream: format!("http://{}", upstream_addr),
+        ..Default::default()
+    };
+    let bindings: BindingMap = Arc::new(Mutex::new(HashMap::new()));
+    let context = Arc::new(ProxyContext::new(bindings.clone()));
+    let mut events = context.events.subscribe();
+    let mut ports = Vec::new();
+    for _ in 0..2 {
+        let binding = ProxyBinding::bind(&spec, &context).await.unwrap();
+        ports.push(binding.port);
+        bindings.lock().await.insert(binding.port, binding);
+    }
+    let (idle_port, busy_port) = (ports[0], ports[1]);
+
+    // Keep a connection open on the second binding
+    let mut client = TcpStream::connect(format!("127.0.0.1:{}", busy_port))
+        .await
+        .unwrap();
+    client
+        .write_all(b"CONNECT example.com:443 HTTP/1.1\r\nHost: example.com:443\r\n\r\n")
+        .await
+        .unwrap();
+    while bindings.lock().await[&busy_port].state.connections.len() != 1 {
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+
+    // Neither binding has been idle for longer than a generous TTL
+    assert!(remove_idle_bindings(&context, Duration::from_secs(60))
+        .await
+        .is_empty());
+
+    // Only the binding without active connections is deleted
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    let removed = remove_idle_bindings(&context, Duration::from_millis(50)).await;
+    assert_eq!(removed, vec![idle_port]);
+    let event = events.try_recv().unwrap();
+    assert_eq!(event.kind, BindingEventKind::Deleted);
+    assert_eq!(event.port, idle_port);
+    assert!(events.try_recv().is_err());
+    assert!(!bindings.lock().await.contains_key(&idle_port));
+    assert!(bindings.lock().await.contains_key(&busy_port));
+
+    // The deleted binding's listener stops accepting connections
+    let mut closed = false;
+    for _ in 0..50 {
+        if TcpStream::connect(format!("127.0.0.1:{}", idle_port))
+            .await
+            .is_err()
+        {
+            closed = true;
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    assert!(closed);
+
+    drain_bindings(&bindings, Some(Duration::from_millis(50))).await;
+}
+
+#[tokio::test]
+async fn test_connection_limit_holds_excess_connections() {
+    // An upstream proxy that accepts CONNECT requests but never answers them
+    let upstream = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let upstream_addr = upstream.local_addr().unwrap();
+    tokio::spawn(async move {
+        let mut held = Vec::new();
+        while let Ok((stream, _)) = upstream.accept().await {
+            held.push(stream);
+        }
+    });
+
+    let limit = ConnectionLimit::new(1);
+    let spec = BindingSpec {
+        port: 0,
+        upstream: format!("http://{}", upstream_addr),
+        ..Default::default()
+    };
+    let binding = ProxyBinding::bind(
+        &spec,
+        &Arc::new(ProxyContext {
+            connection_limit: Some(limit.clone()),
+            ..ProxyContext::default()
+        }),
+    )
+    .await
+    .unwrap();
+    let proxy_addr = format!("127.0.0.1:{}", binding.port);
+
+    let connect = b"CONNECT example.com:443 HTTP/1.1\r\nHost: example.com:443\r\n\r\n";
+    let mut first = TcpStream::connect(&proxy_addr).await.unwrap();
+    first.write_all(connect).await.unwrap();
+    while binding.state.connections.len() != 1 {
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    assert_eq!(limit.active(), 1);
+
+    // The second connection waits in the backlog while the first holds the only permit
+    let mut second = TcpStream::connect(&proxy_addr).await.unwrap();
+    second.write_all(connect).await.unwrap();
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert_eq!(binding.state.connections.len(), 1);
+
+    // Ending the first connection releases its permit to the second
+    assert_eq!(binding.reset_connections().await, 1);
+    let mut buf = [0u8; 64];
+    let read = tokio::time::timeout(Duration::from_secs(5), first.read(&mut buf))
+        .await
+        .unwrap();
+    assert!(matches!(read, Ok(0) | Err(_)));
+
+    tokio::time::timeout(Duration::from_secs(5), async {
+        while binding.state.connections.len() != 1 || limit.active() != 1 {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .unwrap();
+
+    let _ = binding.shutdown_tx.send(());
+    drop(second);
+}
+
+#[tokio::test]
+async fn test_connection_limit_drain_waits_for_permits() {
+    let limit = ConnectionLimit::new(3);
+    assert_eq!(limit.drain(Some(Duration::from_millis(10))).await, 0);
+
+    // A permit still held at the deadline is reported as active
+    let first = limit.acquire().await;
+    let second = limit.acquire().await;
+    assert_eq!(limit.drain(Some(Duration::from_millis(50))).await, 2);
+    assert_eq!(limit.active(), 2);
+
+    // Without a timeout, the drain ends once the last permit is returned
+    drop(first);
+    tokio::spawn(async move {
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        drop(second);
+    });
+    assert_eq!(limit.drain(None).await, 0);
+    assert_eq!(limit.active(), 0);
 }
 
 // Note: Testing the actual proxy functionality would require setting up mock TCP servers